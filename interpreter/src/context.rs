@@ -1,5 +1,5 @@
 #[cfg(feature = "advanced")]
-use super::processing::advanced::{DefaultAnalyzer, MusicAnalyzer};
+use super::processing::advanced::{DefaultAnalyzer, MusicAnalyzer, SimilarityPresets};
 use super::processing::database::DatabaseQuerier;
 #[cfg(feature = "fakesql")]
 use super::processing::database::SQLiteTranspileExecutor;
@@ -17,6 +17,8 @@ pub struct Context {
     pub filesystem: Box<dyn FilesystemQuerier>,
     #[cfg(feature = "advanced")]
     pub analysis: Box<dyn MusicAnalyzer>,
+    #[cfg(feature = "advanced")]
+    pub similarity_presets: SimilarityPresets,
     #[cfg(feature = "mpd")]
     pub mpd_database: Box<dyn MpdQuerier>,
 }
@@ -34,12 +36,29 @@ impl Default for Context {
             filesystem: Box::new(FilesystemExecutor::default()),
             #[cfg(feature = "advanced")]
             analysis: Box::new(DefaultAnalyzer::default()),
+            #[cfg(feature = "advanced")]
+            similarity_presets: SimilarityPresets::default(),
             #[cfg(feature = "mpd")]
             mpd_database: Box::new(MpdExecutor::default()),
         }
     }
 }
 
+impl Context {
+    /// Replace the variable storage, e.g. with a `FileOpStorage` to support persisted variables.
+    pub fn with_variables<V: VariableStorer + 'static>(mut self, variables: V) -> Self {
+        self.variables = Box::new(variables);
+        self
+    }
+
+    /// Replace the music analyzer used for similarity, e.g. with a `MetadataAnalyzer` to compare tags instead of audio.
+    #[cfg(feature = "advanced")]
+    pub fn with_analyzer<A: MusicAnalyzer + 'static>(mut self, analyzer: A) -> Self {
+        self.analysis = Box::new(analyzer);
+        self
+    }
+}
+
 impl Display for Context {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         write!(f, "Context{{...}}")?;
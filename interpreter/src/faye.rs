@@ -111,6 +111,12 @@ where
         }
     }
 
+    /// Replace the context used by the interpreter's statements.
+    pub fn set_context(&mut self, ctx: Context) {
+        self.current_stmt.escape();
+        self.current_stmt.enter(ctx);
+    }
+
     // build a new statement
     #[inline]
     fn new_statement(&mut self) -> Option<Result<Box<dyn Op>, InterpreterError>> {
@@ -32,6 +32,7 @@ pub(crate) fn standard_vocab(vocabulary: &mut LanguageDictionary) {
                 .push(crate::lang::vocabulary::item_ops::VariableAssignItemOpFactory)
                 .push(crate::lang::vocabulary::item_ops::VariableDeclareItemOpFactory)
                 .push(crate::lang::vocabulary::item_ops::FileItemOpFactory)
+                .push(crate::lang::vocabulary::item_ops::FeaturesItemOpFactory)
                 .push(crate::lang::vocabulary::item_ops::InterpolateStringItemOpFactory)
                 .push(crate::lang::vocabulary::item_ops::BranchItemOpFactory)
                 .push(crate::lang::vocabulary::item_ops::IterItemOpFactory)
@@ -58,6 +59,8 @@ pub(crate) fn standard_vocab(vocabulary: &mut LanguageDictionary) {
         .add(crate::lang::vocabulary::simple_sql_function_factory())
         .add(crate::lang::vocabulary::repeat_function_factory())
         .add(crate::lang::vocabulary::AssignStatementFactory)
+        .add(crate::lang::vocabulary::PersistStatementFactory)
+        .add(crate::lang::vocabulary::PresetStatementFactory)
         .add(crate::lang::vocabulary::sql_init_function_factory())
         .add(crate::lang::vocabulary::files_function_factory())
         .add(crate::lang::vocabulary::playlist_function_factory())
@@ -66,5 +69,7 @@ pub(crate) fn standard_vocab(vocabulary: &mut LanguageDictionary) {
         .add(crate::lang::vocabulary::reset_function_factory())
         .add(crate::lang::vocabulary::union_function_factory())
         .add(crate::lang::vocabulary::intersection_function_factory())
+        .add(crate::lang::vocabulary::cluster_function_factory())
+        .add(crate::lang::vocabulary::path_function_factory())
         .add(crate::lang::vocabulary::VariableRetrieveStatementFactory);
 }
@@ -51,6 +51,77 @@ impl Item {
     }
 }
 
+/// Write items as a JSON array of objects, with one object field per item field.
+#[cfg(feature = "export")]
+pub fn items_to_json<I: IntoIterator<Item = Item>, W: std::io::Write>(
+    items: I,
+    writer: W,
+) -> std::io::Result<()> {
+    let values: Vec<serde_json::Value> = items.into_iter().map(item_to_json).collect();
+    serde_json::to_writer_pretty(writer, &values).map_err(std::io::Error::from)
+}
+
+/// An item as a JSON object, with one object field per item field.
+#[cfg(feature = "export")]
+pub fn item_to_json(item: Item) -> serde_json::Value {
+    serde_json::Value::Object(
+        item.fields
+            .into_iter()
+            .map(|(key, val)| (key, primitive_to_json(val)))
+            .collect(),
+    )
+}
+
+/// An item from a JSON object made by `item_to_json`.
+/// Whole numbers are unsigned when possible, since JSON doesn't keep track of that.
+#[cfg(feature = "export")]
+pub fn item_from_json(value: &serde_json::Value) -> std::io::Result<Item> {
+    let obj = value.as_object().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "JSON item is not an object",
+        )
+    })?;
+    let mut item = Item::new();
+    for (key, val) in obj.iter() {
+        let primitive = primitive_from_json(val).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid JSON item field `{}`", key),
+            )
+        })?;
+        item.set_field(key, primitive);
+    }
+    Ok(item)
+}
+
+#[cfg(feature = "export")]
+fn primitive_to_json(primitive: TypePrimitive) -> serde_json::Value {
+    match primitive {
+        TypePrimitive::String(s) => s.into(),
+        TypePrimitive::Int(i) => i.into(),
+        TypePrimitive::UInt(u) => u.into(),
+        TypePrimitive::Float(f) => f.into(),
+        TypePrimitive::Bool(b) => b.into(),
+        TypePrimitive::Empty => serde_json::Value::Null,
+    }
+}
+
+#[cfg(feature = "export")]
+fn primitive_from_json(value: &serde_json::Value) -> Option<TypePrimitive> {
+    match value {
+        serde_json::Value::String(s) => Some(TypePrimitive::String(s.to_owned())),
+        serde_json::Value::Number(n) => n
+            .as_u64()
+            .map(TypePrimitive::UInt)
+            .or_else(|| n.as_i64().map(TypePrimitive::Int))
+            .or_else(|| n.as_f64().map(TypePrimitive::Float)),
+        serde_json::Value::Bool(b) => Some(TypePrimitive::Bool(*b)),
+        serde_json::Value::Null => Some(TypePrimitive::Empty),
+        _ => None,
+    }
+}
+
 impl Display for Item {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         write!(f, "Item[({} fields)]", self.fields.len())
@@ -14,10 +14,123 @@ pub trait DatabaseObj: Sized {
     fn id(&self) -> u64;
 }
 
+/// Version of the database structure created by this version of Muss.
+#[cfg(feature = "sql")]
+pub const SCHEMA_VERSION: u64 = 3;
+
+/// Database structure changes, where the migration at index i upgrades the database from version i to i+1.
+#[cfg(feature = "sql")]
+const MIGRATIONS: [&str; SCHEMA_VERSION as usize] = [
+    // 1: original tables
+    "CREATE TABLE IF NOT EXISTS songs (
+        song_id INTEGER NOT NULL PRIMARY KEY,
+        title TEXT NOT NULL,
+        artist INTEGER NOT NULL,
+        album INTEGER,
+        filename TEXT,
+        metadata INTEGER NOT NULL,
+        genre INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS artists (
+        artist_id INTEGER NOT NULL PRIMARY KEY,
+        name TEXT NOT NULL,
+        genre INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS albums (
+        album_id INTEGER NOT NULL PRIMARY KEY,
+        title TEXT,
+        metadata INTEGER NOT NULL,
+        artist INTEGER NOT NULL,
+        genre INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS metadata (
+        meta_id INTEGER NOT NULL PRIMARY KEY,
+        plays INTEGER NOT NULL DEFAULT 0,
+        track INTEGER NOT NULL DEFAULT 1,
+        disc INTEGER NOT NULL DEFAULT 1,
+        duration INTEGER,
+        date INTEGER
+    );
+    CREATE TABLE IF NOT EXISTS genres (
+        genre_id INTEGER NOT NULL PRIMARY KEY,
+        title TEXT
+    );",
+    // 2: file index for incremental updates
+    "CREATE TABLE IF NOT EXISTS files (
+        filename TEXT NOT NULL PRIMARY KEY,
+        modified INTEGER,
+        song INTEGER
+    );",
+    // 3: album artist, composer, bitrate and added-date
+    "ALTER TABLE songs ADD COLUMN album_artist INTEGER;
+    ALTER TABLE metadata ADD COLUMN composer TEXT;
+    ALTER TABLE metadata ADD COLUMN bitrate INTEGER;
+    ALTER TABLE metadata ADD COLUMN added INTEGER NOT NULL DEFAULT 0;
+    -- existing songs must be re-read to fill in the new columns
+    INSERT OR IGNORE INTO files (filename, modified, song)
+        SELECT CASE WHEN filename LIKE 'file://%' THEN substr(filename, 8) ELSE filename END, 0, song_id
+        FROM songs WHERE filename IS NOT NULL;
+    UPDATE files SET modified = 0;",
+];
+
+/// Version of the database structure, or 0 for an empty database.
+#[cfg(feature = "sql")]
+pub fn schema_version(conn: &rusqlite::Connection) -> rusqlite::Result<u64> {
+    conn.execute_batch("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL);")?;
+    let version: Option<u64> =
+        conn.query_row("SELECT MAX(version) FROM schema_version", [], |row| row.get(0))?;
+    if let Some(version) = version {
+        return Ok(version);
+    }
+    // databases created before the structure was versioned
+    let table_exists = |name: &str| {
+        conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?",
+            [name],
+            |row| row.get::<_, u64>(0),
+        )
+        .map(|count| count != 0)
+    };
+    if table_exists("files")? {
+        Ok(2)
+    } else if table_exists("songs")? {
+        Ok(1)
+    } else {
+        Ok(0)
+    }
+}
+
+/// Upgrade the database structure to the latest version, returning the version it was upgraded from.
+#[cfg(feature = "sql")]
+pub fn migrate_db(conn: &mut rusqlite::Connection) -> rusqlite::Result<u64> {
+    let version = schema_version(conn)?;
+    if version > SCHEMA_VERSION {
+        return Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
+            Some(format!(
+                "Database version {} is newer than supported version {}",
+                version, SCHEMA_VERSION
+            )),
+        ));
+    }
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        // each migration is all-or-nothing
+        let transaction = conn.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.execute(
+            "INSERT INTO schema_version (version) VALUES (?)",
+            [i as u64 + 1],
+        )?;
+        transaction.commit()?;
+    }
+    Ok(version)
+}
+
 #[cfg(feature = "sql")]
 pub fn generate_default_db() -> rusqlite::Result<rusqlite::Connection> {
     generate_db(
-        super::utility::music_folder(),
+        &super::utility::music_folders(),
+        &crate::processing::general::ExcludeRules::new(),
         DEFAULT_SQLITE_FILEPATH,
         true,
     )
@@ -25,62 +138,23 @@ pub fn generate_default_db() -> rusqlite::Result<rusqlite::Connection> {
 
 #[cfg(feature = "sql")]
 pub fn generate_db<P1: AsRef<Path>, P2: AsRef<Path>>(
-    music_path: P1,
+    music_paths: &[P1],
+    excludes: &crate::processing::general::ExcludeRules,
     sqlite_path: P2,
     generate: bool,
 ) -> rusqlite::Result<rusqlite::Connection> {
-    #[allow(unused_variables)]
-    let music_path = music_path.as_ref();
+    #[cfg(not(feature = "music_library"))]
+    let _ = (music_paths, excludes);
     let sqlite_path = sqlite_path.as_ref();
     let db_exists = std::path::Path::new(sqlite_path).exists();
-    #[cfg(not(feature = "music_library"))]
-    let conn = rusqlite::Connection::open(sqlite_path)?;
-    #[cfg(feature = "music_library")]
     let mut conn = rusqlite::Connection::open(sqlite_path)?;
+    // create or upgrade db tables
+    migrate_db(&mut conn)?;
     // skip db building if SQLite file already exists
-    // TODO do a more exhaustive db check to make sure it's actually the correct file and database structure
     #[cfg(not(feature = "music_library"))]
     if db_exists && !generate {
         return Ok(conn);
     }
-    // build db tables
-    conn.execute_batch(
-        "BEGIN;
-        CREATE TABLE IF NOT EXISTS songs (
-            song_id INTEGER NOT NULL PRIMARY KEY,
-            title TEXT NOT NULL,
-            artist INTEGER NOT NULL,
-            album INTEGER,
-            filename TEXT,
-            metadata INTEGER NOT NULL,
-            genre INTEGER NOT NULL
-        );
-        CREATE TABLE IF NOT EXISTS artists (
-            artist_id INTEGER NOT NULL PRIMARY KEY,
-            name TEXT NOT NULL,
-            genre INTEGER NOT NULL
-        );
-        CREATE TABLE IF NOT EXISTS albums (
-            album_id INTEGER NOT NULL PRIMARY KEY,
-            title TEXT,
-            metadata INTEGER NOT NULL,
-            artist INTEGER NOT NULL,
-            genre INTEGER NOT NULL
-        );
-        CREATE TABLE IF NOT EXISTS metadata (
-            meta_id INTEGER NOT NULL PRIMARY KEY,
-            plays INTEGER NOT NULL DEFAULT 0,
-            track INTEGER NOT NULL DEFAULT 1,
-            disc INTEGER NOT NULL DEFAULT 1,
-            duration INTEGER,
-            date INTEGER
-        );
-        CREATE TABLE IF NOT EXISTS genres (
-            genre_id INTEGER NOT NULL PRIMARY KEY,
-            title TEXT
-        );
-        COMMIT;",
-    )?;
     // generate data and store in db
     #[cfg(feature = "music_library")]
     if generate {
@@ -89,83 +163,191 @@ pub fn generate_db<P1: AsRef<Path>, P2: AsRef<Path>>(
             crate::music::build_library_from_sqlite(&conn, &mut lib)?;
         }
         lib.clear_modified();
-        match crate::music::build_library_from_files(&music_path, &mut lib) {
-            Ok(_) => {
-                if lib.is_modified() {
-                    let transaction = conn.transaction()?;
-                    {
-                        let mut song_insert = transaction.prepare(
-                            "INSERT OR REPLACE INTO songs (
-                        song_id,
-                        title,
-                        artist,
-                        album,
-                        filename,
-                        metadata,
-                        genre
-                    ) VALUES (?, ?, ?, ?, ?, ?, ?)",
-                        )?;
-                        for song in lib.all_songs() {
-                            song_insert.execute(song.to_params().as_slice())?;
-                        }
-
-                        let mut metadata_insert = transaction.prepare(
-                            "INSERT OR REPLACE INTO metadata (
-                        meta_id,
-                        plays,
-                        track,
-                        disc,
-                        duration,
-                        date
-                    ) VALUES (?, ?, ?, ?, ?, ?)",
-                        )?;
-                        for meta in lib.all_metadata() {
-                            metadata_insert.execute(meta.to_params().as_slice())?;
-                        }
-
-                        let mut artist_insert = transaction.prepare(
-                            "INSERT OR REPLACE INTO artists (
-                        artist_id,
-                        name,
-                        genre
-                    ) VALUES (?, ?, ?)",
-                        )?;
-                        for artist in lib.all_artists() {
-                            artist_insert.execute(artist.to_params().as_slice())?;
-                        }
-
-                        let mut album_insert = transaction.prepare(
-                            "INSERT OR REPLACE INTO albums (
-                        album_id,
-                        title,
-                        metadata,
-                        artist,
-                        genre
-                    ) VALUES (?, ?, ?, ?, ?)",
-                        )?;
-                        for album in lib.all_albums() {
-                            album_insert.execute(album.to_params().as_slice())?;
-                        }
-
-                        let mut genre_insert = transaction.prepare(
-                            "INSERT OR REPLACE INTO genres (
-                        genre_id,
-                        title
-                    ) VALUES (?, ?)",
-                        )?;
-                        for genre in lib.all_genres() {
-                            genre_insert.execute(genre.to_params().as_slice())?;
-                        }
-                    }
-                    transaction.commit()?;
-                }
+        lib.set_excludes(excludes.clone());
+        for music_path in music_paths {
+            let music_path = music_path.as_ref();
+            if let Err(e) = crate::music::build_library_from_files(music_path, &mut lib) {
+                println!("Unable to load music from {}: {}", music_path.display(), e);
             }
-            Err(e) => println!("Unable to load music from {}: {}", music_path.display(), e),
+        }
+        if lib.is_modified() {
+            write_library_changes(&mut conn, &lib)?;
         }
     }
     Ok(conn)
 }
 
+/// Store everything that's changed in the library since it was last cleared.
+#[cfg(all(feature = "sql", feature = "music_library"))]
+pub fn write_library_changes(
+    conn: &mut rusqlite::Connection,
+    lib: &crate::music::Library,
+) -> rusqlite::Result<()> {
+    let transaction = conn.transaction()?;
+    {
+        let mut song_delete = transaction.prepare("DELETE FROM songs WHERE song_id = ?")?;
+        for song_id in lib.removed_songs() {
+            song_delete.execute([song_id])?;
+        }
+
+        let mut metadata_delete = transaction.prepare("DELETE FROM metadata WHERE meta_id = ?")?;
+        for meta_id in lib.removed_metadata() {
+            metadata_delete.execute([meta_id])?;
+        }
+
+        let mut file_delete = transaction.prepare("DELETE FROM files WHERE filename = ?")?;
+        for path in lib.removed_files() {
+            file_delete.execute([path.to_string_lossy()])?;
+        }
+
+        let mut artist_delete = transaction.prepare("DELETE FROM artists WHERE artist_id = ?")?;
+        for artist_id in lib.removed_artists() {
+            artist_delete.execute([artist_id])?;
+        }
+
+        let mut album_delete = transaction.prepare("DELETE FROM albums WHERE album_id = ?")?;
+        for album_id in lib.removed_albums() {
+            album_delete.execute([album_id])?;
+        }
+
+        let mut genre_delete = transaction.prepare("DELETE FROM genres WHERE genre_id = ?")?;
+        for genre_id in lib.removed_genres() {
+            genre_delete.execute([genre_id])?;
+        }
+
+        let mut song_insert = transaction.prepare(
+            "INSERT OR REPLACE INTO songs (
+                song_id,
+                title,
+                artist,
+                album,
+                filename,
+                metadata,
+                genre,
+                album_artist
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )?;
+        for song in lib.changed_songs() {
+            song_insert.execute(song.to_params().as_slice())?;
+        }
+
+        let mut metadata_insert = transaction.prepare(
+            "INSERT OR REPLACE INTO metadata (
+                meta_id,
+                plays,
+                track,
+                disc,
+                duration,
+                date,
+                composer,
+                bitrate,
+                added
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )?;
+        for meta in lib.changed_metadata() {
+            metadata_insert.execute(meta.to_params().as_slice())?;
+        }
+
+        let mut artist_insert = transaction.prepare(
+            "INSERT OR REPLACE INTO artists (
+                artist_id,
+                name,
+                genre
+            ) VALUES (?, ?, ?)",
+        )?;
+        for artist in lib.changed_artists() {
+            artist_insert.execute(artist.to_params().as_slice())?;
+        }
+
+        let mut album_insert = transaction.prepare(
+            "INSERT OR REPLACE INTO albums (
+                album_id,
+                title,
+                metadata,
+                artist,
+                genre
+            ) VALUES (?, ?, ?, ?, ?)",
+        )?;
+        for album in lib.changed_albums() {
+            album_insert.execute(album.to_params().as_slice())?;
+        }
+
+        let mut genre_insert = transaction.prepare(
+            "INSERT OR REPLACE INTO genres (
+                genre_id,
+                title
+            ) VALUES (?, ?)",
+        )?;
+        for genre in lib.changed_genres() {
+            genre_insert.execute(genre.to_params().as_slice())?;
+        }
+
+        let mut file_insert = transaction.prepare(
+            "INSERT OR REPLACE INTO files (
+                filename,
+                modified,
+                song
+            ) VALUES (?, ?, ?)",
+        )?;
+        for (path, info) in lib.changed_files() {
+            file_insert.execute(rusqlite::params![
+                path.to_string_lossy(),
+                info.modified,
+                info.song
+            ])?;
+        }
+    }
+    transaction.commit()
+}
+
+/// Keep the database up to date with changes to the music folders, until they can no longer be watched.
+#[cfg(all(feature = "sql", feature = "music_library", feature = "watch"))]
+pub fn watch_db<P1: AsRef<Path>, P2: AsRef<Path>>(
+    music_paths: &[P1],
+    excludes: &crate::processing::general::ExcludeRules,
+    sqlite_path: P2,
+) -> std::thread::JoinHandle<()> {
+    let music_paths: Vec<std::path::PathBuf> =
+        music_paths.iter().map(|p| p.as_ref().to_path_buf()).collect();
+    let excludes = excludes.clone();
+    let sqlite_path = sqlite_path.as_ref().to_path_buf();
+    std::thread::spawn(move || {
+        let mut watcher: Option<crate::music::LibraryWatcher> = None;
+        for music_path in music_paths.iter() {
+            let result = if let Some(w) = watcher.as_mut() {
+                w.add_root(music_path)
+            } else {
+                crate::music::LibraryWatcher::new(music_path).map(|w| watcher = Some(w))
+            };
+            if let Err(e) = result {
+                println!("Unable to watch music in {}: {}", music_path.display(), e);
+            }
+        }
+        let watcher = match watcher {
+            Some(w) => w,
+            None => return,
+        };
+        let mut conn = match rusqlite::Connection::open(&sqlite_path) {
+            Ok(c) => c,
+            Err(_) => return,
+        };
+        let mut lib = crate::music::Library::new();
+        if crate::music::build_library_from_sqlite(&conn, &mut lib).is_err() {
+            return;
+        }
+        lib.clear_modified();
+        lib.set_excludes(excludes);
+        while let Some(paths) = watcher.wait_for_changes() {
+            for path in paths {
+                lib.update_file(path).unwrap_or(());
+            }
+            if lib.is_modified() && write_library_changes(&mut conn, &lib).is_ok() {
+                lib.clear_modified();
+            }
+        }
+    })
+}
+
 #[derive(Clone, Debug)]
 pub struct DbMusicItem {
     pub song_id: u64,
@@ -175,6 +357,7 @@ pub struct DbMusicItem {
     pub filename: String,
     pub metadata: u64,
     pub genre: u64,
+    pub album_artist: Option<u64>,
 }
 
 impl DatabaseObj for DbMusicItem {
@@ -188,6 +371,7 @@ impl DatabaseObj for DbMusicItem {
             filename: row.get(4)?,
             metadata: row.get(5)?,
             genre: row.get(6)?,
+            album_artist: row.get(7)?,
         })
     }
 
@@ -201,6 +385,7 @@ impl DatabaseObj for DbMusicItem {
             &self.filename,
             &self.metadata,
             &self.genre,
+            &self.album_artist,
         ]
     }
 
@@ -217,6 +402,9 @@ pub struct DbMetaItem {
     pub disc: u64,
     pub duration: u64, // seconds
     pub date: u64,     // year
+    pub composer: Option<String>,
+    pub bitrate: Option<u64>, // kbps
+    pub added: u64,           // seconds since epoch
 }
 
 impl DatabaseObj for DbMetaItem {
@@ -229,6 +417,9 @@ impl DatabaseObj for DbMetaItem {
             disc: row.get(3)?,
             duration: row.get(4)?,
             date: row.get(5)?,
+            composer: row.get(6)?,
+            bitrate: row.get(7)?,
+            added: row.get(8)?,
         })
     }
 
@@ -241,6 +432,9 @@ impl DatabaseObj for DbMetaItem {
             &self.disc,
             &self.duration,
             &self.date,
+            &self.composer,
+            &self.bitrate,
+            &self.added,
         ]
     }
 
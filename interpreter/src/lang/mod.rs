@@ -46,8 +46,13 @@ pub mod db {
     };
     #[cfg(feature = "sql")]
     pub use super::db_items::{
-        generate_db, generate_default_db, DEFAULT_SQLITE_FILEPATH
+        generate_db, generate_default_db, migrate_db, schema_version, DEFAULT_SQLITE_FILEPATH,
+        SCHEMA_VERSION,
     };
+    #[cfg(all(feature = "sql", feature = "music_library"))]
+    pub use super::db_items::write_library_changes;
+    #[cfg(all(feature = "sql", feature = "music_library", feature = "watch"))]
+    pub use super::db_items::watch_db;
 }
 
 #[cfg(test)]
@@ -58,4 +63,29 @@ mod tests {
         super::db::generate_default_db()?;
         Ok(())
     }
+
+    #[cfg(feature = "sql")]
+    #[test]
+    fn db_migrate_test() -> rusqlite::Result<()> {
+        let mut conn = rusqlite::Connection::open_in_memory()?;
+        // unversioned database from before the files table
+        conn.execute_batch(
+            "CREATE TABLE songs (song_id INTEGER NOT NULL PRIMARY KEY, title TEXT NOT NULL, artist INTEGER NOT NULL, album INTEGER, filename TEXT, metadata INTEGER NOT NULL, genre INTEGER NOT NULL);
+            CREATE TABLE metadata (meta_id INTEGER NOT NULL PRIMARY KEY, plays INTEGER NOT NULL DEFAULT 0, track INTEGER NOT NULL DEFAULT 1, disc INTEGER NOT NULL DEFAULT 1, duration INTEGER, date INTEGER);
+            INSERT INTO songs VALUES (0, 'Title', 0, 0, 'file:///music/song.flac', 0, 0);",
+        )?;
+        assert_eq!(super::db::schema_version(&conn)?, 1);
+        assert_eq!(super::db::migrate_db(&mut conn)?, 1);
+        assert_eq!(super::db::schema_version(&conn)?, super::db::SCHEMA_VERSION);
+        // existing songs are marked for re-reading
+        let modified: u64 = conn.query_row(
+            "SELECT modified FROM files WHERE filename = '/music/song.flac'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(modified, 0);
+        // nothing left to do
+        assert_eq!(super::db::migrate_db(&mut conn)?, super::db::SCHEMA_VERSION);
+        Ok(())
+    }
 }
@@ -143,8 +143,82 @@ pub fn assert_type(tokens: &mut VecDeque<Token>) -> Result<TypePrimitive, Syntax
     }
 }
 
+/// Parse a number, including decimals like `0.5` (which are tokenized as `0`, `.`, `5`)
+/// and negative numbers.
+pub fn assert_number(tokens: &mut VecDeque<Token>) -> Result<f64, SyntaxError> {
+    let negative = !tokens.is_empty() && tokens[0].is_minus();
+    if negative {
+        tokens.pop_front();
+    }
+    let whole = assert_token(
+        |t| match t {
+            Token::Name(n) => n.parse::<u64>().ok().map(|_| n),
+            _ => None,
+        },
+        Token::Name("number".into()),
+        tokens,
+    )?;
+    let mut number = whole;
+    if tokens.len() > 1 && tokens[0].is_dot() {
+        if let Token::Name(fraction) = &tokens[1] {
+            if fraction.chars().all(|c| c.is_ascii_digit()) {
+                number.push('.');
+                number.push_str(fraction);
+                tokens.pop_front();
+                tokens.pop_front();
+            }
+        }
+    }
+    let number: f64 = number.parse().map_err(|_| SyntaxError {
+        line: 0,
+        token: Token::Name("number".into()),
+        got: Some(Token::Name(number.clone())),
+    })?;
+    Ok(if negative { -number } else { number })
+}
+
+/// Parse a song path, either as `file("path")` or just `"path"`.
+pub fn assert_file_path(tokens: &mut VecDeque<Token>) -> Result<String, SyntaxError> {
+    let is_file_call =
+        tokens.len() > 1 && check_name("file", &tokens[0]) && tokens[1].is_open_bracket();
+    if is_file_call {
+        assert_name("file", tokens)?;
+        assert_token_raw(Token::OpenBracket, tokens)?;
+    }
+    let path = assert_token(
+        |t| match t {
+            Token::Literal(path) => Some(path),
+            _ => None,
+        },
+        Token::Literal("path".into()),
+        tokens,
+    )?;
+    if is_file_call {
+        assert_token_raw(Token::CloseBracket, tokens)?;
+    }
+    Ok(path)
+}
+
 pub fn music_folder() -> PathBuf {
     dirs::home_dir()
         .unwrap_or_else(|| PathBuf::from("./"))
         .join("Music")
 }
+
+/// Default music folders: the paths in `MUSS_MUSIC_PATH` (separated like `PATH`),
+/// or just `music_folder()` when it's not set.
+pub fn music_folders() -> Vec<PathBuf> {
+    match std::env::var_os("MUSS_MUSIC_PATH") {
+        Some(paths) => {
+            let folders: Vec<PathBuf> = std::env::split_paths(&paths)
+                .filter(|p| !p.as_os_str().is_empty())
+                .collect();
+            if folders.is_empty() {
+                vec![music_folder()]
+            } else {
+                folders
+            }
+        }
+        None => vec![music_folder()],
+    }
+}
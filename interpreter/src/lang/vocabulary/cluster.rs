@@ -0,0 +1,384 @@
+use std::collections::VecDeque;
+use std::fmt::{Debug, Display, Error, Formatter};
+use std::iter::Iterator;
+
+use crate::tokens::Token;
+use crate::Context;
+
+use crate::lang::utility::{assert_name, assert_token, assert_token_raw, check_name};
+use crate::lang::LanguageDictionary;
+use crate::lang::{FunctionFactory, FunctionStatementFactory, IteratorItem, Op, PseudoOp};
+use crate::lang::{RuntimeError, SyntaxError};
+#[cfg(feature = "advanced")]
+use crate::lang::{RuntimeMsg, RuntimeOp, TypePrimitive};
+
+const DEFAULT_K: usize = 8;
+#[cfg(feature = "advanced")]
+const MAX_ITERATIONS: usize = 100;
+
+#[derive(Debug)]
+pub struct ClusterStatement {
+    inner_statement: PseudoOp,
+    k: usize,
+    // state
+    clustered: Option<VecDeque<IteratorItem>>,
+}
+
+impl Display for ClusterStatement {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "cluster({}, k = {})", self.inner_statement, self.k)
+    }
+}
+
+impl std::clone::Clone for ClusterStatement {
+    fn clone(&self) -> Self {
+        Self {
+            inner_statement: self.inner_statement.clone(),
+            k: self.k,
+            clustered: self.clustered.clone(),
+        }
+    }
+}
+
+impl ClusterStatement {
+    #[cfg(feature = "advanced")]
+    fn cluster_all(&mut self) -> Result<VecDeque<IteratorItem>, RuntimeError> {
+        let real_op = self.inner_statement.try_real()?;
+        let mut results = VecDeque::new();
+        while let Some(item) = real_op.next() {
+            results.push_back(item);
+        }
+        let mut ctx = real_op.escape();
+        // songs which can't be analysed are replaced by their error
+        let mut features = Vec::with_capacity(results.len());
+        let mut clustered_items = Vec::with_capacity(results.len());
+        let mut has_features = true;
+        for (i, result) in results.iter_mut().enumerate() {
+            if let Ok(item) = result {
+                match ctx.analysis.get_features(item) {
+                    Ok(item_features) if item_features.is_empty() => {
+                        // e.g. an analyzer which only compares tags
+                        has_features = false;
+                        break;
+                    }
+                    Ok(item_features) => {
+                        features.push(item_features.into_iter().map(|(_, x)| x).collect());
+                        clustered_items.push(i);
+                    }
+                    Err(e) => *result = Err(e.with(RuntimeOp(PseudoOp::from_printable(self)))),
+                }
+            }
+        }
+        self.inner_statement.try_real()?.enter(ctx);
+        if !has_features {
+            return Err(RuntimeMsg(
+                "Clustering needs audio analysis, which the current analyzer does not provide"
+                    .to_string(),
+            )
+            .with(RuntimeOp(PseudoOp::from_printable(self))));
+        }
+        let (clusters, distances) = kmeans(&features, self.k);
+        for (i, (cluster, distance)) in clustered_items
+            .into_iter()
+            .zip(clusters.into_iter().zip(distances))
+        {
+            if let Ok(item) = &mut results[i] {
+                item.set_field("cluster", TypePrimitive::UInt(cluster as _));
+                item.set_field("cluster_distance", TypePrimitive::Float(distance));
+            }
+        }
+        Ok(results)
+    }
+
+    // without analysis, items are left as-is
+    #[cfg(not(feature = "advanced"))]
+    fn cluster_all(&mut self) -> Result<VecDeque<IteratorItem>, RuntimeError> {
+        let real_op = self.inner_statement.try_real()?;
+        let mut results = VecDeque::new();
+        while let Some(item) = real_op.next() {
+            results.push_back(item);
+        }
+        Ok(results)
+    }
+}
+
+impl Iterator for ClusterStatement {
+    type Item = IteratorItem;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.clustered.is_none() {
+            // every item is needed before any can be assigned a cluster
+            match self.cluster_all() {
+                Ok(results) => self.clustered = Some(results),
+                Err(e) => {
+                    self.clustered = Some(VecDeque::new());
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.clustered.as_mut().and_then(|items| items.pop_front())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.clustered {
+            Some(items) => (items.len(), Some(items.len())),
+            None => self
+                .inner_statement
+                .try_real_ref()
+                .map(|x| x.size_hint())
+                .unwrap_or((0, None)),
+        }
+    }
+}
+
+impl Op for ClusterStatement {
+    fn enter(&mut self, ctx: Context) {
+        self.inner_statement.try_real().unwrap().enter(ctx)
+    }
+
+    fn escape(&mut self) -> Context {
+        self.inner_statement.try_real().unwrap().escape()
+    }
+
+    fn is_resetable(&self) -> bool {
+        if let Ok(real_op) = self.inner_statement.try_real_ref() {
+            real_op.is_resetable()
+        } else {
+            false
+        }
+    }
+
+    fn reset(&mut self) -> Result<(), RuntimeError> {
+        self.clustered = None;
+        self.inner_statement.try_real()?.reset()
+    }
+
+    fn dup(&self) -> Box<dyn Op> {
+        Box::new(Self {
+            inner_statement: PseudoOp::from(self.inner_statement.try_real_ref().unwrap().dup()),
+            k: self.k,
+            clustered: None,
+        })
+    }
+}
+
+/// K-means clustering, returning the cluster and distance to its centroid for each point.
+///
+/// Centroids start at the point closest to the mean of all points, then
+/// repeatedly at the point furthest from every centroid so far. Unlike random
+/// initialisation, this gives the same clusters every time for the same songs.
+#[cfg(feature = "advanced")]
+fn kmeans(points: &[Vec<f64>], k: usize) -> (Vec<usize>, Vec<f64>) {
+    if points.is_empty() {
+        return (Vec::new(), Vec::new());
+    }
+    let k = k.min(points.len());
+    let dimensions = points[0].len();
+    let mean = centroid(points.iter(), dimensions);
+    let mut centroids = vec![nearest(&mean, points.iter()).0.clone()];
+    while centroids.len() < k {
+        let furthest = points
+            .iter()
+            .map(|point| {
+                let closest = centroids
+                    .iter()
+                    .map(|c| distance(c, point))
+                    .fold(f64::MAX, f64::min);
+                (point, closest)
+            })
+            .fold(
+                (&points[0], -1.0),
+                |best, x| if x.1 > best.1 { x } else { best },
+            );
+        centroids.push(furthest.0.clone());
+    }
+    let mut clusters = vec![usize::MAX; points.len()];
+    for _ in 0..MAX_ITERATIONS {
+        let mut changed = false;
+        for (point, cluster) in points.iter().zip(clusters.iter_mut()) {
+            let (_, closest) = nearest(point, centroids.iter());
+            if *cluster != closest {
+                *cluster = closest;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+        for (c, current_centroid) in centroids.iter_mut().enumerate() {
+            let members = points
+                .iter()
+                .zip(clusters.iter())
+                .filter(|(_, cluster)| **cluster == c)
+                .map(|(point, _)| point);
+            // an empty cluster keeps its old centroid
+            if clusters.contains(&c) {
+                *current_centroid = centroid(members, dimensions);
+            }
+        }
+    }
+    let distances = points
+        .iter()
+        .zip(clusters.iter())
+        .map(|(point, cluster)| distance(point, &centroids[*cluster]))
+        .collect();
+    (clusters, distances)
+}
+
+#[cfg(feature = "advanced")]
+fn centroid<'a, I: Iterator<Item = &'a Vec<f64>>>(points: I, dimensions: usize) -> Vec<f64> {
+    let mut sum = vec![0.0; dimensions];
+    let mut count = 0;
+    for point in points {
+        for (total, x) in sum.iter_mut().zip(point.iter()) {
+            *total += x;
+        }
+        count += 1;
+    }
+    sum.into_iter()
+        .map(|total| total / count.max(1) as f64)
+        .collect()
+}
+
+// closest of the candidates, and its index
+#[cfg(feature = "advanced")]
+fn nearest<'a, I: Iterator<Item = &'a Vec<f64>>>(
+    point: &[f64],
+    candidates: I,
+) -> (&'a Vec<f64>, usize) {
+    let mut best = None;
+    let mut best_distance = f64::MAX;
+    for (i, candidate) in candidates.enumerate() {
+        let d = distance(point, candidate);
+        if best.is_none() || d < best_distance {
+            best = Some((candidate, i));
+            best_distance = d;
+        }
+    }
+    best.unwrap()
+}
+
+#[cfg(feature = "advanced")]
+fn distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum::<f64>()
+        .sqrt()
+}
+
+pub struct ClusterFunctionFactory;
+
+impl FunctionFactory<ClusterStatement> for ClusterFunctionFactory {
+    fn is_function(&self, name: &str) -> bool {
+        name == "cluster"
+    }
+
+    fn build_function_params(
+        &self,
+        _name: String,
+        tokens: &mut VecDeque<Token>,
+        dict: &LanguageDictionary,
+    ) -> Result<ClusterStatement, SyntaxError> {
+        // cluster(iterable) or cluster(iterable, k) or cluster(iterable, k = k)
+        let end_tokens = tokens.split_off(last_comma(tokens));
+        let inner_statement = dict.try_build_statement(tokens)?;
+        tokens.extend(end_tokens);
+        let mut k = DEFAULT_K;
+        if !tokens.is_empty() && tokens[0].is_comma() {
+            assert_token_raw(Token::Comma, tokens)?;
+            if !tokens.is_empty() && check_name("k", &tokens[0]) {
+                assert_name("k", tokens)?;
+                assert_token_raw(Token::Equals, tokens)?;
+            }
+            k = assert_token(
+                |t| match t {
+                    Token::Name(n) => n.parse::<usize>().ok().filter(|k| *k != 0),
+                    _ => None,
+                },
+                Token::Name("k (non-zero integer)".into()),
+                tokens,
+            )?;
+        }
+        Ok(ClusterStatement {
+            inner_statement: inner_statement.into(),
+            k: k,
+            clustered: None,
+        })
+    }
+}
+
+// the parameters come after the iterable, which may contain commas of its own
+fn last_comma(tokens: &VecDeque<Token>) -> usize {
+    let mut depth = 0;
+    let mut last = tokens.len();
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::OpenBracket | Token::OpenCurly => depth += 1,
+            Token::CloseBracket | Token::CloseCurly => depth -= 1,
+            Token::Comma if depth == 0 => last = i,
+            _ => {}
+        }
+    }
+    last
+}
+
+pub type ClusterStatementFactory =
+    FunctionStatementFactory<ClusterStatement, ClusterFunctionFactory>;
+
+#[inline(always)]
+pub fn cluster_function_factory() -> ClusterStatementFactory {
+    ClusterStatementFactory::new(ClusterFunctionFactory)
+}
+
+#[cfg(all(test, feature = "advanced"))]
+mod tests {
+    use super::kmeans;
+
+    #[test]
+    fn kmeans_separated_groups() {
+        let points = vec![
+            vec![0.0, 0.0],
+            vec![10.0, 10.0],
+            vec![0.0, 2.0],
+            vec![10.0, 12.0],
+            vec![2.0, 1.0],
+        ];
+        let (clusters, distances) = kmeans(&points, 2);
+        assert_eq!(clusters[0], clusters[2]);
+        assert_eq!(clusters[0], clusters[4]);
+        assert_eq!(clusters[1], clusters[3]);
+        assert_ne!(clusters[0], clusters[1]);
+        // centroids are (2/3, 1) and (10, 11)
+        let expected = [
+            (4.0f64 / 9.0 + 1.0).sqrt(),
+            1.0,
+            (4.0f64 / 9.0 + 1.0).sqrt(),
+            1.0,
+            4.0 / 3.0,
+        ];
+        for (distance, expected) in distances.iter().zip(expected) {
+            assert!(
+                (distance - expected).abs() < 1e-9,
+                "{} != {}",
+                distance,
+                expected
+            );
+        }
+        // the same points always give the same clusters
+        assert_eq!(kmeans(&points, 2), (clusters, distances));
+    }
+
+    #[test]
+    fn kmeans_more_clusters_than_points() {
+        let points = vec![vec![1.0], vec![5.0], vec![1.0]];
+        let (clusters, distances) = kmeans(&points, 8);
+        assert_eq!(clusters.len(), 3);
+        assert!(clusters.iter().all(|c| *c < 3));
+        // duplicate points share a cluster, and each point is its own centroid
+        assert_eq!(clusters[0], clusters[2]);
+        assert_ne!(clusters[0], clusters[1]);
+        assert!(distances.iter().all(|d| *d == 0.0));
+        assert!(kmeans(&[], 8).0.is_empty());
+    }
+}
@@ -16,7 +16,8 @@ use crate::processing::general::FileIter;
 pub struct FilesStatement {
     context: Option<Context>,
     // function params
-    folder: Option<String>,
+    folders: Vec<String>,
+    excludes: Vec<String>,
     regex: Option<String>,
     recursive: Option<bool>,
     // state
@@ -28,10 +29,20 @@ impl Display for FilesStatement {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         write!(f, "files(")?;
         let mut preceding = false;
-        if let Some(folder) = &self.folder {
+        for folder in self.folders.iter() {
+            if preceding {
+                write!(f, ", ")?;
+            }
             write!(f, "folder=`{}`", folder)?;
             preceding = true;
         }
+        for exclude in self.excludes.iter() {
+            if preceding {
+                write!(f, ", ")?;
+            }
+            write!(f, "exclude=`{}`", exclude)?;
+            preceding = true;
+        }
         if let Some(regex) = &self.regex {
             if preceding {
                 write!(f, ", ")?;
@@ -54,7 +65,8 @@ impl std::clone::Clone for FilesStatement {
     fn clone(&self) -> Self {
         Self {
             context: None,
-            folder: self.folder.clone(),
+            folders: self.folders.clone(),
+            excludes: self.excludes.clone(),
             regex: self.regex.clone(),
             recursive: self.recursive,
             file_iter: None,
@@ -73,8 +85,11 @@ impl Iterator for FilesStatement {
             } else {
                 self.has_tried = true;
             }
-            let iter = self.context.as_mut().unwrap().filesystem.raw(
-                self.folder.as_deref(),
+            let folders: Vec<&str> = self.folders.iter().map(|s| s.as_str()).collect();
+            let excludes: Vec<&str> = self.excludes.iter().map(|s| s.as_str()).collect();
+            let iter = self.context.as_mut().unwrap().filesystem.raw_roots(
+                &folders,
+                &excludes,
                 self.regex.as_deref(),
                 self.recursive.unwrap_or(true),
             );
@@ -141,14 +156,15 @@ impl FunctionFactory<FilesStatement> for FilesFunctionFactory {
         tokens: &mut VecDeque<Token>,
         _dict: &LanguageDictionary,
     ) -> Result<FilesStatement, SyntaxError> {
-        // files([folder|dir=]"path", [regex|re = "pattern",] [recursive = true|false,])
-        let mut root_path = None;
+        // files([folder|dir=]"path", [folder|dir = "path",]... [exclude = "glob",]... [regex|re = "pattern",] [recursive = true|false,])
+        let mut root_paths = Vec::new();
+        let mut excludes = Vec::new();
         let mut pattern = None;
         let mut recursive = None;
         if !tokens.is_empty() && !tokens[0].is_close_bracket() {
             if tokens[0].is_literal() {
                 // folder is specified without keyword
-                root_path = Some(assert_token(
+                root_paths.push(assert_token(
                     |t| match t {
                         Token::Literal(s) => Some(s),
                         _ => None,
@@ -190,7 +206,7 @@ impl FunctionFactory<FilesStatement> for FilesFunctionFactory {
             for (param, val) in params {
                 match &param as &str {
                     "folder" | "dir" => match val {
-                        Token::Literal(s) => root_path = Some(s),
+                        Token::Literal(s) => root_paths.push(s),
                         token => {
                             return Err(SyntaxError {
                                 line: 0,
@@ -199,6 +215,16 @@ impl FunctionFactory<FilesStatement> for FilesFunctionFactory {
                             })
                         }
                     },
+                    "exclude" => match val {
+                        Token::Literal(s) => excludes.push(s),
+                        token => {
+                            return Err(SyntaxError {
+                                line: 0,
+                                token: Token::Literal("exclude pattern".into()),
+                                got: Some(token),
+                            })
+                        }
+                    },
                     "regex" | "re" => match val {
                         Token::Literal(s) => pattern = Some(s),
                         token => {
@@ -232,7 +258,7 @@ impl FunctionFactory<FilesStatement> for FilesFunctionFactory {
                     s => {
                         return Err(SyntaxError {
                             line: 0,
-                            token: Token::Name("folder|regex|recursive".into()),
+                            token: Token::Name("folder|exclude|regex|recursive".into()),
                             got: Some(Token::Name(s.to_owned())),
                         })
                     }
@@ -241,7 +267,8 @@ impl FunctionFactory<FilesStatement> for FilesFunctionFactory {
         }
         Ok(FilesStatement {
             context: None,
-            folder: root_path,
+            folders: root_paths,
+            excludes: excludes,
             regex: pattern,
             recursive,
             file_iter: None,
@@ -0,0 +1,75 @@
+use core::ops::Deref;
+use std::collections::VecDeque;
+use std::fmt::{Debug, Display, Error, Formatter};
+
+use crate::lang::utility::{assert_name, assert_token_raw, check_name};
+use crate::lang::LanguageDictionary;
+use crate::lang::{ItemBlockFactory, ItemOp, ItemOpFactory};
+use crate::lang::{RuntimeMsg, SyntaxError};
+use crate::processing::general::Type;
+use crate::tokens::Token;
+use crate::Context;
+
+#[derive(Debug)]
+pub struct FeaturesItemOp {
+    inner: Box<dyn ItemOp>,
+}
+
+impl Deref for FeaturesItemOp {
+    type Target = dyn ItemOp;
+    fn deref(&self) -> &Self::Target {
+        self
+    }
+}
+
+impl Display for FeaturesItemOp {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "features({})", self.inner)
+    }
+}
+
+impl ItemOp for FeaturesItemOp {
+    fn execute(&self, context: &mut Context) -> Result<Type, RuntimeMsg> {
+        let inner_return = self.inner.execute(context)?;
+        if let Type::Item(item) = inner_return {
+            // without analysis, the item is left as-is
+            #[cfg(feature = "advanced")]
+            let item = {
+                let mut item = item;
+                for (name, value) in context.analysis.get_features(&item)? {
+                    item.set_field(name, crate::lang::TypePrimitive::Float(value));
+                }
+                item
+            };
+            Ok(Type::Item(item))
+        } else {
+            Err(RuntimeMsg(format!(
+                "Cannot analyse {} (should be Item)",
+                inner_return
+            )))
+        }
+    }
+}
+
+pub struct FeaturesItemOpFactory;
+
+impl ItemOpFactory<FeaturesItemOp> for FeaturesItemOpFactory {
+    fn is_item_op(&self, tokens: &VecDeque<Token>) -> bool {
+        tokens.len() > 1 && check_name("features", &tokens[0]) && tokens[1].is_open_bracket()
+    }
+
+    fn build_item_op(
+        &self,
+        tokens: &mut VecDeque<Token>,
+        factory: &ItemBlockFactory,
+        dict: &LanguageDictionary,
+    ) -> Result<FeaturesItemOp, SyntaxError> {
+        assert_name("features", tokens)?;
+        assert_token_raw(Token::OpenBracket, tokens)?;
+        let end_tokens = tokens.split_off(tokens.len() - 1);
+        let inner_op = factory.try_build_item_statement(tokens, dict)?;
+        tokens.extend(end_tokens);
+        assert_token_raw(Token::CloseBracket, tokens)?;
+        Ok(FeaturesItemOp { inner: inner_op })
+    }
+}
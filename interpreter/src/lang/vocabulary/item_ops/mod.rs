@@ -5,6 +5,7 @@ mod compare;
 mod constant;
 mod constructor;
 mod empty;
+mod features;
 mod field_assign;
 mod file;
 mod iter_op;
@@ -27,6 +28,7 @@ pub use compare::{CompareItemOp, CompareItemOpFactory};
 pub use constant::{ConstantItemOp, ConstantItemOpFactory};
 pub use constructor::{ConstructorItemOp, ConstructorItemOpFactory};
 pub use empty::{EmptyItemOp, EmptyItemOpFactory};
+pub use features::{FeaturesItemOp, FeaturesItemOpFactory};
 pub use field_assign::{FieldAssignItemOp, FieldAssignItemOpFactory};
 pub use file::{FileItemOp, FileItemOpFactory};
 pub use iter_op::{IterItemOp, IterItemOpFactory};
@@ -1,33 +1,41 @@
 #![allow(clippy::while_let_on_iterator)]
+mod cluster;
 mod empties;
 pub(crate) mod empty;
 mod files;
 mod intersection;
 mod mpd_query;
+mod path;
 mod playlist;
 mod repeat;
 mod reset;
+mod similarity_preset;
 mod sql_init;
 mod sql_query;
 mod sql_simple_query;
 mod union;
 mod variable_assign;
 mod variable_iter;
+mod variable_persist;
 
+pub use cluster::{cluster_function_factory, ClusterStatementFactory};
 pub use empties::{empties_function_factory, EmptiesStatementFactory};
 pub use empty::{empty_function_factory, EmptyStatementFactory};
 pub use files::{files_function_factory, FilesStatementFactory};
 pub use intersection::{intersection_function_factory, IntersectionStatementFactory};
 pub use mpd_query::{mpd_query_function_factory, MpdQueryStatementFactory};
+pub use path::{path_function_factory, PathStatementFactory};
 pub use playlist::{playlist_function_factory, PlaylistStatementFactory};
 pub use repeat::{repeat_function_factory, RepeatStatementFactory};
 pub use reset::{reset_function_factory, ResetStatementFactory};
+pub use similarity_preset::{PresetStatement, PresetStatementFactory};
 pub use sql_init::{sql_init_function_factory, SqlInitStatementFactory};
 pub use sql_query::{sql_function_factory, SqlStatementFactory};
 pub use sql_simple_query::{simple_sql_function_factory, SimpleSqlStatementFactory};
 pub use union::{union_function_factory, UnionStatementFactory};
 pub use variable_assign::{AssignStatement, AssignStatementFactory};
 pub use variable_iter::{VariableRetrieveStatement, VariableRetrieveStatementFactory};
+pub use variable_persist::{PersistStatement, PersistStatementFactory};
 
 pub mod filters;
 pub mod item_ops;
@@ -0,0 +1,289 @@
+use std::collections::VecDeque;
+use std::fmt::{Debug, Display, Error, Formatter};
+use std::iter::Iterator;
+
+use crate::tokens::Token;
+use crate::Context;
+use crate::Item;
+
+use crate::lang::utility::{assert_file_path, assert_token, assert_token_raw};
+use crate::lang::LanguageDictionary;
+use crate::lang::{FunctionFactory, FunctionStatementFactory, IteratorItem, Op, PseudoOp};
+use crate::lang::{RuntimeError, RuntimeMsg, RuntimeOp, SyntaxError};
+
+const DEFAULT_LENGTH: usize = 20;
+
+#[derive(Debug)]
+pub struct PathStatement {
+    inner_statement: PseudoOp,
+    from: String,
+    to: String,
+    length: usize,
+    // state
+    path: Option<VecDeque<IteratorItem>>,
+}
+
+impl Display for PathStatement {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(
+            f,
+            "path({}, from = `{}`, to = `{}`, length = {})",
+            self.inner_statement, self.from, self.to, self.length
+        )
+    }
+}
+
+impl std::clone::Clone for PathStatement {
+    fn clone(&self) -> Self {
+        Self {
+            inner_statement: self.inner_statement.clone(),
+            from: self.from.clone(),
+            to: self.to.clone(),
+            length: self.length,
+            path: self.path.clone(),
+        }
+    }
+}
+
+impl PathStatement {
+    fn find_path(&mut self) -> Result<VecDeque<IteratorItem>, RuntimeError> {
+        let real_op = self.inner_statement.try_real()?;
+        let mut candidates = Vec::new();
+        let mut errors = VecDeque::new();
+        while let Some(item) = real_op.next() {
+            match item {
+                Ok(item) => candidates.push(item),
+                Err(e) => errors.push_back(Err(e)),
+            }
+        }
+        let mut ctx = real_op.escape();
+        let path = self.choose_path(candidates, &mut ctx);
+        self.inner_statement.try_real()?.enter(ctx);
+        let path = path.map_err(|e| e.with(RuntimeOp(PseudoOp::from_printable(self))))?;
+        errors.extend(path.into_iter().map(Ok));
+        Ok(errors)
+    }
+
+    /// Choose songs between the first and last songs, by repeatedly picking the song
+    /// which is close to the previous song and about the right distance from the last song.
+    #[cfg(feature = "advanced")]
+    fn choose_path(
+        &self,
+        candidates: Vec<Item>,
+        ctx: &mut Context,
+    ) -> Result<Vec<Item>, RuntimeMsg> {
+        let first = ctx.filesystem.single(&self.from, None)?;
+        let last = ctx.filesystem.single(&self.to, None)?;
+        let mut candidates: Vec<Item> = candidates
+            .into_iter()
+            .filter(|item| !same_file(item, &first) && !same_file(item, &last))
+            .collect();
+        for item in candidates.iter() {
+            ctx.analysis.prepare_distance(&last, item)?;
+        }
+        let total_distance = ctx.analysis.get_distance(&first, &last)?;
+        let mut to_last = Vec::with_capacity(candidates.len());
+        for item in candidates.iter() {
+            to_last.push(ctx.analysis.get_distance(item, &last)?);
+        }
+        let steps = self.length.saturating_sub(1).max(1);
+        let mut path = vec![first];
+        for step in 1..steps {
+            if candidates.is_empty() {
+                break;
+            }
+            let previous = path.last().unwrap();
+            for item in candidates.iter() {
+                ctx.analysis.prepare_distance(previous, item)?;
+            }
+            // how far from the last song the path should be by now, to progress steadily towards it
+            let target = total_distance * (steps - step) as f64 / steps as f64;
+            let mut best_index = 0;
+            let mut best_cost = f64::MAX;
+            for (i, item) in candidates.iter().enumerate() {
+                let cost = ctx.analysis.get_distance(previous, item)? + (to_last[i] - target).abs();
+                if cost < best_cost {
+                    best_index = i;
+                    best_cost = cost;
+                }
+            }
+            path.push(candidates.swap_remove(best_index));
+            to_last.swap_remove(best_index);
+        }
+        if self.length > 1 {
+            path.push(last);
+        }
+        path.truncate(self.length);
+        Ok(path)
+    }
+
+    // without analysis, the songs in between are just the first from the iterable
+    #[cfg(not(feature = "advanced"))]
+    fn choose_path(
+        &self,
+        candidates: Vec<Item>,
+        ctx: &mut Context,
+    ) -> Result<Vec<Item>, RuntimeMsg> {
+        let first = ctx.filesystem.single(&self.from, None)?;
+        let last = ctx.filesystem.single(&self.to, None)?;
+        let middle: Vec<Item> = candidates
+            .into_iter()
+            .filter(|item| !same_file(item, &first) && !same_file(item, &last))
+            .take(self.length.saturating_sub(2))
+            .collect();
+        let mut path = vec![first];
+        path.extend(middle);
+        if self.length > 1 {
+            path.push(last);
+        }
+        path.truncate(self.length);
+        Ok(path)
+    }
+}
+
+fn same_file(a: &Item, b: &Item) -> bool {
+    match (a.field("filename"), b.field("filename")) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+impl Iterator for PathStatement {
+    type Item = IteratorItem;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.path.is_none() {
+            // every item is needed to choose the best path
+            match self.find_path() {
+                Ok(path) => self.path = Some(path),
+                Err(e) => {
+                    self.path = Some(VecDeque::new());
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.path.as_mut().and_then(|items| items.pop_front())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.path {
+            Some(items) => (items.len(), Some(items.len())),
+            None => (0, Some(self.length)),
+        }
+    }
+}
+
+impl Op for PathStatement {
+    fn enter(&mut self, ctx: Context) {
+        self.inner_statement.try_real().unwrap().enter(ctx)
+    }
+
+    fn escape(&mut self) -> Context {
+        self.inner_statement.try_real().unwrap().escape()
+    }
+
+    fn is_resetable(&self) -> bool {
+        if let Ok(real_op) = self.inner_statement.try_real_ref() {
+            real_op.is_resetable()
+        } else {
+            false
+        }
+    }
+
+    fn reset(&mut self) -> Result<(), RuntimeError> {
+        self.path = None;
+        self.inner_statement.try_real()?.reset()
+    }
+
+    fn dup(&self) -> Box<dyn Op> {
+        Box::new(Self {
+            inner_statement: PseudoOp::from(self.inner_statement.try_real_ref().unwrap().dup()),
+            from: self.from.clone(),
+            to: self.to.clone(),
+            length: self.length,
+            path: None,
+        })
+    }
+}
+
+pub struct PathFunctionFactory;
+
+impl FunctionFactory<PathStatement> for PathFunctionFactory {
+    fn is_function(&self, name: &str) -> bool {
+        name == "path"
+    }
+
+    fn build_function_params(
+        &self,
+        _name: String,
+        tokens: &mut VecDeque<Token>,
+        dict: &LanguageDictionary,
+    ) -> Result<PathStatement, SyntaxError> {
+        // path(iterable, from = file(a), to = file(b), length = 20)
+        let end_tokens = tokens.split_off(next_comma(tokens));
+        let inner_statement = dict.try_build_statement(tokens)?;
+        tokens.extend(end_tokens);
+        let mut from = None;
+        let mut to = None;
+        let mut length = DEFAULT_LENGTH;
+        while !tokens.is_empty() && tokens[0].is_comma() {
+            assert_token_raw(Token::Comma, tokens)?;
+            let param = assert_token(
+                |t| match t {
+                    Token::Name(n) if n == "from" || n == "to" || n == "length" => Some(n),
+                    _ => None,
+                },
+                Token::Name("from|to|length".into()),
+                tokens,
+            )?;
+            assert_token_raw(Token::Equals, tokens)?;
+            match &param as &str {
+                "from" => from = Some(assert_file_path(tokens)?),
+                "to" => to = Some(assert_file_path(tokens)?),
+                _ => {
+                    length = assert_token(
+                        |t| match t {
+                            Token::Name(n) => n.parse::<usize>().ok(),
+                            _ => None,
+                        },
+                        Token::Name("usize".into()),
+                        tokens,
+                    )?
+                }
+            }
+        }
+        let missing = |name: &str| SyntaxError {
+            line: 0,
+            token: Token::Name(name.into()),
+            got: None,
+        };
+        Ok(PathStatement {
+            inner_statement: inner_statement.into(),
+            from: from.ok_or_else(|| missing("from"))?,
+            to: to.ok_or_else(|| missing("to"))?,
+            length: length,
+            path: None,
+        })
+    }
+}
+
+// the iterable may contain commas of its own, in brackets
+fn next_comma(tokens: &VecDeque<Token>) -> usize {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::OpenBracket | Token::OpenCurly => depth += 1,
+            Token::CloseBracket | Token::CloseCurly => depth -= 1,
+            Token::Comma if depth == 0 => return i,
+            _ => {}
+        }
+    }
+    tokens.len()
+}
+
+pub type PathStatementFactory = FunctionStatementFactory<PathStatement, PathFunctionFactory>;
+
+#[inline(always)]
+pub fn path_function_factory() -> PathStatementFactory {
+    PathStatementFactory::new(PathFunctionFactory)
+}
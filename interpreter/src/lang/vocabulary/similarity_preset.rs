@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+use std::fmt::{Debug, Display, Error, Formatter};
+use std::iter::Iterator;
+
+use crate::tokens::Token;
+use crate::Context;
+
+use crate::lang::utility::{assert_name, assert_token, assert_token_raw, check_name};
+use crate::lang::vocabulary::sorters::SimilaritySpec;
+use crate::lang::LanguageDictionary;
+use crate::lang::{BoxedOpFactory, IteratorItem, Op, OpFactory};
+#[cfg(feature = "advanced")]
+use crate::lang::{PseudoOp, RuntimeOp};
+use crate::lang::{RuntimeError, SyntaxError};
+
+#[derive(Debug)]
+pub struct PresetStatement {
+    preset_name: String,
+    similarity: SimilaritySpec,
+    context: Option<Context>,
+    is_tried: bool,
+}
+
+impl Display for PresetStatement {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "preset {} = {}", self.preset_name, self.similarity)
+    }
+}
+
+impl std::clone::Clone for PresetStatement {
+    fn clone(&self) -> Self {
+        Self {
+            preset_name: self.preset_name.clone(),
+            similarity: self.similarity.clone(),
+            context: None,
+            is_tried: self.is_tried,
+        }
+    }
+}
+
+impl Iterator for PresetStatement {
+    type Item = IteratorItem;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_tried {
+            return None;
+        }
+        self.is_tried = true;
+        #[cfg(feature = "advanced")]
+        {
+            let presets = &mut self.context.as_mut().unwrap().similarity_presets;
+            // presets can build on other presets, which are resolved now so redefining them later has no effect
+            match self.similarity.options(presets) {
+                Ok(options) => {
+                    presets.define(self.preset_name.clone(), options);
+                    None
+                }
+                Err(e) => Some(Err(e.with(RuntimeOp(PseudoOp::from_printable(self))))),
+            }
+        }
+        #[cfg(not(feature = "advanced"))]
+        {
+            // nothing uses presets without analysis
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(1))
+    }
+}
+
+impl Op for PresetStatement {
+    fn enter(&mut self, ctx: Context) {
+        self.context = Some(ctx)
+    }
+
+    fn escape(&mut self) -> Context {
+        self.context.take().unwrap()
+    }
+
+    fn is_resetable(&self) -> bool {
+        true
+    }
+
+    fn reset(&mut self) -> Result<(), RuntimeError> {
+        self.is_tried = false;
+        Ok(())
+    }
+
+    fn dup(&self) -> Box<dyn Op> {
+        Box::new(Self {
+            preset_name: self.preset_name.clone(),
+            similarity: self.similarity.clone(),
+            context: None,
+            is_tried: false,
+        })
+    }
+}
+
+pub struct PresetStatementFactory;
+
+impl OpFactory<PresetStatement> for PresetStatementFactory {
+    fn is_op(&self, tokens: &VecDeque<Token>) -> bool {
+        tokens.len() >= 3
+            && check_name("preset", &tokens[0])
+            && tokens[1].is_name()
+            && tokens[2].is_equals()
+    }
+
+    fn build_op(
+        &self,
+        tokens: &mut VecDeque<Token>,
+        _dict: &LanguageDictionary,
+    ) -> Result<PresetStatement, SyntaxError> {
+        // preset preset_name = similarity options
+        assert_name("preset", tokens)?;
+        let name = assert_token(
+            |t| match t {
+                Token::Name(s) => Some(s),
+                _ => None,
+            },
+            Token::Name("preset_name".into()),
+            tokens,
+        )?;
+        assert_token_raw(Token::Equals, tokens)?;
+        let similarity = SimilaritySpec::parse(tokens)?;
+        Ok(PresetStatement {
+            preset_name: name,
+            similarity: similarity,
+            context: None,
+            is_tried: false,
+        })
+    }
+}
+
+impl BoxedOpFactory for PresetStatementFactory {
+    fn build_op_boxed(
+        &self,
+        tokens: &mut VecDeque<Token>,
+        dict: &LanguageDictionary,
+    ) -> Result<Box<dyn Op>, SyntaxError> {
+        self.build_box(tokens, dict)
+    }
+
+    fn is_op_boxed(&self, tokens: &VecDeque<Token>) -> bool {
+        self.is_op(tokens)
+    }
+}
@@ -2,11 +2,16 @@ use std::collections::VecDeque;
 #[cfg(feature = "advanced")]
 use std::fmt::{Debug, Display, Error, Formatter};
 
-use crate::lang::utility::{assert_name, check_name, assert_token_raw};
+#[cfg(feature = "advanced")]
+use super::similarity::similarity_distance;
+use super::similarity::SimilaritySpec;
+use crate::lang::utility::{assert_name, assert_token_raw, check_name};
 use crate::lang::SyntaxError;
 #[cfg(feature = "advanced")]
 use crate::lang::{IteratorItem, Op, RuntimeMsg, Sorter};
 use crate::lang::{LanguageDictionary, SortStatementFactory, SorterFactory};
+#[cfg(feature = "advanced")]
+use crate::processing::advanced::MusicAnalyzerDistance;
 use crate::tokens::Token;
 #[cfg(feature = "advanced")]
 use crate::Item;
@@ -18,6 +23,8 @@ pub struct BlissNextSorter {
     algorithm_done: bool,
     init_done: bool,
     item_buf: VecDeque<Item>,
+    similarity: SimilaritySpec,
+    comparison: Option<MusicAnalyzerDistance>,
 }
 
 #[cfg(feature = "advanced")]
@@ -28,6 +35,8 @@ impl std::clone::Clone for BlissNextSorter {
             algorithm_done: self.algorithm_done,
             init_done: self.init_done,
             item_buf: self.item_buf.clone(),
+            similarity: self.similarity.clone(),
+            comparison: self.comparison.clone(),
         }
     }
 }
@@ -40,6 +49,8 @@ impl Default for BlissNextSorter {
             algorithm_done: false,
             init_done: false,
             item_buf: VecDeque::new(),
+            similarity: SimilaritySpec::default(),
+            comparison: None,
         }
     }
 }
@@ -66,6 +77,13 @@ impl Sorter for BlissNextSorter {
             if !self.item_buf.is_empty() {
                 let first = &self.item_buf[0];
                 let mut ctx = iterator.escape();
+                match self.similarity.resolve(&ctx.similarity_presets) {
+                    Ok(comparison) => self.comparison = comparison,
+                    Err(e) => {
+                        iterator.enter(ctx);
+                        return Err(e);
+                    }
+                }
                 for i in 1..self.item_buf.len() {
                     let item = &self.item_buf[i];
                     if let Err(e) = ctx.analysis.prepare_distance(first, item) {
@@ -85,7 +103,7 @@ impl Sorter for BlissNextSorter {
                 let mut ctx = iterator.escape();
                 for i in 0..self.item_buf.len() {
                     let current_item = &self.item_buf[i];
-                    match ctx.analysis.get_distance(&last, current_item) {
+                    match similarity_distance(&mut ctx, &self.comparison, &last, current_item) {
                         Err(e) => {
                             iterator.enter(ctx);
                             return Err(e);
@@ -134,7 +152,11 @@ pub type BlissNextSorter = crate::lang::vocabulary::sorters::EmptySorter;
 #[cfg(feature = "advanced")]
 impl Display for BlissNextSorter {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        write!(f, "advanced bliss_next")
+        if self.similarity.is_empty() {
+            write!(f, "advanced bliss_next")
+        } else {
+            write!(f, "advanced bliss_next {}", self.similarity)
+        }
     }
 }
 
@@ -158,7 +180,19 @@ impl SorterFactory<BlissNextSorter> for BlissNextSorterFactory {
             assert_name("advanced", tokens)?;
         }
         assert_name("bliss_next", tokens)?;
-        Ok(BlissNextSorter::default())
+        let similarity = SimilaritySpec::parse(tokens)?;
+        #[cfg(not(feature = "advanced"))]
+        {
+            let _ = similarity;
+            Ok(BlissNextSorter::default())
+        }
+        #[cfg(feature = "advanced")]
+        {
+            Ok(BlissNextSorter {
+                similarity: similarity,
+                ..Default::default()
+            })
+        }
     }
 }
 
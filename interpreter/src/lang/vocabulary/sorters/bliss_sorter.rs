@@ -5,7 +5,10 @@ use std::fmt::{Debug, Display, Error, Formatter};
 #[cfg(feature = "advanced")]
 use std::collections::HashMap;
 
-use crate::lang::utility::{assert_name, check_name, assert_token_raw};
+#[cfg(feature = "advanced")]
+use super::similarity::similarity_distance;
+use super::similarity::SimilaritySpec;
+use crate::lang::utility::{assert_name, assert_token_raw, check_name};
 use crate::lang::SyntaxError;
 #[cfg(feature = "advanced")]
 use crate::lang::{IteratorItem, Op, RuntimeMsg, Sorter};
@@ -22,6 +25,7 @@ const DEFAULT_ORDER: std::cmp::Ordering = std::cmp::Ordering::Greater;
 pub struct BlissSorter {
     up_to: usize,
     first_song: Option<Item>,
+    similarity: SimilaritySpec,
 }
 
 #[cfg(feature = "advanced")]
@@ -30,6 +34,7 @@ impl std::clone::Clone for BlissSorter {
         Self {
             up_to: self.up_to,
             first_song: self.first_song.clone(),
+            similarity: self.similarity.clone(),
         }
     }
 }
@@ -40,6 +45,7 @@ impl Default for BlissSorter {
         Self {
             up_to: usize::MAX,
             first_song: None,
+            similarity: SimilaritySpec::default(),
         }
     }
 }
@@ -87,12 +93,19 @@ impl Sorter for BlissSorter {
             let mut cache = HashMap::<Item, f64>::new();
             cache.insert(first.clone(), 0.0);
             let mut ctx = iterator.escape();
+            let comparison = match self.similarity.resolve(&ctx.similarity_presets) {
+                Ok(comparison) => comparison,
+                Err(e) => {
+                    iterator.enter(ctx);
+                    return Err(e);
+                }
+            };
             for i in 0..item_buf.len() {
                 if let Ok(item) = &item_buf[i] {
                     if item == &first {
                         continue;
                     }
-                    match ctx.analysis.get_distance(&first, item) {
+                    match similarity_distance(&mut ctx, &comparison, &first, item) {
                         Err(e) => {
                             iterator.enter(ctx);
                             return Err(e);
@@ -125,7 +138,11 @@ pub type BlissSorter = crate::lang::vocabulary::sorters::EmptySorter;
 #[cfg(feature = "advanced")]
 impl Display for BlissSorter {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        write!(f, "advanced bliss_first")
+        if self.similarity.is_empty() {
+            write!(f, "advanced bliss_first")
+        } else {
+            write!(f, "advanced bliss_first {}", self.similarity)
+        }
     }
 }
 
@@ -149,7 +166,19 @@ impl SorterFactory<BlissSorter> for BlissSorterFactory {
             assert_name("advanced", tokens)?;
         }
         assert_name("bliss_first", tokens)?;
-        Ok(BlissSorter::default())
+        let similarity = SimilaritySpec::parse(tokens)?;
+        #[cfg(not(feature = "advanced"))]
+        {
+            let _ = similarity;
+            Ok(BlissSorter::default())
+        }
+        #[cfg(feature = "advanced")]
+        {
+            Ok(BlissSorter {
+                similarity: similarity,
+                ..Default::default()
+            })
+        }
     }
 }
 
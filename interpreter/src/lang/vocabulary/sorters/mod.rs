@@ -4,6 +4,7 @@ mod empty_sorter;
 mod field_sorter;
 mod radio_sorter;
 mod shuffle;
+mod similarity;
 
 pub use bliss_next_sorter::{
     bliss_next_sort, BlissNextSorter, BlissNextSorterFactory, BlissNextSorterStatementFactory,
@@ -15,3 +16,4 @@ pub use radio_sorter::{radio_sort, RadioSorter, RadioSorterFactory, RadioSorterS
 pub use shuffle::{
     shuffle_sort, ShuffleSorter, ShuffleSorterFactory, ShuffleSorterStatementFactory,
 };
+pub(crate) use similarity::SimilaritySpec;
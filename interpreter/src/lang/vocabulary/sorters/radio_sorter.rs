@@ -2,18 +2,39 @@ use std::collections::VecDeque;
 #[cfg(feature = "advanced")]
 use std::fmt::{Debug, Display, Error, Formatter};
 
+#[cfg(feature = "advanced")]
 use rand::{thread_rng, Rng};
 
-use crate::lang::utility::{assert_name, check_name, assert_token_raw, assert_token};
-use crate::lang::SyntaxError;
 #[cfg(feature = "advanced")]
-use crate::lang::{IteratorItem, Op, RuntimeMsg, Sorter};
-use crate::lang::{LanguageDictionary, SortStatementFactory, SorterFactory};
-use crate::tokens::Token;
+use super::similarity::similarity_distance;
+use super::similarity::SimilaritySpec;
+use crate::lang::utility::{
+    assert_file_path, assert_name, assert_number, assert_token_raw, check_name,
+};
+use crate::lang::vocabulary::filters::field::{
+    BoxedFilterPredicate, FieldFilterBlockFactory, FieldFilterComparisonFactory,
+    FieldFilterMaybeFactory, FieldLikeFilterFactory, FieldRegexFilterFactory,
+};
+use crate::lang::SyntaxError;
+use crate::lang::{FilterFactory, LanguageDictionary, SortStatementFactory, SorterFactory};
 #[cfg(feature = "advanced")]
-use crate::Item;
+use crate::lang::{FilterPredicate, IteratorItem, Op, RuntimeMsg, Sorter};
 #[cfg(feature = "advanced")]
 use crate::processing::advanced::MusicAnalyzerDistance;
+use crate::tokens::Token;
+#[cfg(feature = "advanced")]
+use crate::{Context, Item};
+
+/// The song a radio starts from.
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "advanced"), allow(dead_code))]
+enum RadioSeed {
+    Random,
+    /// A song file, which does not have to be in the iterable
+    File(String),
+    /// The first song in the iterable which matches a field filter
+    Matching(BoxedFilterPredicate),
+}
 
 #[cfg(feature = "advanced")]
 #[derive(Debug)]
@@ -22,7 +43,11 @@ pub struct RadioSorter {
     algorithm_done: bool,
     init_done: bool,
     item_buf: VecDeque<Item>,
+    last: Option<Item>,
+    similarity: SimilaritySpec,
     comparison: Option<MusicAnalyzerDistance>,
+    seed: RadioSeed,
+    max_distance: Option<f64>,
 }
 
 #[cfg(feature = "advanced")]
@@ -33,7 +58,11 @@ impl std::clone::Clone for RadioSorter {
             algorithm_done: self.algorithm_done,
             init_done: self.init_done,
             item_buf: self.item_buf.clone(),
-            comparison: None,
+            last: self.last.clone(),
+            similarity: self.similarity.clone(),
+            comparison: self.comparison.clone(),
+            seed: self.seed.clone(),
+            max_distance: self.max_distance,
         }
     }
 }
@@ -46,8 +75,104 @@ impl Default for RadioSorter {
             algorithm_done: false,
             init_done: false,
             item_buf: VecDeque::new(),
+            last: None,
+            similarity: SimilaritySpec::default(),
             comparison: None,
+            seed: RadioSeed::Random,
+            max_distance: None,
+        }
+    }
+}
+
+#[cfg(feature = "advanced")]
+impl RadioSorter {
+    // remove the first song from the buffer, returning it if it should be emitted.
+    // The seed song is always used as the first song to compare to, even when it's not in the iterable.
+    fn take_seed(&mut self, ctx: &mut Context) -> Result<Option<Item>, RuntimeMsg> {
+        match &mut self.seed {
+            RadioSeed::Random => {
+                let random_num: usize = thread_rng().gen();
+                let random_i = random_num % self.item_buf.len();
+                let first = self.item_buf.remove(random_i).unwrap();
+                self.last = Some(first.clone());
+                Ok(Some(first))
+            }
+            RadioSeed::File(path) => {
+                let seed = ctx.filesystem.single(path, None)?;
+                let filename = seed.field("filename");
+                let seed_i = self
+                    .item_buf
+                    .iter()
+                    .position(|item| filename.is_some() && item.field("filename") == filename);
+                self.last = Some(seed);
+                Ok(seed_i.and_then(|i| self.item_buf.remove(i)))
+            }
+            RadioSeed::Matching(filter) => {
+                filter.reset()?;
+                let mut seed_i = None;
+                for (i, item) in self.item_buf.iter().enumerate() {
+                    if filter.matches(item, ctx)? {
+                        seed_i = Some(i);
+                        break;
+                    }
+                }
+                if let Some(i) = seed_i {
+                    let first = self.item_buf.remove(i).unwrap();
+                    self.last = Some(first.clone());
+                    Ok(Some(first))
+                } else {
+                    Err(RuntimeMsg(format!("No song matches radio seed {}", filter)))
+                }
+            }
+        }
+    }
+
+    fn prepare_distances(&self, ctx: &mut Context) -> Result<(), RuntimeMsg> {
+        if let Some(last) = &self.last {
+            for item in self.item_buf.iter() {
+                ctx.analysis.prepare_distance(last, item)?;
+            }
+        }
+        Ok(())
+    }
+
+    // find the song most similar to the last one
+    fn nearest(&self, last: &Item, ctx: &mut Context) -> Result<(usize, f64), RuntimeMsg> {
+        let mut best_index = 0;
+        let mut best_distance = f64::MAX;
+        for (i, current_item) in self.item_buf.iter().enumerate() {
+            let distance = similarity_distance(ctx, &self.comparison, last, current_item)?;
+            if distance < best_distance {
+                best_index = i;
+                best_distance = distance;
+            }
+        }
+        Ok((best_index, best_distance))
+    }
+
+    fn next_item(&mut self, ctx: &mut Context) -> Result<Option<Item>, RuntimeMsg> {
+        let last = match self.last.take() {
+            Some(last) => last,
+            None => return Ok(None),
+        };
+        if self.item_buf.len() == 1 && self.max_distance.is_none() {
+            // no need to compare when there's no choice
+            return Ok(self.item_buf.pop_front());
+        } else if self.item_buf.is_empty() {
+            return Ok(None);
+        }
+        let (best_index, best_distance) = self.nearest(&last, ctx)?;
+        if let Some(max_distance) = self.max_distance {
+            if best_distance > max_distance {
+                // everything left is too different
+                self.item_buf.clear();
+                return Ok(None);
+            }
         }
+        let next = self.item_buf.remove(best_index).unwrap();
+        self.last = Some(next.clone());
+        self.prepare_distances(ctx)?;
+        Ok(Some(next))
     }
 }
 
@@ -58,6 +183,7 @@ impl Sorter for RadioSorter {
         iterator: &mut dyn Op,
         items_out: &mut VecDeque<IteratorItem>,
     ) -> Result<(), RuntimeMsg> {
+        let mut ctx;
         if !self.init_done {
             // first run
             self.init_done = true;
@@ -70,89 +196,45 @@ impl Sorter for RadioSorter {
                     break;
                 }
             }
-            if !self.item_buf.is_empty() {
-                // choose (new) random first element
-                let random_num: usize = thread_rng().gen();
-                let random_i = random_num % self.item_buf.len();
-                self.item_buf.swap(random_i, 0);
-                // compare everything to new first element
-                let first = &self.item_buf[0];
-                let mut ctx = iterator.escape();
-                for i in 1..self.item_buf.len() {
-                    let item = &self.item_buf[i];
-                    if let Err(e) = ctx.analysis.prepare_distance(first, item) {
-                        iterator.enter(ctx);
-                        return Err(e);
-                    }
-                }
-                iterator.enter(ctx);
-                items_out.push_back(Ok(first.to_owned()));
+            if self.item_buf.is_empty() {
+                return Ok(());
             }
-        } else {
-            #[allow(clippy::collapsible_else_if)]
-            if self.item_buf.len() > 2 {
-                let last = self.item_buf.pop_front().unwrap();
-                let mut best_index = 0;
-                let mut best_distance = f64::MAX;
-                let mut ctx = iterator.escape();
-                for i in 0..self.item_buf.len() {
-                    let current_item = &self.item_buf[i];
-                    if let Some(comp) = self.comparison.clone() {
-                        match ctx.analysis.get_custom_distance(&last, current_item, comp) {
-                            Err(e) => {
-                                iterator.enter(ctx);
-                                return Err(e);
-                            }
-                            Ok(distance) => {
-                                if distance < best_distance {
-                                    best_index = i;
-                                    best_distance = distance;
-                                }
-                            }
-                        }
-                    } else {
-                        match ctx.analysis.get_distance(&last, current_item) {
-                            Err(e) => {
-                                iterator.enter(ctx);
-                                return Err(e);
-                            }
-                            Ok(distance) => {
-                                if distance < best_distance {
-                                    best_index = i;
-                                    best_distance = distance;
-                                }
-                            }
-                        }
-                    }
-
-                }
-                if best_index != 0 {
-                    self.item_buf.swap(0, best_index);
-                }
-                items_out.push_back(Ok(self.item_buf[0].clone()));
-                let next = &self.item_buf[0];
-                for i in 1..self.item_buf.len() {
-                    let item = &self.item_buf[i];
-                    if let Err(e) = ctx.analysis.prepare_distance(next, item) {
-                        iterator.enter(ctx);
-                        return Err(e);
-                    }
+            ctx = iterator.escape();
+            // compare everything to the seed
+            let first = match self
+                .similarity
+                .resolve(&ctx.similarity_presets)
+                .map(|comparison| self.comparison = comparison)
+                .and_then(|_| self.take_seed(&mut ctx))
+                .and_then(|first| self.prepare_distances(&mut ctx).map(|_| first))
+            {
+                Ok(first) => first,
+                Err(e) => {
+                    iterator.enter(ctx);
+                    return Err(e);
                 }
+            };
+            if let Some(first) = first {
                 iterator.enter(ctx);
-            } else if self.item_buf.len() == 2 {
-                self.item_buf.pop_front();
-                items_out.push_back(Ok(self.item_buf.pop_front().unwrap()));
-                // note item_buf is emptied here, so this will not proceed to len() == 1 case on next call
-            } else if !self.item_buf.is_empty() {
-                // edge case where item_buf only ever had 1 item
-                items_out.push_back(Ok(self.item_buf.pop_front().unwrap()));
+                items_out.push_back(Ok(first));
+                return Ok(());
             }
+            // the seed isn't in the iterable, so start with the song most similar to it
+        } else {
+            ctx = iterator.escape();
+        }
+        let next = self.next_item(&mut ctx);
+        iterator.enter(ctx);
+        if let Some(next) = next? {
+            items_out.push_back(Ok(next));
         }
         Ok(())
     }
 
     fn reset(&mut self) {
         self.init_done = false;
+        self.item_buf.clear();
+        self.last = None;
     }
 }
 
@@ -162,53 +244,87 @@ pub type RadioSorter = crate::lang::vocabulary::sorters::EmptySorter;
 #[cfg(feature = "advanced")]
 impl Display for RadioSorter {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        write!(f, "~ radio")
+        write!(f, "~ radio")?;
+        if !self.similarity.is_empty() {
+            write!(f, " {}", self.similarity)?;
+        }
+        match &self.seed {
+            RadioSeed::Random => {}
+            RadioSeed::File(path) => write!(f, " from `{}`", path)?,
+            RadioSeed::Matching(filter) => write!(f, " seed {}", filter)?,
+        }
+        if let Some(max_distance) = self.max_distance {
+            write!(f, " max_distance={}", max_distance)?;
+        }
+        Ok(())
     }
 }
 
 pub struct RadioSorterFactory;
 
+impl RadioSorterFactory {
+    // `seed .field <comparison>`, like a field filter
+    fn build_filter_seed(
+        tokens: &mut VecDeque<Token>,
+        dict: &LanguageDictionary,
+    ) -> Result<BoxedFilterPredicate, SyntaxError> {
+        assert_name("seed", tokens)?;
+        FieldFilterBlockFactory::new()
+            .push(FieldFilterComparisonFactory)
+            .push(FieldFilterMaybeFactory)
+            .push(FieldLikeFilterFactory)
+            .push(FieldRegexFilterFactory)
+            .build_filter(tokens, dict)
+    }
+}
+
 impl SorterFactory<RadioSorter> for RadioSorterFactory {
     fn is_sorter(&self, tokens: &VecDeque<&Token>) -> bool {
-        tokens.len() > 1
-            && tokens[0].is_tilde()
-            && check_name("radio", tokens[1])
+        tokens.len() > 1 && tokens[0].is_tilde() && check_name("radio", tokens[1])
     }
 
     fn build_sorter(
         &self,
         tokens: &mut VecDeque<Token>,
-        _dict: &LanguageDictionary,
+        dict: &LanguageDictionary,
     ) -> Result<RadioSorter, SyntaxError> {
         assert_token_raw(Token::Tilde, tokens)?;
         assert_name("radio", tokens)?;
-        #[allow(dead_code)]
-        let mode = if !tokens.is_empty() {
-            Some(assert_token(|t| match t {
-                Token::Name(n) => match &n as &str {
-                    "tempo" | "beat" => Some(MusicAnalyzerDistance::Tempo),
-                    "spectrum" | "s" => Some(MusicAnalyzerDistance::Spectrum),
-                    "loudness" | "volume" => Some(MusicAnalyzerDistance::Loudness),
-                    "chroma" | "c" => Some(MusicAnalyzerDistance::Chroma),
-                    _ => None
-                },
-                _ => None,
-            }, Token::Name("".into()), tokens)?)
-        } else {
-            None
-        };
+        let mut similarity = SimilaritySpec::default();
+        let mut seed = RadioSeed::Random;
+        let mut max_distance = None;
+        while !tokens.is_empty() {
+            if check_name("from", &tokens[0]) {
+                assert_name("from", tokens)?;
+                seed = RadioSeed::File(assert_file_path(tokens)?);
+            } else if check_name("seed", &tokens[0]) {
+                seed = RadioSeed::Matching(Self::build_filter_seed(tokens, dict)?);
+            } else if check_name("max_distance", &tokens[0]) {
+                assert_name("max_distance", tokens)?;
+                assert_token_raw(Token::Equals, tokens)?;
+                max_distance = Some(assert_number(tokens)?);
+            } else {
+                similarity.parse_param(tokens)?;
+            }
+        }
         #[cfg(not(feature = "advanced"))]
-        {Ok(RadioSorter::default())}
+        {
+            let _ = (similarity, seed, max_distance);
+            Ok(RadioSorter::default())
+        }
         #[cfg(feature = "advanced")]
-        {Ok(RadioSorter {
-            comparison: mode,
-            ..Default::default()
-        })}
+        {
+            Ok(RadioSorter {
+                similarity: similarity,
+                seed: seed,
+                max_distance: max_distance,
+                ..Default::default()
+            })
+        }
     }
 }
 
-pub type RadioSorterStatementFactory =
-    SortStatementFactory<RadioSorter, RadioSorterFactory>;
+pub type RadioSorterStatementFactory = SortStatementFactory<RadioSorter, RadioSorterFactory>;
 
 #[inline(always)]
 pub fn radio_sort() -> RadioSorterStatementFactory {
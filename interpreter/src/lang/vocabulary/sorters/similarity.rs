@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+use std::fmt::{Display, Error, Formatter};
+
+use crate::lang::utility::{
+    assert_name, assert_number, assert_token, assert_token_raw, check_name,
+};
+#[cfg(feature = "advanced")]
+use crate::lang::RuntimeMsg;
+use crate::lang::SyntaxError;
+use crate::processing::{feature_group_index, FEATURE_GROUP_NAMES};
+#[cfg(feature = "advanced")]
+use crate::processing::advanced::{
+    DistanceMetric, MusicAnalyzerDistance, SimilarityOptions, SimilarityPresets,
+};
+use crate::tokens::Token;
+#[cfg(feature = "advanced")]
+use crate::{Context, Item};
+
+const METRICS: [&str; 3] = ["euclidean", "cosine", "mahalanobis"];
+
+/// Similarity options for a sorter, e.g. `preset=dance tempo=2 chroma=0.5 cosine`.
+///
+/// Weights which aren't given are 1, or those of the preset, unless a feature group is named without a weight
+/// (e.g. `tempo`), in which case only the named groups are compared.
+#[derive(Debug, Clone, Default)]
+pub struct SimilaritySpec {
+    preset: Option<String>,
+    only: Vec<usize>,
+    weights: Vec<(usize, f64)>,
+    metric: Option<&'static str>,
+}
+
+impl SimilaritySpec {
+    pub fn is_empty(&self) -> bool {
+        self.preset.is_none()
+            && self.only.is_empty()
+            && self.weights.is_empty()
+            && self.metric.is_none()
+    }
+
+    /// Parse one similarity option
+    pub fn parse_param(&mut self, tokens: &mut VecDeque<Token>) -> Result<(), SyntaxError> {
+        if !tokens.is_empty() && check_name("preset", &tokens[0]) {
+            assert_name("preset", tokens)?;
+            assert_token_raw(Token::Equals, tokens)?;
+            self.preset = Some(assert_token(
+                |t| match t {
+                    Token::Name(n) => Some(n),
+                    _ => None,
+                },
+                Token::Name("preset_name".into()),
+                tokens,
+            )?);
+            return Ok(());
+        }
+        let (group, metric) = assert_token(
+            |t| match t {
+                Token::Name(n) => {
+                    if let Some(group) = feature_group_index(&n) {
+                        Some((Some(group), None))
+                    } else {
+                        METRICS
+                            .iter()
+                            .find(|metric| **metric == n)
+                            .map(|metric| (None, Some(*metric)))
+                    }
+                }
+                _ => None,
+            },
+            Token::Name(
+                "tempo|spectrum|loudness|chroma|euclidean|cosine|mahalanobis|preset".into(),
+            ),
+            tokens,
+        )?;
+        if let Some(group) = group {
+            if !tokens.is_empty() && tokens[0].is_equals() {
+                assert_token_raw(Token::Equals, tokens)?;
+                let weight = assert_number(tokens)?;
+                self.weights.push((group, weight));
+            } else {
+                self.only.push(group);
+            }
+        }
+        if metric.is_some() {
+            self.metric = metric;
+        }
+        Ok(())
+    }
+
+    /// Parse every remaining token as similarity options
+    pub fn parse(tokens: &mut VecDeque<Token>) -> Result<Self, SyntaxError> {
+        let mut spec = Self::default();
+        while !tokens.is_empty() {
+            spec.parse_param(tokens)?;
+        }
+        Ok(spec)
+    }
+
+    /// The options to compare songs with, or None to use the analyzer's default distance.
+    #[cfg(feature = "advanced")]
+    pub fn resolve(
+        &self,
+        presets: &SimilarityPresets,
+    ) -> Result<Option<MusicAnalyzerDistance>, RuntimeMsg> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        self.options(presets)
+            .map(|x| Some(MusicAnalyzerDistance::Weighted(x)))
+    }
+
+    /// The options to compare songs with, starting from the defaults when there's no preset.
+    #[cfg(feature = "advanced")]
+    pub fn options(&self, presets: &SimilarityPresets) -> Result<SimilarityOptions, RuntimeMsg> {
+        let mut options = if let Some(name) = &self.preset {
+            presets
+                .get(name)
+                .cloned()
+                .ok_or_else(|| RuntimeMsg(format!("Unknown similarity preset `{}`", name)))?
+        } else {
+            SimilarityOptions::default()
+        };
+        if !self.only.is_empty() {
+            for (i, weight) in options.weights.iter_mut().enumerate() {
+                *weight = if self.only.contains(&i) { 1.0 } else { 0.0 };
+            }
+        }
+        for (group, weight) in self.weights.iter() {
+            options.weights[*group] = *weight;
+        }
+        if let Some(metric) = self.metric.and_then(DistanceMetric::from_name) {
+            options.metric = metric;
+        }
+        Ok(options)
+    }
+}
+
+impl Display for SimilaritySpec {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        let mut params = Vec::new();
+        if let Some(preset) = &self.preset {
+            params.push(format!("preset={}", preset));
+        }
+        for group in self.only.iter() {
+            params.push(FEATURE_GROUP_NAMES[*group][0].to_owned());
+        }
+        for (group, weight) in self.weights.iter() {
+            params.push(format!("{}={}", FEATURE_GROUP_NAMES[*group][0], weight));
+        }
+        if let Some(metric) = self.metric {
+            params.push(metric.to_owned());
+        }
+        write!(f, "{}", params.join(" "))
+    }
+}
+
+/// Distance between songs, using the default distance when there are no similarity options.
+#[cfg(feature = "advanced")]
+pub fn similarity_distance(
+    ctx: &mut Context,
+    comparison: &Option<MusicAnalyzerDistance>,
+    from: &Item,
+    to: &Item,
+) -> Result<f64, RuntimeMsg> {
+    if let Some(comparison) = comparison {
+        ctx.analysis
+            .get_custom_distance(from, to, comparison.clone())
+    } else {
+        ctx.analysis.get_distance(from, to)
+    }
+}
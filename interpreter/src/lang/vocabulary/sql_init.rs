@@ -1,3 +1,4 @@
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::fmt::{Debug, Display, Error, Formatter};
@@ -22,8 +23,10 @@ pub struct SqlInitStatement {
 impl Display for SqlInitStatement {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         write!(f, "sql_init(")?;
-        for (key, val) in self.params.iter() {
-            write!(f, "{} = {},", key, val)?;
+        for (key, vals) in self.params.iter() {
+            for val in vals.lines() {
+                write!(f, "{} = {},", key, val)?;
+            }
         }
         write!(f, ")")
     }
@@ -128,10 +131,23 @@ impl FunctionFactory<SqlInitStatement> for SqlInitFunctionFactory {
             )?;
             Ok(Some((param_name, param_val))) // successfully ingested one phrase
         };
-        let params = repeated_tokens(ingest, Token::Comma).ingest_all(tokens)?;
+        let mut params = HashMap::new();
+        // params given more than once (e.g. several excludes) are kept, one value per line
+        for (name, val) in repeated_tokens(ingest, Token::Comma).ingest_all(tokens)? {
+            match params.entry(name) {
+                Entry::Occupied(mut entry) => {
+                    let vals: &mut String = entry.get_mut();
+                    vals.push('\n');
+                    vals.push_str(&val);
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(val);
+                }
+            }
+        }
         Ok(SqlInitStatement {
             context: None,
-            params: HashMap::from_iter(params),
+            params: params,
             has_tried: false,
         })
     }
@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+use std::fmt::{Debug, Display, Error, Formatter};
+use std::iter::Iterator;
+
+use crate::tokens::Token;
+use crate::Context;
+
+use crate::lang::utility::{assert_token, check_name};
+use crate::lang::LanguageDictionary;
+use crate::lang::{BoxedOpFactory, IteratorItem, Op, OpFactory, PseudoOp};
+use crate::lang::{RuntimeError, RuntimeOp, SyntaxError};
+
+#[derive(Debug)]
+pub struct PersistStatement {
+    variable_name: String,
+    is_forget: bool,
+    context: Option<Context>,
+    is_tried: bool,
+}
+
+impl Display for PersistStatement {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        if self.is_forget {
+            write!(f, "forget {}", self.variable_name)
+        } else {
+            write!(f, "persist {}", self.variable_name)
+        }
+    }
+}
+
+impl std::clone::Clone for PersistStatement {
+    fn clone(&self) -> Self {
+        Self {
+            variable_name: self.variable_name.clone(),
+            is_forget: self.is_forget,
+            context: None,
+            is_tried: self.is_tried,
+        }
+    }
+}
+
+impl Iterator for PersistStatement {
+    type Item = IteratorItem;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_tried {
+            return None;
+        }
+        self.is_tried = true;
+        let variables = &mut self.context.as_mut().unwrap().variables;
+        let result = if self.is_forget {
+            variables.forget(&self.variable_name)
+        } else {
+            variables.persist(&self.variable_name)
+        };
+        match result {
+            Ok(_) => None,
+            Err(e) => Some(Err(e.with(RuntimeOp(PseudoOp::from_printable(self))))),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(1))
+    }
+}
+
+impl Op for PersistStatement {
+    fn enter(&mut self, ctx: Context) {
+        self.context = Some(ctx)
+    }
+
+    fn escape(&mut self) -> Context {
+        self.context.take().unwrap()
+    }
+
+    fn is_resetable(&self) -> bool {
+        true
+    }
+
+    fn reset(&mut self) -> Result<(), RuntimeError> {
+        self.is_tried = false;
+        Ok(())
+    }
+
+    fn dup(&self) -> Box<dyn Op> {
+        Box::new(Self {
+            variable_name: self.variable_name.clone(),
+            is_forget: self.is_forget,
+            context: None,
+            is_tried: false,
+        })
+    }
+}
+
+pub struct PersistStatementFactory;
+
+impl OpFactory<PersistStatement> for PersistStatementFactory {
+    fn is_op(&self, tokens: &VecDeque<Token>) -> bool {
+        tokens.len() >= 2
+            && (check_name("persist", &tokens[0]) || check_name("forget", &tokens[0]))
+            && tokens[1].is_name()
+    }
+
+    fn build_op(
+        &self,
+        tokens: &mut VecDeque<Token>,
+        _dict: &LanguageDictionary,
+    ) -> Result<PersistStatement, SyntaxError> {
+        // (persist | forget) variable_name
+        let is_forget = check_name("forget", &tokens[0]);
+        tokens.pop_front();
+        let name = assert_token(
+            |t| match t {
+                Token::Name(s) => Some(s),
+                _ => None,
+            },
+            Token::Name("variable_name".into()),
+            tokens,
+        )?;
+        Ok(PersistStatement {
+            variable_name: name,
+            is_forget: is_forget,
+            context: None,
+            is_tried: false,
+        })
+    }
+}
+
+impl BoxedOpFactory for PersistStatementFactory {
+    fn build_op_boxed(
+        &self,
+        tokens: &mut VecDeque<Token>,
+        dict: &LanguageDictionary,
+    ) -> Result<Box<dyn Op>, SyntaxError> {
+        self.build_box(tokens, dict)
+    }
+
+    fn is_op_boxed(&self, tokens: &VecDeque<Token>) -> bool {
+        self.is_op(tokens)
+    }
+}
@@ -103,13 +103,13 @@
 //! E.g. `files(folder="~/Music/", recursive=true);` is valid function syntax to execute the files function with parameters `folder="~/Music/", recursive=true`.
 //!
 //!
-//! ### sql_init(generate = true|false, folder = "path/to/music");
+//! ### sql_init(generate = true|false, folder = "path/to/music", exclude = "pattern", watch = true|false);
 //!
-//! Initialize the SQLite database connection using the provided parameters. This must be performed before any other database operation (otherwise the database will already be connected with default settings). This returns an empty iterable (contains zero items).
+//! Initialize the SQLite database connection using the provided parameters. This must be performed before any other database operation (otherwise the database will already be connected with default settings). When generate is true, only new, modified and deleted files are re-indexed. Databases created by older versions of Muss are upgraded in place. Several music folders can be given in folder, separated like the PATH environment variable (`:` on Linux). exclude can be given more than once, and files and folders matching any of its gitignore-style patterns are skipped (e.g. `exclude="audiobooks/", exclude="*.wav"`). When watch is true, the database (or the in-memory index used without the `sql` feature) is kept up to date with changes to the music folders while Muss is running. This returns an empty iterable (contains zero items).
 //!
 //! ### sql("SQL query here");
 //!
-//! Perform a raw SQLite query on the database which Muss auto-generates. An iterator of the results is returned.
+//! Perform a raw SQLite query on the database which Muss auto-generates. An iterator of the results is returned. When Muss is built without SQLite (the default), queries are answered from an in-memory index of the music folder instead, which is built by the first query and kept up to date with changes to the folder. This supports a single SELECT with WHERE conditions (comparisons, AND, OR, NOT, IN, BETWEEN, LIKE (with ESCAPE), GLOB, IS NULL and the lower(), upper() and length() functions), ORDER BY one or more fields and LIMIT/OFFSET.
 //!
 //! ### song("something");
 //!
@@ -131,9 +131,9 @@
 //!
 //! Repeat the iterable count times, or infinite times if count is omitted.
 //!
-//! ### files(folder = "path/to/music", recursive = true|false, regex = "pattern");
+//! ### files(folder = "path/to/music", exclude = "pattern", recursive = true|false, regex = "pattern");
 //!
-//! Retrieve all files from a folder, matching a regex pattern.
+//! Retrieve all files from a folder, matching a regex pattern. folder and exclude can be given more than once, to read several folders and to skip files and folders matching gitignore-style patterns (e.g. `exclude="audiobooks/", exclude="*.wav"`). The default folders are listed in the MUSS_MUSIC_PATH environment variable, or ~/Music when it is not set. Paths listed in a `.mussignore` file (same format as `.gitignore`) in any folder are skipped too, both here and when indexing the music library.
 //!
 //! ### mpd(address, term = value, term2 = value2, ...);
 //!
@@ -155,6 +155,14 @@
 //!
 //! Combine multiple iterables such that only items that exist in iterable1 and iterable2 and ... are returned. The order of items from iterable1 is maintained. There is no limit to the amount of iterables which can be provided as parameters.
 //!
+//! ### cluster(iterable, k = count);
+//!
+//! Group songs by audio similarity using k-means clustering of their analysis features, into k clusters (default 8). Each song gets a `cluster` field (0 to k-1) and a `cluster_distance` field with its distance from the middle of the cluster, so songs can then be filtered or sorted by cluster, e.g. `cluster(files(), k = 5).(.cluster == 2)`. Songs keep their original order. Clusters are the same every time for the same songs. This requires audio analysis (the `advanced-bliss` feature), so with an analyzer that only compares tags this returns an error instead. Without the `advanced` feature enabled this is still valid syntax but songs are not clustered.
+//!
+//! ### path(iterable, from = file("path"), to = file("path"), length = count);
+//!
+//! Make a smooth transition from one song to another, using songs from the iterable in between, e.g. `path(files(), from = file("~/Music/calm.flac"), to = file("~/Music/upbeat.flac"), length = 20)`. Each song is chosen to be similar to the one before it while getting steadily closer to the `to` song. The `from` and `to` songs do not have to be in the iterable, and are always the first and last songs. `length` is the total number of songs including `from` and `to` (default 20); the path is shorter if the iterable doesn't have enough songs. This uses the [bliss music analyser](https://github.com/polochon-street/bliss-rs) and requires the `advanced` feature to be enabled (without the feature enabled this is still valid syntax but the songs in between are not chosen by similarity).
+//!
 //! ### empty();
 //!
 //! Empty iterator containing zero items. Useful for deleting items using replacement filters.
@@ -163,6 +171,18 @@
 //!
 //! Iterate over count empty items. The items in this iterator have no fields (i.e. are empty).
 //!
+//! ### persist variable; -- e.g. `persist party;`
+//!
+//! Save a variable so that it is available in future sessions. Iterables are saved as their script text and re-parsed when loaded. This requires persistent variable storage, which the REPL uses by default.
+//!
+//! ### forget variable; -- e.g. `forget party;`
+//!
+//! Stop saving a persisted variable. The variable is still available until the end of the current session.
+//!
+//! ### preset name = options; -- e.g. `preset dance = tempo=2 loudness=0.5 cosine;`
+//!
+//! Define similarity options once, so they can be used by `~radio`, `bliss_first` and `bliss_next` with `preset=name`. Options can start from another preset, e.g. `preset chill = preset=dance loudness=2;`, which copies that preset as it is when this statement runs.
+//!
 //! ## Sorters
 //! Operations to sort the items in an iterable: `iterable~(sorter)`.
 //!
@@ -177,13 +197,20 @@
 //!
 //! ### ~radio
 //! ### ~radio qualifier -- e.g. `iterable~(~radio)`
-//! Sort by musical similarity, starting with a random first song from the iterator. The optional qualifier may be `chroma`, `loudness`, `spectrum`, or `tempo`. When the qualifier is omitted, they are all considered for comparing audio similarity.
+//! ### ~radio from file("path") -- e.g. `iterable~(~radio from file("~/Music/song.flac") max_distance=1.5)`
+//! ### ~radio seed .field comparison -- e.g. `iterable~(~radio seed .title == "Perm")`
+//! ### ~radio options -- e.g. `iterable~(~radio tempo=2 chroma=0.5 cosine)`
+//! Sort by musical similarity, starting with a random first song from the iterator. The optional qualifier may be `chroma`, `loudness`, `spectrum`, or `tempo`. When the qualifier is omitted, they are all considered for comparing audio similarity. Song analysis results are cached on disk and re-used until the song file changes, so only new or modified songs need to be analysed.
+//! Use `from` to start from a song file instead; the song is only included in the results if it is also in the iterator. Use `seed` to start from the first song in the iterator which matches a field filter. When `max_distance` is set, the radio stops once no remaining song is within that distance of the previous one, instead of ending with the songs which aren't similar. Without the `advanced-bliss` feature, songs are compared by their tags (artist, album artist, genre, composer, year and BPM) instead of their audio, which is much faster but less accurate; this also applies to `bliss_first`, `bliss_next` and `path(...)`.
+//! Similarity options may follow `~radio`, `bliss_first` and `bliss_next` to change how songs are compared. Feature groups (`tempo`, `spectrum`, `loudness` and `chroma`) can be weighted, e.g. `tempo=2 chroma=0.5`; naming a group without a weight, like the qualifier above, only compares that group. The distance metric can be `euclidean` (the default), `cosine` (which ignores the magnitude of features) or `mahalanobis` (which accounts for how features vary together across the analysed library). Use `preset=name` to start from a preset defined with `preset name = ...;`.
 //!
 //! ### advanced bliss_first -- e.g. `iterable~(advanced bliss_first);`
+//! ### advanced bliss_first options -- e.g. `iterable~(advanced bliss_first preset=dance);`
 //!
 //! Sort by the distance (similarity) from the first song in the iterator. Songs which are more similar (lower distance) to the first song in the iterator will be placed closer to the first song, while less similar songs will be sorted to the end. This uses the [bliss music analyser](https://github.com/polochon-street/bliss-rs), which is a very slow operation and can cause music playback interruptions for large iterators. This requires the `advanced` feature to be enabled (without the feature enabled this is still valid syntax but doesn't change the order).
 //!
 //! ### advanced bliss_next -- e.g. `iterable~(advanced bliss_next);`
+//! ### advanced bliss_next options -- e.g. `iterable~(advanced bliss_next tempo=2 mahalanobis);`
 //!
 //! Sort by the distance (similarity) between the last played song in the iterator. Similar to bliss_first. The song which is the most similar (lower distance) to the previous song in the iterator will be placed next to it, then the process is repeated. This uses the [bliss music analyser](https://github.com/polochon-street/bliss-rs), which is a very slow operation and can cause music playback interruptions for large iterators. This requires the `advanced` feature to be enabled (without the feature enabled this is still valid syntax but doesn't change the order).
 //!
@@ -249,6 +276,10 @@
 //!
 //! Load an item from file, populating the item with the song's tags.
 //!
+//! ### features(item) -- e.g. `item = features(item),`
+//!
+//! Add audio features from the [bliss music analyser](https://github.com/polochon-street/bliss-rs) to a copy of an item, as Float fields: tempo, zcr, spectral_centroid, spectral_rolloff, spectral_flatness and loudness (plus a _deviation field for each of the last four) and chroma1 to chroma10. This makes filters like `.(.tempo > 0)` and sorters like `~(.loudness)` possible after e.g. `.{item = features(item)}`. Analysis results are cached, so this is only slow the first time a song is analysed. This requires the `advanced` feature to be enabled (without the feature enabled the item is unchanged).
+//!
 
 #![allow(clippy::redundant_field_names)]
 
@@ -271,6 +302,8 @@ pub use debug::Debugger;
 pub use errors::InterpreterError;
 pub use faye::{Interpreter, InterpreterEvent, InterpreterItem};
 //pub use interpretor::{interpretor, Interpretor};
+#[cfg(feature = "export")]
+pub use item::{item_from_json, item_to_json, items_to_json};
 pub use item::Item;
 //pub(crate) use item::ItemRuntimeUtil;
 //pub use music_item::MusicItem;
@@ -1,13 +1,15 @@
 use std::path::Path;
 
 use super::Library;
+#[cfg(feature = "sql")]
+use super::FileInfo;
 
 #[cfg(feature = "sql")]
 use crate::lang::db::*;
 
 pub fn build_library_from_files<P: AsRef<Path>>(path: P, lib: &mut Library) -> std::io::Result<()> {
     //let mut result = Library::new();
-    lib.read_path(path, 10)?;
+    lib.update_path(path, 10)?;
     Ok(())
 }
 
@@ -51,5 +53,21 @@ pub fn build_library_from_sqlite(
     {
         lib.add_genre(genre?);
     }
+    // build file index
+    for file in conn
+        .prepare("SELECT filename, modified, song from files")?
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                FileInfo {
+                    modified: row.get(1)?,
+                    song: row.get(2)?,
+                },
+            ))
+        })?
+    {
+        let (filename, info) = file?;
+        lib.add_file(filename.into(), info);
+    }
     Ok(())
 }
@@ -0,0 +1,250 @@
+use std::io::{Error, ErrorKind, Read, Write};
+
+#[cfg(feature = "export")]
+use serde_json::{json, Map, Value};
+
+use crate::lang::db::*;
+
+// JSON (de)serialisation of library entries
+
+#[cfg(feature = "export")]
+pub(super) fn song_to_json(song: &DbMusicItem) -> Value {
+    json!({
+        "song_id": song.song_id,
+        "title": song.title,
+        "artist": song.artist,
+        "album": song.album,
+        "filename": song.filename,
+        "metadata": song.metadata,
+        "genre": song.genre,
+        "album_artist": song.album_artist,
+    })
+}
+
+#[cfg(feature = "export")]
+pub(super) fn song_from_json(value: &Value) -> std::io::Result<DbMusicItem> {
+    let obj = json_object(value, "song")?;
+    Ok(DbMusicItem {
+        song_id: json_u64(obj, "song_id")?,
+        title: json_string(obj, "title")?,
+        artist: json_u64(obj, "artist")?,
+        album: json_u64_opt(obj, "album")?,
+        filename: json_string(obj, "filename")?,
+        metadata: json_u64(obj, "metadata")?,
+        genre: json_u64(obj, "genre")?,
+        album_artist: json_u64_opt(obj, "album_artist")?,
+    })
+}
+
+#[cfg(feature = "export")]
+pub(super) fn meta_to_json(meta: &DbMetaItem) -> Value {
+    json!({
+        "meta_id": meta.meta_id,
+        "plays": meta.plays,
+        "track": meta.track,
+        "disc": meta.disc,
+        "duration": meta.duration,
+        "date": meta.date,
+        "composer": meta.composer,
+        "bitrate": meta.bitrate,
+        "added": meta.added,
+    })
+}
+
+#[cfg(feature = "export")]
+pub(super) fn meta_from_json(value: &Value) -> std::io::Result<DbMetaItem> {
+    let obj = json_object(value, "metadata")?;
+    Ok(DbMetaItem {
+        meta_id: json_u64(obj, "meta_id")?,
+        plays: json_u64(obj, "plays")?,
+        track: json_u64(obj, "track")?,
+        disc: json_u64(obj, "disc")?,
+        duration: json_u64(obj, "duration")?,
+        date: json_u64(obj, "date")?,
+        // added in later versions, so optional
+        composer: json_string_opt(obj, "composer")?,
+        bitrate: json_u64_opt(obj, "bitrate")?,
+        added: json_u64_opt(obj, "added")?.unwrap_or(0),
+    })
+}
+
+#[cfg(feature = "export")]
+pub(super) fn artist_to_json(artist: &DbArtistItem) -> Value {
+    json!({
+        "artist_id": artist.artist_id,
+        "name": artist.name,
+        "genre": artist.genre,
+    })
+}
+
+#[cfg(feature = "export")]
+pub(super) fn artist_from_json(value: &Value) -> std::io::Result<DbArtistItem> {
+    let obj = json_object(value, "artist")?;
+    Ok(DbArtistItem {
+        artist_id: json_u64(obj, "artist_id")?,
+        name: json_string(obj, "name")?,
+        genre: json_u64(obj, "genre")?,
+    })
+}
+
+#[cfg(feature = "export")]
+pub(super) fn album_to_json(album: &DbAlbumItem) -> Value {
+    json!({
+        "album_id": album.album_id,
+        "title": album.title,
+        "metadata": album.metadata,
+        "artist": album.artist,
+        "genre": album.genre,
+    })
+}
+
+#[cfg(feature = "export")]
+pub(super) fn album_from_json(value: &Value) -> std::io::Result<DbAlbumItem> {
+    let obj = json_object(value, "album")?;
+    Ok(DbAlbumItem {
+        album_id: json_u64(obj, "album_id")?,
+        title: json_string(obj, "title")?,
+        metadata: json_u64(obj, "metadata")?,
+        artist: json_u64(obj, "artist")?,
+        genre: json_u64(obj, "genre")?,
+    })
+}
+
+#[cfg(feature = "export")]
+pub(super) fn genre_to_json(genre: &DbGenreItem) -> Value {
+    json!({
+        "genre_id": genre.genre_id,
+        "title": genre.title,
+    })
+}
+
+#[cfg(feature = "export")]
+pub(super) fn genre_from_json(value: &Value) -> std::io::Result<DbGenreItem> {
+    let obj = json_object(value, "genre")?;
+    Ok(DbGenreItem {
+        genre_id: json_u64(obj, "genre_id")?,
+        title: json_string(obj, "title")?,
+    })
+}
+
+#[cfg(feature = "export")]
+pub(super) fn json_array<'a>(value: &'a Value, key: &str) -> std::io::Result<&'a Vec<Value>> {
+    match value.get(key) {
+        Some(Value::Array(arr)) => Ok(arr),
+        Some(_) => Err(invalid_data(format!("JSON field `{}` is not an array", key))),
+        None => Err(invalid_data(format!("Missing JSON field `{}`", key))),
+    }
+}
+
+#[cfg(feature = "export")]
+fn json_object<'a>(value: &'a Value, name: &str) -> std::io::Result<&'a Map<String, Value>> {
+    value
+        .as_object()
+        .ok_or_else(|| invalid_data(format!("JSON {} is not an object", name)))
+}
+
+#[cfg(feature = "export")]
+fn json_u64(obj: &Map<String, Value>, key: &str) -> std::io::Result<u64> {
+    obj.get(key)
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| invalid_data(format!("Missing or invalid JSON field `{}`", key)))
+}
+
+#[cfg(feature = "export")]
+fn json_u64_opt(obj: &Map<String, Value>, key: &str) -> std::io::Result<Option<u64>> {
+    match obj.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(v) => v
+            .as_u64()
+            .map(Some)
+            .ok_or_else(|| invalid_data(format!("Invalid JSON field `{}`", key))),
+    }
+}
+
+#[cfg(feature = "export")]
+fn json_string(obj: &Map<String, Value>, key: &str) -> std::io::Result<String> {
+    obj.get(key)
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_owned())
+        .ok_or_else(|| invalid_data(format!("Missing or invalid JSON field `{}`", key)))
+}
+
+#[cfg(feature = "export")]
+fn json_string_opt(obj: &Map<String, Value>, key: &str) -> std::io::Result<Option<String>> {
+    match obj.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(v) => v
+            .as_str()
+            .map(|s| Some(s.to_owned()))
+            .ok_or_else(|| invalid_data(format!("Invalid JSON field `{}`", key))),
+    }
+}
+
+// CSV (RFC 4180)
+
+pub(super) fn write_csv_row<W: Write, I: IntoIterator>(writer: &mut W, fields: I) -> std::io::Result<()>
+where
+    I::Item: AsRef<str>,
+{
+    let mut first = true;
+    for field in fields {
+        if !first {
+            write!(writer, ",")?;
+        }
+        first = false;
+        let field = field.as_ref();
+        if field.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+            write!(writer, "\"{}\"", field.replace('"', "\"\""))?;
+        } else {
+            write!(writer, "{}", field)?;
+        }
+    }
+    writeln!(writer)
+}
+
+pub(super) fn read_csv<R: Read>(mut reader: R) -> std::io::Result<Vec<Vec<String>>> {
+    let mut data = String::new();
+    reader.read_to_string(&mut data)?;
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = data.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                field.push(c);
+            }
+        } else {
+            match c {
+                '"' => in_quotes = true,
+                ',' => row.push(std::mem::take(&mut field)),
+                '\r' => {}
+                '\n' => {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+                c => field.push(c),
+            }
+        }
+    }
+    if in_quotes {
+        return Err(invalid_data("Unterminated quoted CSV field".to_owned()));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+pub(super) fn invalid_data(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
@@ -1,12 +1,66 @@
 use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::UNIX_EPOCH;
 
+use symphonia::core::formats::FormatReader;
 use symphonia::core::io::MediaSourceStream;
 use symphonia::core::probe::Hint;
 
+use super::export::*;
 use super::tag::Tags;
 use crate::lang::db::*;
+use crate::processing::general::{ExcludeRules, IGNORE_FILENAME};
+use crate::Item;
+
+const CSV_COLUMNS: [&str; 14] = [
+    "title",
+    "artist",
+    "album_artist",
+    "album",
+    "genre",
+    "track",
+    "disc",
+    "duration",
+    "date",
+    "plays",
+    "filename",
+    "composer",
+    "bitrate",
+    "added",
+];
+
+// columns after these were added later, so they're optional when importing
+const CSV_REQUIRED_COLUMNS: usize = 11;
+
+/// Indexing information about a file in the library
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileInfo {
+    /// Modification time (seconds since the UNIX epoch) when the file was last read
+    pub modified: Option<u64>,
+    /// Song which was read from the file, if it's a song
+    pub song: Option<u64>,
+}
+
+// entries modified since the last clear_modified()
+#[derive(Clone, Default)]
+struct LibraryChanges {
+    songs: HashSet<u64>,
+    metadata: HashSet<u64>,
+    artists: HashSet<u64>,
+    albums: HashSet<u64>,
+    genres: HashSet<u64>,
+    files: HashSet<PathBuf>,
+    removed_songs: HashSet<u64>,
+    removed_metadata: HashSet<u64>,
+    removed_files: HashSet<PathBuf>,
+    removed_artists: HashSet<u64>,
+    removed_albums: HashSet<u64>,
+    removed_genres: HashSet<u64>,
+    // tags of the files which were read, so they don't need reading again to make items
+    tag_items: HashMap<PathBuf, Item>,
+}
 
 #[derive(Clone, Default)]
 pub struct Library {
@@ -15,8 +69,15 @@ pub struct Library {
     artists: HashMap<String, DbArtistItem>,
     albums: HashMap<String, DbAlbumItem>,
     genres: HashMap<String, DbGenreItem>,
-    files: HashSet<PathBuf>,
+    files: HashMap<PathBuf, FileInfo>,
+    next_song_id: u64,
+    next_meta_id: u64,
+    next_artist_id: u64,
+    next_album_id: u64,
+    next_genre_id: u64,
+    changes: LibraryChanges,
     dirty: bool,
+    excludes: ExcludeRules,
 }
 
 impl Library {
@@ -27,11 +88,28 @@ impl Library {
             artists: HashMap::new(),
             albums: HashMap::new(),
             genres: HashMap::new(),
-            files: HashSet::new(),
+            files: HashMap::new(),
+            next_song_id: 0,
+            next_meta_id: 0,
+            next_artist_id: 0,
+            next_album_id: 0,
+            next_genre_id: 0,
+            changes: LibraryChanges::default(),
             dirty: false,
+            excludes: ExcludeRules::new(),
         }
     }
 
+    /// Set the rules for files to skip when reading folders.
+    /// `.mussignore` files are always honoured.
+    pub fn set_excludes(&mut self, excludes: ExcludeRules) {
+        self.excludes = excludes;
+    }
+
+    pub fn excludes(&self) -> &ExcludeRules {
+        &self.excludes
+    }
+
     pub fn len(&self) -> usize {
         self.songs.len()
     }
@@ -42,6 +120,7 @@ impl Library {
 
     pub fn clear_modified(&mut self) {
         self.dirty = false;
+        self.changes = LibraryChanges::default();
     }
 
     pub fn is_modified(&self) -> bool {
@@ -55,19 +134,37 @@ impl Library {
 
     #[inline]
     pub fn contains_path<P: AsRef<Path>>(&self, path: P) -> bool {
-        self.files.contains(&path.as_ref().to_path_buf())
+        self.files.contains_key(path.as_ref())
     }
 
     pub fn all_songs(&self) -> Vec<&'_ DbMusicItem> {
         self.songs.values().collect()
     }
 
+    pub fn changed_songs(&self) -> Vec<&'_ DbMusicItem> {
+        self.changes
+            .songs
+            .iter()
+            .filter_map(|id| self.songs.get(id))
+            .collect()
+    }
+
+    pub fn removed_songs(&self) -> Vec<u64> {
+        self.changes.removed_songs.iter().copied().collect()
+    }
+
     #[inline]
     pub fn add_song(&mut self, song: DbMusicItem) {
         self.modify();
-        if let Ok(path) = PathBuf::from_str(&song.filename) {
-            self.files.insert(path);
+        let filename = song.filename.strip_prefix("file://").unwrap_or(&song.filename);
+        if let Ok(path) = PathBuf::from_str(filename) {
+            self.files.entry(path).or_default().song = Some(song.song_id);
+        }
+        if song.song_id >= self.next_song_id {
+            self.next_song_id = song.song_id + 1;
         }
+        self.changes.songs.insert(song.song_id);
+        self.changes.removed_songs.remove(&song.song_id);
         self.songs.insert(song.song_id, song);
     }
 
@@ -75,9 +172,26 @@ impl Library {
         self.metadata.values().collect()
     }
 
+    pub fn changed_metadata(&self) -> Vec<&'_ DbMetaItem> {
+        self.changes
+            .metadata
+            .iter()
+            .filter_map(|id| self.metadata.get(id))
+            .collect()
+    }
+
+    pub fn removed_metadata(&self) -> Vec<u64> {
+        self.changes.removed_metadata.iter().copied().collect()
+    }
+
     #[inline]
     pub fn add_metadata(&mut self, meta: DbMetaItem) {
         self.modify();
+        if meta.meta_id >= self.next_meta_id {
+            self.next_meta_id = meta.meta_id + 1;
+        }
+        self.changes.metadata.insert(meta.meta_id);
+        self.changes.removed_metadata.remove(&meta.meta_id);
         self.metadata.insert(meta.meta_id, meta);
     }
 
@@ -85,9 +199,25 @@ impl Library {
         self.artists.values().collect()
     }
 
+    pub fn changed_artists(&self) -> Vec<&'_ DbArtistItem> {
+        self.artists
+            .values()
+            .filter(|artist| self.changes.artists.contains(&artist.artist_id))
+            .collect()
+    }
+
+    pub fn removed_artists(&self) -> Vec<u64> {
+        self.changes.removed_artists.iter().copied().collect()
+    }
+
     #[inline]
     pub fn add_artist(&mut self, artist: DbArtistItem) {
         self.modify();
+        if artist.artist_id >= self.next_artist_id {
+            self.next_artist_id = artist.artist_id + 1;
+        }
+        self.changes.artists.insert(artist.artist_id);
+        self.changes.removed_artists.remove(&artist.artist_id);
         self.artists
             .insert(Self::sanitise_key(&artist.name), artist);
     }
@@ -96,9 +226,25 @@ impl Library {
         self.albums.values().collect()
     }
 
+    pub fn changed_albums(&self) -> Vec<&'_ DbAlbumItem> {
+        self.albums
+            .values()
+            .filter(|album| self.changes.albums.contains(&album.album_id))
+            .collect()
+    }
+
+    pub fn removed_albums(&self) -> Vec<u64> {
+        self.changes.removed_albums.iter().copied().collect()
+    }
+
     #[inline]
     pub fn add_album(&mut self, album: DbAlbumItem) {
         self.modify();
+        if album.album_id >= self.next_album_id {
+            self.next_album_id = album.album_id + 1;
+        }
+        self.changes.albums.insert(album.album_id);
+        self.changes.removed_albums.remove(&album.album_id);
         self.albums.insert(Self::sanitise_key(&album.title), album);
     }
 
@@ -106,27 +252,273 @@ impl Library {
         self.genres.values().collect()
     }
 
+    pub fn changed_genres(&self) -> Vec<&'_ DbGenreItem> {
+        self.genres
+            .values()
+            .filter(|genre| self.changes.genres.contains(&genre.genre_id))
+            .collect()
+    }
+
+    pub fn removed_genres(&self) -> Vec<u64> {
+        self.changes.removed_genres.iter().copied().collect()
+    }
+
     #[inline]
     pub fn add_genre(&mut self, genre: DbGenreItem) {
         self.modify();
+        if genre.genre_id >= self.next_genre_id {
+            self.next_genre_id = genre.genre_id + 1;
+        }
+        self.changes.genres.insert(genre.genre_id);
+        self.changes.removed_genres.remove(&genre.genre_id);
         self.genres.insert(Self::sanitise_key(&genre.title), genre);
     }
 
+    pub fn all_files(&self) -> Vec<(&'_ Path, &'_ FileInfo)> {
+        self.files
+            .iter()
+            .map(|(path, info)| (path.as_path(), info))
+            .collect()
+    }
+
+    pub fn changed_files(&self) -> Vec<(&'_ Path, &'_ FileInfo)> {
+        self.changes
+            .files
+            .iter()
+            .filter_map(|path| self.files.get_key_value(path))
+            .map(|(path, info)| (path.as_path(), info))
+            .collect()
+    }
+
+    /// Tags of a file which was read since the last clear_modified(), as an item.
+    pub fn changed_tags(&self, path: &Path) -> Option<&'_ Item> {
+        self.changes.tag_items.get(path)
+    }
+
+    pub fn removed_files(&self) -> Vec<&'_ Path> {
+        self.changes
+            .removed_files
+            .iter()
+            .map(|path| path.as_path())
+            .collect()
+    }
+
+    #[inline]
+    pub fn add_file(&mut self, path: PathBuf, info: FileInfo) {
+        self.modify();
+        self.changes.removed_files.remove(&path);
+        self.changes.files.insert(path.clone());
+        self.files.insert(path, info);
+    }
+
+    /// Remove a file (or every file in a folder) and the songs read from it,
+    /// along with any albums, artists and genres which are left without songs.
+    pub fn remove_path<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref();
+        let to_remove: Vec<PathBuf> = if self.files.contains_key(path) {
+            vec![path.to_path_buf()]
+        } else {
+            self.files
+                .keys()
+                .filter(|file| file.starts_with(path))
+                .cloned()
+                .collect()
+        };
+        let mut removed_songs = false;
+        for file in to_remove {
+            if let Some(info) = self.files.remove(&file) {
+                self.modify();
+                if let Some(song) = info.song.and_then(|id| self.songs.remove(&id)) {
+                    removed_songs = true;
+                    if self.metadata.remove(&song.metadata).is_some() {
+                        self.changes.metadata.remove(&song.metadata);
+                        self.changes.removed_metadata.insert(song.metadata);
+                    }
+                    self.changes.songs.remove(&song.song_id);
+                    self.changes.removed_songs.insert(song.song_id);
+                }
+                self.changes.files.remove(&file);
+                self.changes.tag_items.remove(&file);
+                self.changes.removed_files.insert(file);
+            }
+        }
+        if removed_songs {
+            self.remove_unused();
+        }
+    }
+
+    // albums only exist for their songs, artists for their songs and albums, and genres for all of those
+    fn remove_unused(&mut self) {
+        let mut used_albums = HashSet::new();
+        let mut used_artists = HashSet::new();
+        let mut used_genres = HashSet::new();
+        for song in self.songs.values() {
+            used_albums.extend(song.album);
+            used_artists.insert(song.artist);
+            used_artists.extend(song.album_artist);
+            used_genres.insert(song.genre);
+        }
+        let changes = &mut self.changes;
+        let metadata = &mut self.metadata;
+        self.albums.retain(|_, album| {
+            if used_albums.contains(&album.album_id) {
+                used_artists.insert(album.artist);
+                used_genres.insert(album.genre);
+                true
+            } else {
+                changes.albums.remove(&album.album_id);
+                changes.removed_albums.insert(album.album_id);
+                if metadata.remove(&album.metadata).is_some() {
+                    changes.metadata.remove(&album.metadata);
+                    changes.removed_metadata.insert(album.metadata);
+                }
+                false
+            }
+        });
+        self.artists.retain(|_, artist| {
+            if used_artists.contains(&artist.artist_id) {
+                used_genres.insert(artist.genre);
+                true
+            } else {
+                changes.artists.remove(&artist.artist_id);
+                changes.removed_artists.insert(artist.artist_id);
+                false
+            }
+        });
+        self.genres.retain(|_, genre| {
+            if used_genres.contains(&genre.genre_id) {
+                true
+            } else {
+                changes.genres.remove(&genre.genre_id);
+                changes.removed_genres.insert(genre.genre_id);
+                false
+            }
+        });
+    }
+
+    /// Read all files in a folder which are not already in the library.
     pub fn read_path<P: AsRef<Path>>(&mut self, path: P, depth: usize) -> std::io::Result<()> {
         let path = path.as_ref();
-        if self.contains_path(path) {
-            return Ok(());
-        } // skip existing entries
         if path.is_dir() && depth != 0 {
+            self.excludes.load_ignore_file(path);
             for entry in path.read_dir()? {
-                self.read_path(entry?.path(), depth - 1)?;
+                let entry = entry?.path();
+                if !self.excludes.is_excluded(&entry, entry.is_dir()) {
+                    self.read_path(entry, depth - 1)?;
+                }
             }
         } else if path.is_file() {
-            self.read_file(path)?;
+            let path = canonical_path(path);
+            if self.contains_path(&path) {
+                return Ok(());
+            } // skip existing entries
+            self.read_file(&path)?;
         }
         Ok(())
     }
 
+    /// Bring the library up to date with the files in a folder.
+    /// New and modified files are (re-)read and files which no longer exist are removed.
+    pub fn update_path<P: AsRef<Path>>(&mut self, path: P, depth: usize) -> std::io::Result<()> {
+        let path = canonical_path(path.as_ref());
+        let mut seen = HashSet::new();
+        if path.exists() {
+            self.update_path_impl(&path, depth, &mut seen)?;
+        }
+        let deleted: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter(|file| file.starts_with(&path) && !seen.contains(*file))
+            .cloned()
+            .collect();
+        for file in deleted {
+            self.remove_path(file);
+        }
+        Ok(())
+    }
+
+    fn update_path_impl(
+        &mut self,
+        path: &Path,
+        depth: usize,
+        seen: &mut HashSet<PathBuf>,
+    ) -> std::io::Result<()> {
+        if path.is_dir() && depth != 0 {
+            self.excludes.load_ignore_file(path);
+            for entry in path.read_dir()? {
+                let entry = entry?.path();
+                if !self.excludes.is_excluded(&entry, entry.is_dir()) {
+                    self.update_path_impl(&entry, depth - 1, seen)?;
+                }
+            }
+        } else if path.is_file() {
+            let path = canonical_path(path);
+            self.update_file(&path)?;
+            seen.insert(path);
+        }
+        Ok(())
+    }
+
+    /// Re-read a file if it is new or has been modified since it was last read,
+    /// or remove it if it no longer exists or is excluded.
+    /// Folders are updated recursively.
+    pub fn update_file<P: AsRef<Path>>(&mut self, path: P) -> std::io::Result<()> {
+        let path = canonical_path(path.as_ref());
+        if path.file_name().map(|name| name == IGNORE_FILENAME).unwrap_or(false) {
+            // exclusions changed, so the whole folder needs re-checking
+            return match path.parent() {
+                Some(parent) => self.update_path(parent, usize::MAX),
+                None => Ok(()),
+            };
+        } else if self.excludes.is_path_excluded(&path) {
+            self.remove_path(&path);
+            return Ok(());
+        } else if path.is_dir() {
+            return self.update_path(path, usize::MAX);
+        } else if !path.exists() {
+            self.remove_path(&path);
+            return Ok(());
+        }
+        let modified = file_modified(&path);
+        if let Some(info) = self.files.get(&path) {
+            if info.modified.is_none() {
+                // indexed before modification times were recorded, assume it's up to date
+                let info = FileInfo {
+                    modified: modified,
+                    song: info.song,
+                };
+                self.add_file(path, info);
+                return Ok(());
+            } else if info.modified == modified {
+                return Ok(());
+            }
+            // a modified song is still the same song, so keep when it was added
+            let added = info
+                .song
+                .and_then(|id| self.songs.get(&id))
+                .and_then(|song| self.metadata.get(&song.metadata))
+                .map(|meta| meta.added)
+                .filter(|added| *added != 0);
+            self.remove_path(&path);
+            self.read_file(&path)?;
+            if let Some(added) = added {
+                let meta = self
+                    .files
+                    .get(&path)
+                    .and_then(|info| info.song)
+                    .and_then(|id| self.songs.get(&id))
+                    .and_then(|song| self.metadata.get(&song.metadata))
+                    .cloned();
+                if let Some(mut meta) = meta {
+                    meta.added = added;
+                    self.add_metadata(meta);
+                }
+            }
+            return Ok(());
+        }
+        self.read_file(&path)
+    }
+
     pub fn read_media_tags<P: AsRef<Path>>(path: P) -> std::io::Result<Tags> {
         let path = path.as_ref();
         let file = Box::new(std::fs::File::open(path)?);
@@ -163,12 +555,14 @@ impl Library {
                     tags.add_visual(vis);
                 }
             }
+            add_stream_info(&mut tags, probed.format.as_ref(), path);
         }
         Ok(tags)
     }
 
     fn read_file<P: AsRef<Path>>(&mut self, path: P) -> std::io::Result<()> {
         let path = path.as_ref();
+        let modified = file_modified(path);
         let file = Box::new(std::fs::File::open(path)?);
         // use symphonia to get metadata
         let mss = MediaSourceStream::new(file, Default::default() /* options */);
@@ -179,6 +573,7 @@ impl Library {
             &Default::default(),
         );
         // process audio file, ignoring any processing errors (skip file on error)
+        let mut tag_item = Item::new();
         if let Ok(mut probed) = probed {
             let mut tags = Tags::new(path);
             // collect metadata
@@ -204,8 +599,20 @@ impl Library {
                     tags.add_visual(vis);
                 }
             }
+            add_stream_info(&mut tags, probed.format.as_ref(), path);
             self.generate_entries(&tags);
+            tags.export_to_item(&mut tag_item, true);
         }
+        // remember non-song files too, so they aren't re-read every update
+        let song = self.files.get(path).and_then(|info| info.song);
+        self.add_file(
+            path.to_path_buf(),
+            FileInfo {
+                modified: modified,
+                song: song,
+            },
+        );
+        self.changes.tag_items.insert(path.to_path_buf(), tag_item);
         Ok(())
     }
 
@@ -214,32 +621,33 @@ impl Library {
         if tags.len() == 0 {
             return;
         } // probably not a valid song, let's skip it
-        let song_id = self.songs.len() as u64; // guaranteed to be created
-        let meta_id = self.metadata.len() as u64; // guaranteed to be created
+        let song_id = self.next_song_id; // guaranteed to be created
+        let meta_id = self.next_meta_id; // guaranteed to be created
         self.add_metadata(tags.meta(meta_id)); // definitely necessary
                                                // genre has no links to others, so find that first
         let mut genre = tags.genre(0);
-        genre.genre_id = Self::find_or_gen_id(&self.genres, &genre.title);
-        if genre.genre_id == self.genres.len() as u64 {
+        genre.genre_id = Self::find_or_gen_id(&self.genres, &genre.title, self.next_genre_id);
+        if genre.genre_id == self.next_genre_id {
             self.add_genre(genre.clone());
         }
         // artist only links to genre, so that can be next
         let mut artist = tags.artist(0, genre.genre_id);
-        artist.artist_id = Self::find_or_gen_id(&self.artists, &artist.name);
-        if artist.artist_id == self.artists.len() as u64 {
+        artist.artist_id = Self::find_or_gen_id(&self.artists, &artist.name, self.next_artist_id);
+        if artist.artist_id == self.next_artist_id {
             self.add_artist(artist.clone());
         }
         // same with album artist
         let mut album_artist = tags.album_artist(0, genre.genre_id);
-        album_artist.artist_id = Self::find_or_gen_id(&self.artists, &album_artist.name);
-        if album_artist.artist_id == self.artists.len() as u64 {
+        album_artist.artist_id =
+            Self::find_or_gen_id(&self.artists, &album_artist.name, self.next_artist_id);
+        if album_artist.artist_id == self.next_artist_id {
             self.add_artist(album_artist.clone());
         }
         // album now has all links ready
         let mut album = tags.album(0, 0, album_artist.artist_id, genre.genre_id);
-        album.album_id = Self::find_or_gen_id(&self.albums, &album.title);
-        if album.album_id == self.albums.len() as u64 {
-            let album_meta = tags.album_meta(self.metadata.len() as u64);
+        album.album_id = Self::find_or_gen_id(&self.albums, &album.title, self.next_album_id);
+        if album.album_id == self.next_album_id {
+            let album_meta = tags.album_meta(self.next_meta_id);
             album.metadata = album_meta.meta_id;
             self.add_album(album.clone());
             self.add_metadata(album_meta);
@@ -252,15 +660,212 @@ impl Library {
             Some(album.album_id),
             meta_id,
             genre.genre_id,
+            Some(album_artist.artist_id),
         ));
     }
 
+    /// Write the whole library as a JSON object, with entries sorted by id.
+    #[cfg(feature = "export")]
+    pub fn export_json<W: Write>(&self, writer: W) -> std::io::Result<()> {
+        let mut songs = self.all_songs();
+        songs.sort_by_key(|x| x.song_id);
+        let mut metadata = self.all_metadata();
+        metadata.sort_by_key(|x| x.meta_id);
+        let mut artists = self.all_artists();
+        artists.sort_by_key(|x| x.artist_id);
+        let mut albums = self.all_albums();
+        albums.sort_by_key(|x| x.album_id);
+        let mut genres = self.all_genres();
+        genres.sort_by_key(|x| x.genre_id);
+        let value = serde_json::json!({
+            "songs": songs.into_iter().map(song_to_json).collect::<Vec<_>>(),
+            "metadata": metadata.into_iter().map(meta_to_json).collect::<Vec<_>>(),
+            "artists": artists.into_iter().map(artist_to_json).collect::<Vec<_>>(),
+            "albums": albums.into_iter().map(album_to_json).collect::<Vec<_>>(),
+            "genres": genres.into_iter().map(genre_to_json).collect::<Vec<_>>(),
+        });
+        serde_json::to_writer_pretty(writer, &value).map_err(std::io::Error::from)
+    }
+
+    /// Add entries from JSON written by `export_json`. Entries with the same id are replaced.
+    #[cfg(feature = "export")]
+    pub fn import_json<R: Read>(&mut self, reader: R) -> std::io::Result<()> {
+        let value: serde_json::Value = serde_json::from_reader(reader)?;
+        // parse everything before adding anything, so bad data doesn't leave the library half-imported
+        let songs = json_array(&value, "songs")?
+            .iter()
+            .map(song_from_json)
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let metadata = json_array(&value, "metadata")?
+            .iter()
+            .map(meta_from_json)
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let artists = json_array(&value, "artists")?
+            .iter()
+            .map(artist_from_json)
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let albums = json_array(&value, "albums")?
+            .iter()
+            .map(album_from_json)
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let genres = json_array(&value, "genres")?
+            .iter()
+            .map(genre_from_json)
+            .collect::<std::io::Result<Vec<_>>>()?;
+        songs.into_iter().for_each(|x| self.add_song(x));
+        metadata.into_iter().for_each(|x| self.add_metadata(x));
+        artists.into_iter().for_each(|x| self.add_artist(x));
+        albums.into_iter().for_each(|x| self.add_album(x));
+        genres.into_iter().for_each(|x| self.add_genre(x));
+        Ok(())
+    }
+
+    /// Write the library as CSV, with one row per song.
+    pub fn export_csv<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        let artists: HashMap<u64, &DbArtistItem> =
+            self.artists.values().map(|x| (x.artist_id, x)).collect();
+        let albums: HashMap<u64, &DbAlbumItem> =
+            self.albums.values().map(|x| (x.album_id, x)).collect();
+        let genres: HashMap<u64, &DbGenreItem> =
+            self.genres.values().map(|x| (x.genre_id, x)).collect();
+        write_csv_row(&mut writer, CSV_COLUMNS)?;
+        let mut songs = self.all_songs();
+        songs.sort_by_key(|x| x.song_id);
+        for song in songs {
+            let album = song.album.and_then(|id| albums.get(&id));
+            let meta = self.metadata.get(&song.metadata);
+            let number = |f: fn(&DbMetaItem) -> u64| meta.map(|m| f(m).to_string()).unwrap_or_default();
+            write_csv_row(
+                &mut writer,
+                [
+                    song.title.clone(),
+                    artists.get(&song.artist).map(|x| x.name.clone()).unwrap_or_default(),
+                    album
+                        .and_then(|x| artists.get(&x.artist))
+                        .map(|x| x.name.clone())
+                        .unwrap_or_default(),
+                    album.map(|x| x.title.clone()).unwrap_or_default(),
+                    genres.get(&song.genre).map(|x| x.title.clone()).unwrap_or_default(),
+                    number(|m| m.track),
+                    number(|m| m.disc),
+                    number(|m| m.duration),
+                    number(|m| m.date),
+                    number(|m| m.plays),
+                    song.filename.clone(),
+                    meta.and_then(|m| m.composer.clone()).unwrap_or_default(),
+                    meta.and_then(|m| m.bitrate).map(|x| x.to_string()).unwrap_or_default(),
+                    number(|m| m.added),
+                ],
+            )?;
+        }
+        writer.flush()
+    }
+
+    /// Add songs from CSV written by `export_csv`.
+    /// Artists, albums and genres are matched by name, so ids are not preserved.
+    pub fn import_csv<R: Read>(&mut self, reader: R) -> std::io::Result<()> {
+        let mut rows = read_csv(reader)?.into_iter();
+        let header = rows
+            .next()
+            .ok_or_else(|| invalid_data("Missing CSV header".to_owned()))?;
+        let mut columns = [None; CSV_COLUMNS.len()];
+        for (i, column) in CSV_COLUMNS.iter().enumerate() {
+            columns[i] = header.iter().position(|h| h == column);
+            if columns[i].is_none() && i < CSV_REQUIRED_COLUMNS {
+                return Err(invalid_data(format!("Missing CSV column `{}`", column)));
+            }
+        }
+        for row in rows {
+            let field = |i: usize| {
+                columns[i]
+                    .and_then(|c| row.get(c))
+                    .map(|s| s.as_str())
+                    .unwrap_or("")
+            };
+            let number = |i: usize| field(i).parse::<u64>().unwrap_or(0);
+            // genre has no links to others, so find that first
+            let genre_title = field(4);
+            let genre_id = Self::find_or_gen_id(&self.genres, genre_title, self.next_genre_id);
+            if genre_id == self.next_genre_id {
+                self.add_genre(DbGenreItem {
+                    genre_id: genre_id,
+                    title: genre_title.to_owned(),
+                });
+            }
+            let mut artist_ids = [0; 2];
+            for (artist_id, name) in artist_ids.iter_mut().zip([field(1), field(2)]) {
+                *artist_id = Self::find_or_gen_id(&self.artists, name, self.next_artist_id);
+                if *artist_id == self.next_artist_id {
+                    self.add_artist(DbArtistItem {
+                        artist_id: *artist_id,
+                        name: name.to_owned(),
+                        genre: genre_id,
+                    });
+                }
+            }
+            let album_title = field(3);
+            let album_id = Self::find_or_gen_id(&self.albums, album_title, self.next_album_id);
+            if album_id == self.next_album_id {
+                let album_meta = DbMetaItem {
+                    meta_id: self.next_meta_id,
+                    plays: 0,
+                    track: 0,
+                    disc: 1,
+                    duration: 0,
+                    date: number(8),
+                    composer: None,
+                    bitrate: None,
+                    added: 0,
+                };
+                self.add_album(DbAlbumItem {
+                    album_id: album_id,
+                    title: album_title.to_owned(),
+                    metadata: album_meta.meta_id,
+                    artist: artist_ids[1],
+                    genre: genre_id,
+                });
+                self.add_metadata(album_meta);
+            }
+            let filename = field(10);
+            // replace existing song for the same file
+            let existing = self
+                .files
+                .get(Path::new(filename.strip_prefix("file://").unwrap_or(filename)))
+                .and_then(|info| info.song)
+                .and_then(|id| self.songs.get(&id))
+                .map(|song| (song.song_id, song.metadata));
+            let (song_id, meta_id) = existing.unwrap_or((self.next_song_id, self.next_meta_id));
+            self.add_metadata(DbMetaItem {
+                meta_id: meta_id,
+                plays: number(9),
+                track: number(5),
+                disc: number(6),
+                duration: number(7),
+                date: number(8),
+                composer: Some(field(11)).filter(|x| !x.is_empty()).map(|x| x.to_owned()),
+                bitrate: field(12).parse().ok(),
+                added: number(13),
+            });
+            self.add_song(DbMusicItem {
+                song_id: song_id,
+                title: field(0).to_owned(),
+                artist: artist_ids[0],
+                album: Some(album_id),
+                filename: filename.to_owned(),
+                metadata: meta_id,
+                genre: genre_id,
+                album_artist: Some(artist_ids[1]),
+            });
+        }
+        Ok(())
+    }
+
     #[inline]
-    fn find_or_gen_id<D: DatabaseObj>(map: &HashMap<String, D>, key: &str) -> u64 {
+    fn find_or_gen_id<D: DatabaseObj>(map: &HashMap<String, D>, key: &str, next_id: u64) -> u64 {
         if let Some(obj) = Self::find_by_key(map, key) {
             obj.id()
         } else {
-            map.len() as u64
+            next_id
         }
     }
 
@@ -274,3 +879,40 @@ impl Library {
         key.trim().to_lowercase()
     }
 }
+
+#[inline]
+fn canonical_path(path: &Path) -> PathBuf {
+    // deleted files can't be canonicalized, but their parent folder usually can be
+    path.canonicalize().unwrap_or_else(|_| {
+        match (path.parent().and_then(|p| p.canonicalize().ok()), path.file_name()) {
+            (Some(parent), Some(name)) => parent.join(name),
+            _ => path.to_path_buf(),
+        }
+    })
+}
+
+/// Duration (seconds) and average bitrate (kbps) of the default audio track.
+fn add_stream_info(tags: &mut Tags, format: &dyn FormatReader, path: &Path) {
+    if let Some(track) = format.default_track() {
+        let params = &track.codec_params;
+        if let (Some(frames), Some(sample_rate)) = (params.n_frames, params.sample_rate) {
+            if sample_rate != 0 {
+                let duration = frames / sample_rate as u64;
+                let bitrate = std::fs::metadata(path)
+                    .ok()
+                    .filter(|_| duration != 0)
+                    .map(|meta| meta.len() * 8 / duration / 1000);
+                tags.add_stream_info(Some(duration), bitrate);
+            }
+        }
+    }
+}
+
+#[inline]
+fn file_modified(path: &Path) -> Option<u64> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+}
@@ -1,8 +1,13 @@
 mod build_library;
+mod export;
 mod library;
 mod tag;
+#[cfg(feature = "watch")]
+mod watcher;
 
 pub use build_library::build_library_from_files;
 #[cfg(feature = "sql")]
 pub use build_library::build_library_from_sqlite;
-pub use library::Library;
+pub use library::{FileInfo, Library};
+#[cfg(feature = "watch")]
+pub use watcher::LibraryWatcher;
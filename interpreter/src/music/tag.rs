@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use symphonia::core::meta::{Value, Visual, Tag, StandardTagKey};
 
@@ -155,6 +156,16 @@ impl Tags {
         }
     }
 
+    /// Record properties of the audio stream, which are not stored as tags in the file.
+    pub fn add_stream_info(&mut self, duration: Option<u64>, bitrate: Option<u64>) {
+        if let Some(duration) = duration {
+            self.data.insert("duration".to_owned(), TagType::U64(duration));
+        }
+        if let Some(bitrate) = bitrate {
+            self.data.insert("bitrate".to_owned(), TagType::U64(bitrate));
+        }
+    }
+
     pub fn len(&self) -> usize {
         self.data.len()
     }
@@ -219,6 +230,23 @@ impl Tags {
             .map(|s| s.to_string())
     }
 
+    #[inline]
+    pub fn composer_name(&self) -> Option<String> {
+        self.data
+            .get(std_tag_to_str(StandardTagKey::Composer))
+            .unwrap_or(&TagType::Unknown)
+            .str()
+            .map(|s| s.to_string())
+    }
+
+    #[inline]
+    pub fn bitrate(&self) -> Option<u64> {
+        self.data
+            .get("bitrate")
+            .unwrap_or(&TagType::Unknown)
+            .uint()
+    }
+
     #[inline]
     pub fn track_number(&self) -> Option<u64> {
         self.data
@@ -251,6 +279,7 @@ impl Tags {
         album_id: Option<u64>,
         meta_id: u64,
         genre_id: u64,
+        album_artist_id: Option<u64>,
     ) -> DbMusicItem {
         DbMusicItem {
             song_id: id,
@@ -260,6 +289,7 @@ impl Tags {
             filename: format!("file://{}", self.filename.to_str().unwrap_or("")),
             metadata: meta_id,
             genre: genre_id,
+            album_artist: album_artist_id,
         }
     }
 
@@ -286,6 +316,9 @@ impl Tags {
                 .uint()
                 .unwrap_or(0),
             date: self.track_date().unwrap_or(0),
+            composer: self.composer_name(),
+            bitrate: self.bitrate(),
+            added: now_secs(),
         }
     }
 
@@ -346,6 +379,9 @@ impl Tags {
                 .unwrap_or(&TagType::Unknown)
                 .uint()
                 .unwrap_or(0),
+            composer: None,
+            bitrate: None,
+            added: now_secs(),
         }
     }
 
@@ -370,6 +406,14 @@ impl Tags {
     }
 }
 
+#[inline]
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
 #[derive(Clone)]
 enum TagType {
     Boolean(bool),
@@ -0,0 +1,69 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+// time to wait for more changes before reporting them
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+/// Filesystem watcher for music folders, for keeping a `Library` up to date.
+pub struct LibraryWatcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+}
+
+impl LibraryWatcher {
+    pub fn new<P: AsRef<Path>>(root: P) -> notify::Result<Self> {
+        let (tx, rx) = channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            tx.send(event).unwrap_or(());
+        })?;
+        watcher.watch(root.as_ref(), RecursiveMode::Recursive)?;
+        Ok(Self {
+            watcher: watcher,
+            events: rx,
+        })
+    }
+
+    /// Watch another music folder too.
+    pub fn add_root<P: AsRef<Path>>(&mut self, root: P) -> notify::Result<()> {
+        self.watcher.watch(root.as_ref(), RecursiveMode::Recursive)
+    }
+
+    /// Block until something changes, then return the changed paths.
+    /// Returns None when the watcher has stopped.
+    pub fn wait_for_changes(&self) -> Option<Vec<PathBuf>> {
+        let mut paths = HashSet::new();
+        Self::add_event(&mut paths, self.events.recv().ok()?);
+        // file operations usually come in bursts (e.g. copying an album)
+        while let Ok(event) = self.events.recv_timeout(SETTLE_TIME) {
+            Self::add_event(&mut paths, event);
+        }
+        Some(paths.into_iter().collect())
+    }
+
+    /// Return the paths which have changed since the last call, without blocking.
+    pub fn changes(&self) -> Vec<PathBuf> {
+        let mut paths = HashSet::new();
+        for event in self.events.try_iter() {
+            Self::add_event(&mut paths, event);
+        }
+        paths.into_iter().collect()
+    }
+
+    fn add_event(paths: &mut HashSet<PathBuf>, event: notify::Result<Event>) {
+        if let Ok(event) = event {
+            if !event.kind.is_access() {
+                paths.extend(event.paths);
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for LibraryWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "LibraryWatcher")
+    }
+}
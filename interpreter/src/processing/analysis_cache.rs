@@ -0,0 +1,316 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const CACHE_FILENAME: &str = "analysis.txt";
+const CACHE_HEADER: &str = "# muss analysis cache v1";
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    modified: u64,
+    size: u64,
+    features: Vec<f32>,
+}
+
+/// On-disk store of song analysis results.
+///
+/// Entries are keyed by canonical file path and are only valid while the file's size and
+/// modification time match what they were when the song was analysed.
+#[derive(Debug)]
+pub struct AnalysisCache {
+    path: PathBuf,
+    entries: HashMap<String, CacheEntry>,
+    // removed since the last save, so they aren't merged back in from the file
+    removed: HashSet<String>,
+    dirty: bool,
+}
+
+impl AnalysisCache {
+    /// Load the cache file, or start empty if it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let mut new = Self::empty(path);
+        let data = match std::fs::read_to_string(&new.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(new),
+            Err(e) => return Err(e),
+        };
+        let mut lines = data.lines();
+        if lines.next() != Some(CACHE_HEADER) {
+            // unknown format, start again
+            new.dirty = true;
+            return Ok(new);
+        }
+        for line in lines {
+            if let Some((path, entry)) = parse_entry(line) {
+                new.entries.insert(path, entry);
+            }
+        }
+        Ok(new)
+    }
+
+    pub fn empty<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            entries: HashMap::new(),
+            removed: HashSet::new(),
+            dirty: false,
+        }
+    }
+
+    pub fn default_path() -> PathBuf {
+        dirs::cache_dir()
+            .unwrap_or_else(|| PathBuf::from("./"))
+            .join("muss")
+            .join(CACHE_FILENAME)
+    }
+
+    /// Retrieve the analysis for a file, if it has not changed since it was cached.
+    /// Stale entries are removed.
+    pub fn get(&mut self, path: &str) -> Option<&[f32]> {
+        let (modified, size) = file_stamp(path)?;
+        let key = cache_key(path);
+        let is_stale = match self.entries.get(&key) {
+            Some(entry) => entry.modified != modified || entry.size != size,
+            None => return None,
+        };
+        if is_stale {
+            self.remove(&key);
+            None
+        } else {
+            self.entries
+                .get(&key)
+                .map(|entry| entry.features.as_slice())
+        }
+    }
+
+    pub fn contains(&mut self, path: &str) -> bool {
+        self.get(path).is_some()
+    }
+
+    /// Store the analysis for a file, stamped with the file's current size and modification time.
+    pub fn insert(&mut self, path: &str, features: Vec<f32>) {
+        if path.contains('\n') {
+            // cannot be represented in the cache file
+            return;
+        }
+        if let Some((modified, size)) = file_stamp(path) {
+            let key = cache_key(path);
+            self.removed.remove(&key);
+            self.entries.insert(
+                key,
+                CacheEntry {
+                    modified: modified,
+                    size: size,
+                    features: features,
+                },
+            );
+            self.dirty = true;
+        }
+    }
+
+    pub fn remove(&mut self, path: &str) {
+        let key = cache_key(path);
+        if self.entries.remove(&key).is_some() {
+            self.removed.insert(key);
+            self.dirty = true;
+        }
+    }
+
+    /// Every cached analysis, without checking whether the files have changed.
+    pub fn all_features(&self) -> impl Iterator<Item = &[f32]> {
+        self.entries.values().map(|entry| entry.features.as_slice())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Write the cache to disk, if it has changed since it was last saved.
+    /// Entries saved by someone else in the meantime (e.g. `muss analyze`) are kept.
+    pub fn save(&mut self) -> std::io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Ok(saved) = Self::open(&self.path) {
+            for (path, entry) in saved.entries {
+                if !self.removed.contains(&path) {
+                    self.entries.entry(path).or_insert(entry);
+                }
+            }
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // write to a temporary file first so an interrupted save doesn't corrupt the cache
+        let tmp_path = self.path.with_extension("tmp");
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+        writeln!(file, "{}", CACHE_HEADER)?;
+        for (path, entry) in self.entries.iter() {
+            let features: Vec<String> = entry.features.iter().map(|f| f.to_string()).collect();
+            writeln!(
+                file,
+                "{}\t{}\t{}\t{}",
+                entry.modified,
+                entry.size,
+                features.join(","),
+                path
+            )?;
+        }
+        file.flush()?;
+        drop(file);
+        std::fs::rename(&tmp_path, &self.path)?;
+        self.removed.clear();
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+// modified<TAB>size<TAB>feature,feature,...<TAB>path
+fn parse_entry(line: &str) -> Option<(String, CacheEntry)> {
+    let mut parts = line.splitn(4, '\t');
+    let modified = parts.next()?.parse().ok()?;
+    let size = parts.next()?.parse().ok()?;
+    let features = parts
+        .next()?
+        .split(',')
+        .map(|f| f.parse().ok())
+        .collect::<Option<Vec<f32>>>()?;
+    let path = parts.next()?;
+    Some((
+        path.to_owned(),
+        CacheEntry {
+            modified: modified,
+            size: size,
+            features: features,
+        },
+    ))
+}
+
+// the same song can be reached through different paths (relative, symlinked, etc.)
+fn cache_key(path: &str) -> String {
+    std::fs::canonicalize(path)
+        .ok()
+        .and_then(|path| path.to_str().map(|s| s.to_owned()))
+        .unwrap_or_else(|| path.to_owned())
+}
+
+fn file_stamp(path: &str) -> Option<(u64, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    Some((modified, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a song file and a cache file which are deleted at the end of the test
+    struct TestFiles {
+        song: PathBuf,
+        cache: PathBuf,
+    }
+
+    impl TestFiles {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir();
+            let files = Self {
+                song: dir.join(format!("muss_test_{}.flac", name)),
+                cache: dir.join(format!("muss_test_{}_analysis.txt", name)),
+            };
+            std::fs::write(&files.song, b"not really a song").unwrap();
+            std::fs::remove_file(&files.cache).unwrap_or(());
+            files
+        }
+
+        fn song(&self) -> &str {
+            self.song.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TestFiles {
+        fn drop(&mut self) {
+            std::fs::remove_file(&self.song).unwrap_or(());
+            std::fs::remove_file(&self.cache).unwrap_or(());
+        }
+    }
+
+    #[test]
+    fn save_and_open() -> std::io::Result<()> {
+        let files = TestFiles::new("cache_save");
+        let mut cache = AnalysisCache::open(&files.cache)?;
+        assert!(cache.is_empty());
+        cache.insert(files.song(), vec![1.0, -0.5]);
+        assert!(cache.is_dirty());
+        cache.save()?;
+        assert!(!cache.is_dirty());
+        let mut cache = AnalysisCache::open(&files.cache)?;
+        assert_eq!(cache.get(files.song()), Some(&[1.0, -0.5][..]));
+        assert_eq!(cache.get("/not/a/song.flac"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn stale_entry() -> std::io::Result<()> {
+        let files = TestFiles::new("cache_stale");
+        let mut cache = AnalysisCache::empty(&files.cache);
+        cache.insert(files.song(), vec![1.0]);
+        std::fs::write(&files.song, b"a different song")?;
+        assert!(!cache.contains(files.song()));
+        assert!(cache.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn save_merges() -> std::io::Result<()> {
+        let files = TestFiles::new("cache_merge");
+        let other = TestFiles::new("cache_merge_other");
+        let mut first = AnalysisCache::open(&files.cache)?;
+        let mut second = AnalysisCache::open(&files.cache)?;
+        first.insert(files.song(), vec![2.0]);
+        first.save()?;
+        // saving the second cache doesn't lose the first cache's entry
+        second.insert(other.song(), vec![3.0]);
+        second.save()?;
+        let mut cache = AnalysisCache::open(&files.cache)?;
+        assert_eq!(cache.get(files.song()), Some(&[2.0][..]));
+        assert_eq!(cache.get(other.song()), Some(&[3.0][..]));
+        // but entries it removes stay removed
+        second.remove(files.song());
+        second.save()?;
+        let mut cache = AnalysisCache::open(&files.cache)?;
+        assert!(!cache.contains(files.song()));
+        assert!(cache.contains(other.song()));
+        Ok(())
+    }
+
+    #[test]
+    fn canonical_keys() -> std::io::Result<()> {
+        let files = TestFiles::new("cache_canonical");
+        let mut cache = AnalysisCache::empty(&files.cache);
+        let roundabout = files
+            .song
+            .parent()
+            .unwrap()
+            .join(".")
+            .join(files.song.file_name().unwrap());
+        cache.insert(roundabout.to_str().unwrap(), vec![4.0]);
+        assert_eq!(cache.get(files.song()), Some(&[4.0][..]));
+        cache.remove(files.song());
+        assert!(cache.is_empty());
+        Ok(())
+    }
+}
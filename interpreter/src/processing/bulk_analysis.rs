@@ -0,0 +1,328 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bliss_audio_symphonia::Song;
+
+use super::analysis_cache::AnalysisCache;
+use super::exclude::ExcludeRules;
+
+// file extensions which are worth trying to analyse
+const AUDIO_EXTENSIONS: [&str; 9] = [
+    "mp3", "wav", "ogg", "flac", "mp4", "m4a", "aac", "opus", "aiff",
+];
+
+// minimum time between writes of the analysis cache, so progress isn't lost if interrupted
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Progress of a bulk analysis run.
+#[derive(Debug, Clone, Default)]
+pub struct AnalysisProgress {
+    /// Songs found
+    pub total: usize,
+    /// Songs which were already in the analysis cache
+    pub cached: usize,
+    /// Songs analysed successfully
+    pub analysed: usize,
+    /// Songs which could not be analysed
+    pub failed: usize,
+    pub elapsed: Duration,
+}
+
+impl AnalysisProgress {
+    /// Songs which have been dealt with, one way or another.
+    pub fn done(&self) -> usize {
+        self.cached + self.analysed + self.failed
+    }
+
+    /// Estimated time until every song has been analysed, based on the analysis rate so far.
+    pub fn eta(&self) -> Option<Duration> {
+        let processed = self.analysed + self.failed;
+        if processed == 0 {
+            None
+        } else {
+            let remaining = self.total.saturating_sub(self.done()) as u32;
+            Some(self.elapsed / processed as u32 * remaining)
+        }
+    }
+}
+
+/// Something which happened during a bulk analysis run.
+#[derive(Debug, Clone)]
+pub enum AnalysisEvent {
+    /// Finished looking for songs
+    Found(AnalysisProgress),
+    Analysed {
+        path: PathBuf,
+        progress: AnalysisProgress,
+    },
+    Failed {
+        path: PathBuf,
+        msg: String,
+        progress: AnalysisProgress,
+    },
+}
+
+/// Analyses every song in some folders ahead of time, so that bliss sorters don't have to.
+///
+/// Results are stored in the same analysis cache the sorters use, and songs
+/// which are already in the cache (and unchanged) are skipped.
+#[derive(Debug, Clone)]
+pub struct BulkAnalyzer {
+    workers: usize,
+    cache_path: PathBuf,
+    excludes: Vec<String>,
+}
+
+impl std::default::Default for BulkAnalyzer {
+    fn default() -> Self {
+        Self {
+            workers: Self::default_workers(),
+            cache_path: AnalysisCache::default_path(),
+            excludes: Vec::new(),
+        }
+    }
+}
+
+impl BulkAnalyzer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Half of the available threads, since bliss uses another thread per song.
+    pub fn default_workers() -> usize {
+        std::thread::available_parallelism()
+            .map(|x| x.get() / 2)
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    pub fn with_cache_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.cache_path = path.as_ref().to_path_buf();
+        self
+    }
+
+    /// Skip files and folders matching these gitignore-style patterns.
+    pub fn with_excludes(mut self, patterns: Vec<String>) -> Self {
+        self.excludes = patterns;
+        self
+    }
+
+    /// Analyse all songs in the paths (files or folders), or in the music folders when there are no paths.
+    /// `on_event` is called from the calling thread as songs are analysed.
+    pub fn analyze<P: AsRef<Path>, F: FnMut(AnalysisEvent)>(
+        &self,
+        paths: &[P],
+        mut on_event: F,
+    ) -> std::io::Result<AnalysisProgress> {
+        let start = Instant::now();
+        let roots: Vec<PathBuf> = if paths.is_empty() {
+            crate::lang::utility::music_folders()
+        } else {
+            paths.iter().map(|p| p.as_ref().to_path_buf()).collect()
+        };
+        let mut excludes = ExcludeRules::for_folders(&roots, &self.excludes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        let mut cache = AnalysisCache::open(&self.cache_path)?;
+        let mut files = Vec::new();
+        for root in roots.iter() {
+            find_songs(root, &mut excludes, &mut files);
+        }
+        let mut progress = AnalysisProgress {
+            total: files.len(),
+            ..Default::default()
+        };
+        let mut queue: Vec<PathBuf> = files
+            .into_iter()
+            .filter(|path| match path.to_str() {
+                Some(path_str) if cache.contains(path_str) => {
+                    progress.cached += 1;
+                    false
+                }
+                _ => true,
+            })
+            .collect();
+        progress.elapsed = start.elapsed();
+        on_event(AnalysisEvent::Found(progress.clone()));
+
+        // workers take songs from the end
+        queue.reverse();
+        let queue = Arc::new(Mutex::new(queue));
+        let (tx, rx) = channel();
+        let workers: Vec<_> = (0..self.workers)
+            .map(|_| {
+                let queue = queue.clone();
+                let tx = tx.clone();
+                std::thread::spawn(move || loop {
+                    let path = match queue.lock().ok().and_then(|mut q| q.pop()) {
+                        Some(path) => path,
+                        None => break,
+                    };
+                    let result = Song::from_path(&path);
+                    if tx.send((path, result)).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+
+        // rates are measured from when analysis started, not including the search
+        let analysis_start = Instant::now();
+        let mut last_save = Instant::now();
+        for (path, result) in rx.iter() {
+            progress.elapsed = analysis_start.elapsed();
+            match result {
+                Ok(song) => {
+                    progress.analysed += 1;
+                    cache.insert(&path.to_string_lossy(), song.analysis.as_vec());
+                    on_event(AnalysisEvent::Analysed {
+                        path: path,
+                        progress: progress.clone(),
+                    });
+                }
+                Err(e) => {
+                    progress.failed += 1;
+                    on_event(AnalysisEvent::Failed {
+                        path: path,
+                        msg: e.to_string(),
+                        progress: progress.clone(),
+                    });
+                }
+            }
+            if last_save.elapsed() > SAVE_INTERVAL {
+                cache.save()?;
+                last_save = Instant::now();
+            }
+        }
+        for worker in workers {
+            worker.join().unwrap_or(());
+        }
+        cache.save()?;
+        progress.elapsed = start.elapsed();
+        Ok(progress)
+    }
+}
+
+// recursively find audio files, in the same order as files()
+fn find_songs(path: &Path, excludes: &mut ExcludeRules, found: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+        excludes.load_ignore_file(path);
+        let mut entries: Vec<PathBuf> = match path.read_dir() {
+            Ok(dir) => dir.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+            Err(_) => return,
+        };
+        entries.sort_by_cached_key(|p| p.to_string_lossy().to_lowercase());
+        for entry in entries {
+            if !excludes.is_excluded(&entry, entry.is_dir()) {
+                find_songs(&entry, excludes, found);
+            }
+        }
+    } else if path.is_file() && is_audio_file(path) {
+        found.push(path.to_path_buf());
+    }
+}
+
+fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_eta() {
+        let mut progress = AnalysisProgress {
+            total: 10,
+            cached: 2,
+            ..Default::default()
+        };
+        // nothing has been analysed yet, so there is no rate to go by
+        assert_eq!(progress.eta(), None);
+        progress.analysed = 3;
+        progress.failed = 1;
+        progress.elapsed = Duration::from_secs(8);
+        assert_eq!(progress.done(), 6);
+        assert_eq!(progress.eta(), Some(Duration::from_secs(8)));
+        progress.analysed = 7;
+        assert_eq!(progress.eta(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn analyze_folder() -> std::io::Result<()> {
+        let dir = std::env::temp_dir().join("muss_test_bulk_analysis");
+        std::fs::remove_dir_all(&dir).unwrap_or(());
+        std::fs::create_dir_all(dir.join("skip"))?;
+        let dir = dir.canonicalize()?;
+        for name in [
+            "cached.flac",
+            "broken.mp3",
+            "notes.txt",
+            "sample.wav",
+            "skip/song.flac",
+        ] {
+            std::fs::write(dir.join(name), b"not really a song")?;
+        }
+        let cache_path = dir.join("analysis.txt");
+        let mut cache = AnalysisCache::open(&cache_path)?;
+        cache.insert(dir.join("cached.flac").to_str().unwrap(), vec![1.0]);
+        cache.save()?;
+
+        let mut events = Vec::new();
+        let progress = BulkAnalyzer::new()
+            .with_workers(2)
+            .with_cache_path(&cache_path)
+            .with_excludes(vec!["skip/".to_owned(), "*.wav".to_owned()])
+            .analyze(&[&dir], |event| events.push(event))?;
+        assert_eq!(progress.total, 2);
+        assert_eq!(progress.cached, 1);
+        assert_eq!(progress.analysed, 0);
+        assert_eq!(progress.failed, 1);
+        assert_eq!(progress.eta(), Some(Duration::ZERO));
+        assert_eq!(events.len(), 2);
+        match &events[0] {
+            AnalysisEvent::Found(found) => assert_eq!((found.total, found.cached), (2, 1)),
+            e => panic!("Expected Found, got {:?}", e),
+        }
+        match &events[1] {
+            AnalysisEvent::Failed { path, .. } => assert_eq!(path, &dir.join("broken.mp3")),
+            e => panic!("Expected Failed, got {:?}", e),
+        }
+        // songs which were already analysed stay in the cache file
+        assert!(
+            AnalysisCache::open(&cache_path)?.contains(dir.join("cached.flac").to_str().unwrap())
+        );
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn analyze_empty_folder() -> std::io::Result<()> {
+        let dir = std::env::temp_dir().join("muss_test_bulk_analysis_empty");
+        std::fs::remove_dir_all(&dir).unwrap_or(());
+        std::fs::create_dir_all(&dir)?;
+        let mut found = None;
+        let progress = BulkAnalyzer::new()
+            .with_cache_path(dir.join("analysis.txt"))
+            .analyze(&[&dir], |event| {
+                if let AnalysisEvent::Found(progress) = event {
+                    found = Some(progress.total);
+                }
+            })?;
+        assert_eq!(found, Some(0));
+        assert_eq!(progress.done(), 0);
+        assert_eq!(progress.eta(), None);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use regex::Regex;
+
+/// Name of the file listing paths to exclude from a folder, in gitignore format.
+pub const IGNORE_FILENAME: &str = ".mussignore";
+
+#[derive(Debug, Clone)]
+struct ExcludeRule {
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl ExcludeRule {
+    fn parse(pattern: &str) -> Result<Option<Self>, String> {
+        let pattern = pattern.trim_end();
+        if pattern.is_empty() || pattern.starts_with('#') {
+            return Ok(None);
+        }
+        let (negated, pattern) = match pattern.strip_prefix('!') {
+            Some(p) => (true, p),
+            None => (false, pattern.strip_prefix('\\').unwrap_or(pattern)),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(p) => (true, p),
+            None => (false, pattern),
+        };
+        // patterns with a slash are relative to the folder, otherwise they match at any depth
+        let anchored = pattern.contains('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        let mut re = if anchored {
+            "^".to_owned()
+        } else {
+            "^(?:.*/)?".to_owned()
+        };
+        re.push_str(&glob_to_regex(pattern));
+        re.push('$');
+        Ok(Some(Self {
+            regex: Regex::new(&re)
+                .map_err(|e| format!("Invalid exclude pattern `{}`: {}", pattern, e))?,
+            negated: negated,
+            dir_only: dir_only,
+        }))
+    }
+}
+
+/// Gitignore-style exclusion rules for music folders.
+///
+/// Rules come from patterns given for a folder and from `.mussignore` files,
+/// and only apply to paths inside the folder they were given for.
+/// Later rules take precedence, so `!pattern` can re-include paths excluded by an earlier rule.
+#[derive(Debug, Clone, Default)]
+pub struct ExcludeRules {
+    rules: Vec<(PathBuf, ExcludeRule)>,
+    ignore_files: HashMap<PathBuf, Vec<ExcludeRule>>,
+}
+
+impl ExcludeRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rules where every pattern applies to each of the folders.
+    pub fn for_folders<P: AsRef<Path>, S: AsRef<str>>(
+        folders: &[P],
+        patterns: &[S],
+    ) -> Result<Self, String> {
+        let mut rules = Self::new();
+        for folder in folders {
+            for pattern in patterns {
+                rules.add_pattern(folder, pattern.as_ref())?;
+            }
+        }
+        Ok(rules)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty() && self.ignore_files.is_empty()
+    }
+
+    /// Add a pattern which applies to paths inside `folder`.
+    pub fn add_pattern<P: AsRef<Path>>(&mut self, folder: P, pattern: &str) -> Result<(), String> {
+        if let Some(rule) = ExcludeRule::parse(pattern)? {
+            self.rules.push((folder.as_ref().to_path_buf(), rule));
+        }
+        Ok(())
+    }
+
+    /// (Re-)load the `.mussignore` file in a folder, if there is one.
+    /// Invalid patterns in the file are skipped.
+    pub fn load_ignore_file<P: AsRef<Path>>(&mut self, folder: P) {
+        let folder = folder.as_ref();
+        match std::fs::read_to_string(folder.join(IGNORE_FILENAME)) {
+            Ok(data) => {
+                let rules = data
+                    .lines()
+                    .filter_map(|line| ExcludeRule::parse(line).ok().flatten())
+                    .collect();
+                self.ignore_files.insert(folder.to_path_buf(), rules);
+            }
+            Err(_) => {
+                self.ignore_files.remove(folder);
+            }
+        }
+    }
+
+    /// Whether a path should be skipped.
+    /// Rules in `.mussignore` files take precedence over rules given for a folder,
+    /// and rules in deeper folders take precedence over those above them.
+    pub fn is_excluded<P: AsRef<Path>>(&self, path: P, is_dir: bool) -> bool {
+        let path = path.as_ref();
+        if path
+            .file_name()
+            .map(|name| name == IGNORE_FILENAME)
+            .unwrap_or(false)
+        {
+            return true;
+        }
+        let mut excluded = false;
+        for (folder, rule) in self.rules.iter() {
+            if let Some(result) = Self::check_rule(rule, folder, path, is_dir) {
+                excluded = result;
+            }
+        }
+        let mut folders: Vec<&PathBuf> = self
+            .ignore_files
+            .keys()
+            .filter(|folder| path.starts_with(folder))
+            .collect();
+        folders.sort_by_key(|folder| folder.components().count());
+        for folder in folders {
+            for rule in self.ignore_files[folder].iter() {
+                if let Some(result) = Self::check_rule(rule, folder, path, is_dir) {
+                    excluded = result;
+                }
+            }
+        }
+        excluded
+    }
+
+    /// Whether a path or any folder containing it should be skipped.
+    pub fn is_path_excluded<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = path.as_ref();
+        self.is_excluded(path, path.is_dir())
+            || path
+                .ancestors()
+                .skip(1)
+                .any(|folder| self.is_excluded(folder, true))
+    }
+
+    #[inline]
+    fn check_rule(rule: &ExcludeRule, folder: &Path, path: &Path, is_dir: bool) -> Option<bool> {
+        if rule.dir_only && !is_dir {
+            return None;
+        }
+        let relative = path.strip_prefix(folder).ok()?;
+        let relative: Vec<&str> = relative
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(s) => s.to_str(),
+                _ => None,
+            })
+            .collect();
+        if relative.is_empty() {
+            // the folder itself
+            None
+        } else if rule.regex.is_match(&relative.join("/")) {
+            Some(!rule.negated)
+        } else {
+            None
+        }
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut re = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                if chars.get(i + 2) == Some(&'/') {
+                    // **/ matches zero or more folders
+                    re.push_str("(?:.*/)?");
+                    i += 2;
+                } else {
+                    re.push_str(".*");
+                    i += 1;
+                }
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            '[' => match chars[i + 1..].iter().position(|c| *c == ']') {
+                Some(len) if len > 0 => {
+                    re.push('[');
+                    for (j, c) in chars[i + 1..i + 1 + len].iter().enumerate() {
+                        match c {
+                            '!' if j == 0 => re.push('^'),
+                            '\\' | '[' | '&' | '~' | '^' => {
+                                re.push('\\');
+                                re.push(*c);
+                            }
+                            c => re.push(*c),
+                        }
+                    }
+                    re.push(']');
+                    i += len + 1;
+                }
+                _ => re.push_str(r"\["),
+            },
+            '\\' if i + 1 < chars.len() => {
+                re.push_str(&regex::escape(chars[i + 1].encode_utf8(&mut [0; 4])));
+                i += 1;
+            }
+            c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+        i += 1;
+    }
+    re
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExcludeRules;
+
+    #[test]
+    fn exclude_patterns() {
+        let mut rules = ExcludeRules::new();
+        rules.add_pattern("/music", "audiobooks/").unwrap();
+        rules.add_pattern("/music", "*.wav").unwrap();
+        rules.add_pattern("/music", "!keep.wav").unwrap();
+        rules.add_pattern("/music", "/samples").unwrap();
+        rules.add_pattern("/music", "live/**/bootleg*").unwrap();
+        assert!(rules.is_excluded("/music/audiobooks", true));
+        assert!(!rules.is_excluded("/music/audiobooks", false));
+        assert!(rules.is_excluded("/music/artist/song.wav", false));
+        assert!(!rules.is_excluded("/music/artist/keep.wav", false));
+        assert!(rules.is_excluded("/music/samples", true));
+        assert!(!rules.is_excluded("/music/artist/samples", true));
+        assert!(rules.is_excluded("/music/live/2020/june/bootleg 1.flac", false));
+        assert!(rules.is_excluded("/music/live/bootleg.flac", false));
+        assert!(!rules.is_excluded("/music/artist/song.flac", false));
+        assert!(!rules.is_excluded("/other/song.wav", false));
+        assert!(rules.is_excluded("/music/.mussignore", false));
+        assert!(rules.is_path_excluded("/music/audiobooks/book/chapter 1.mp3"));
+    }
+}
@@ -6,16 +6,19 @@ use std::io::Read;
 
 use regex::Regex;
 
+use super::exclude::ExcludeRules;
 use crate::lang::{RuntimeMsg, TypePrimitive, GeneratorOp};
 use crate::Item;
 
-const DEFAULT_REGEX: &str = r"/(?P<artist>[^/]+)/(?P<album>[^/]+)/(?:(?:(?P<disc>\d+)\s+)?(?P<track>\d+)\.?\s+)?(?P<title>[^/]+)\.(?P<format>(?:mp3)|(?:wav)|(?:ogg)|(?:flac)|(?:mp4)|(?:aac))$";
+pub(crate) const DEFAULT_REGEX: &str = r"/(?P<artist>[^/]+)/(?P<album>[^/]+)/(?:(?:(?P<disc>\d+)\s+)?(?P<track>\d+)\.?\s+)?(?P<title>[^/]+)\.(?P<format>(?:mp3)|(?:wav)|(?:ogg)|(?:flac)|(?:mp4)|(?:aac))$";
 
 const DEFAULT_VEC_CACHE_SIZE: usize = 4;
 
 #[derive(Debug)]
 pub struct FileIter {
     root: PathBuf,
+    next_roots: Vec<PathBuf>,
+    excludes: ExcludeRules,
     pattern: Option<Regex>,
     tags_pattern: Regex,
     recursive: bool,
@@ -72,7 +75,11 @@ impl Display for FileIter {
         write!(
             f,
             "root=`{}`, pattern={}, recursive={}",
-            self.root.to_str().unwrap_or(""),
+            std::iter::once(&self.root)
+                .chain(self.next_roots.iter())
+                .map(|root| root.to_str().unwrap_or(""))
+                .collect::<Vec<_>>()
+                .join("`, `"),
             self.pattern
                 .as_ref()
                 .map(|re| re.to_string())
@@ -88,11 +95,14 @@ impl FileIter {
         pattern: Option<&str>,
         recurse: bool,
     ) -> Result<Self, String> {
-        let root_path = match root {
-            None => crate::lang::utility::music_folder(),
-            Some(p) => p.as_ref().to_path_buf(),
+        let mut next_roots = match root {
+            None => crate::lang::utility::music_folders(),
+            Some(p) => vec![p.as_ref().to_path_buf()],
         };
+        let root_path = next_roots.remove(0);
+        let mut excludes = ExcludeRules::new();
         let dir_vec = if root_path.is_dir() {
+            excludes.load_ignore_file(&root_path);
             let mut vec = Vec::with_capacity(DEFAULT_VEC_CACHE_SIZE);
             vec.push(
                 root_path
@@ -113,6 +123,8 @@ impl FileIter {
             Regex::new(DEFAULT_REGEX).map_err(|e| format!("Regex compile error: {}", e))?;
         Ok(Self {
             root: root_path,
+            next_roots: next_roots,
+            excludes: excludes,
             pattern: pattern_re,
             tags_pattern: tags_re,
             recursive: recurse,
@@ -122,12 +134,17 @@ impl FileIter {
     }
 
     pub fn common_defaults(recurse: bool) -> Self {
-        let root_path = crate::lang::utility::music_folder();
+        let mut next_roots = crate::lang::utility::music_folders();
+        let root_path = next_roots.remove(0);
         let read_dir = root_path.read_dir().unwrap();
         let mut dir_vec = Vec::with_capacity(DEFAULT_VEC_CACHE_SIZE);
         dir_vec.push(read_dir.into());
+        let mut excludes = ExcludeRules::new();
+        excludes.load_ignore_file(&root_path);
         Self {
             root: root_path,
+            next_roots: next_roots,
+            excludes: excludes,
             pattern: None,
             tags_pattern: Regex::new(DEFAULT_REGEX).unwrap(),
             recursive: recurse,
@@ -136,6 +153,33 @@ impl FileIter {
         }
     }
 
+    /// Also iterate over these folders (or files), after the current ones.
+    pub fn with_roots<P: AsRef<Path>, I: IntoIterator<Item = P>>(mut self, roots: I) -> Self {
+        self.next_roots
+            .extend(roots.into_iter().map(|root| root.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Skip files and folders matching these rules, in addition to those in `.mussignore` files.
+    pub fn with_excludes(mut self, mut excludes: ExcludeRules) -> Self {
+        // the root's .mussignore was loaded by the constructor
+        excludes.load_ignore_file(&self.root);
+        self.excludes = excludes;
+        self
+    }
+
+    // move on to the next root, if there are any left
+    fn next_root(&mut self) -> bool {
+        self.dir_iters.clear();
+        if self.next_roots.is_empty() {
+            self.is_complete = true;
+            false
+        } else {
+            self.root = self.next_roots.remove(0);
+            true
+        }
+    }
+
     fn build_item<P: AsRef<Path>>(&self, filepath: P) -> Option<Item> {
         let path = filepath.as_ref();
         let path_str = path.to_str()?;
@@ -229,17 +273,7 @@ impl FileIter {
         captures: Option<regex::Captures>,
         capture_names: regex::CaptureNames,
     ) {
-        // populates fields from named capture groups
-        if let Some(captures) = captures {
-            for name in capture_names.flatten() {
-                if item.field(name).is_some() {
-                    // do nothing
-                } else if let Some(value) = captures.name(name).map(|m| m.as_str().to_string()) {
-                    item.set_field(name, TypePrimitive::parse(value));
-                }
-            }
-        }
-        item.set_field("filename", format!("file://{}", path_str).into());
+        populate_item_from_path(item, path_str, captures, capture_names)
     }
 
     fn only_once(&mut self) -> Result<Item, String> {
@@ -269,6 +303,27 @@ impl FileIter {
     }*/
 }
 
+/// Fill in the fields of a file's item which its tags didn't have from named capture groups,
+/// and set its filename.
+pub(crate) fn populate_item_from_path(
+    item: &mut Item,
+    path_str: &str,
+    captures: Option<regex::Captures>,
+    capture_names: regex::CaptureNames,
+) {
+    // populates fields from named capture groups
+    if let Some(captures) = captures {
+        for name in capture_names.flatten() {
+            if item.field(name).is_some() {
+                // do nothing
+            } else if let Some(value) = captures.name(name).map(|m| m.as_str().to_string()) {
+                item.set_field(name, TypePrimitive::parse(value));
+            }
+        }
+    }
+    item.set_field("filename", format!("file://{}", path_str).into());
+}
+
 impl Iterator for FileIter {
     type Item = Result<Item, String>;
 
@@ -278,16 +333,21 @@ impl Iterator for FileIter {
             None
         } else if self.dir_iters.is_empty() {
             if self.root.is_file() {
-                self.is_complete = true;
-                self.build_item(&self.root).map(Ok)
+                let item = self.build_item(&self.root);
+                self.next_root();
+                match item {
+                    Some(item) => Some(Ok(item)),
+                    None => self.next(),
+                }
             } else {
                 self.dir_iters.push(match self.root.read_dir() {
                     Ok(x) => x.into(),
                     Err(e) => {
-                        self.is_complete = true;
+                        self.next_root();
                         return Some(Err(format!("Directory read error: {}", e)));
                     }
                 });
+                self.excludes.load_ignore_file(&self.root);
                 self.next()
             }
         } else {
@@ -296,10 +356,15 @@ impl Iterator for FileIter {
                 'inner: while let Some(path_result) = dir_iter.next() {
                     match path_result {
                         Ok(dir_entry) => {
-                            if dir_entry.path().is_dir() {
+                            let path = dir_entry.path();
+                            let is_dir = path.is_dir();
+                            if self.excludes.is_excluded(&path, is_dir) {
+                                continue 'inner;
+                            }
+                            if is_dir {
                                 if self.recursive {
                                     self.dir_iters.push(dir_iter);
-                                    self.dir_iters.push(match dir_entry.path().read_dir() {
+                                    self.dir_iters.push(match path.read_dir() {
                                         Ok(x) => x.into(),
                                         Err(e) => {
                                             return Some(Err(format!(
@@ -308,10 +373,11 @@ impl Iterator for FileIter {
                                             )))
                                         }
                                     });
+                                    self.excludes.load_ignore_file(&path);
                                     //return self.next();
                                     break 'inner;
                                 }
-                            } else if let Some(item) = self.build_item(dir_entry.path()) {
+                            } else if let Some(item) = self.build_item(path) {
                                 self.dir_iters.push(dir_iter);
                                 return Some(Ok(item));
                             }
@@ -323,8 +389,11 @@ impl Iterator for FileIter {
                     }
                 }
             }
-            self.is_complete = true;
-            None
+            if self.next_root() {
+                self.next()
+            } else {
+                None
+            }
         }
     }
 }
@@ -337,6 +406,33 @@ pub trait FilesystemQuerier: Debug + Send {
         recursive: bool,
     ) -> Result<FileIter, RuntimeMsg>;
 
+    /// Iterate over several folders, skipping paths which match gitignore-style exclude patterns.
+    fn raw_roots(
+        &mut self,
+        folders: &[&str],
+        excludes: &[&str],
+        pattern: Option<&str>,
+        recursive: bool,
+    ) -> Result<FileIter, RuntimeMsg> {
+        let mut roots = Vec::with_capacity(folders.len());
+        for folder in folders {
+            if let Some(root) = self.expand(Some(folder))? {
+                roots.push(PathBuf::from(root));
+            }
+        }
+        if roots.is_empty() {
+            let excludes = ExcludeRules::for_folders(&crate::lang::utility::music_folders(), excludes)
+                .map_err(RuntimeMsg)?;
+            Ok(self.raw(None, pattern, recursive)?.with_excludes(excludes))
+        } else {
+            let excludes = ExcludeRules::for_folders(&roots, excludes).map_err(RuntimeMsg)?;
+            Ok(self
+                .raw(Some(folders[0]), pattern, recursive)?
+                .with_roots(roots.drain(1..))
+                .with_excludes(excludes))
+        }
+    }
+
     fn single(&mut self, path: &str, pattern: Option<&str>) -> Result<Item, RuntimeMsg>;
 
     fn read_file(&mut self, path: &str) -> Result<GeneratorOp, RuntimeMsg>;
@@ -0,0 +1,266 @@
+use crate::lang::vocabulary::filters::utility::sanitise_string;
+use crate::lang::{RuntimeMsg, TypePrimitive};
+use crate::Item;
+
+use super::music_analysis::{MusicAnalyzer, MusicAnalyzerDistance};
+use super::similarity::FEATURE_GROUPS;
+
+// how much each difference adds to the distance between songs
+const ARTIST_WEIGHT: f64 = 1.0;
+const ALBUM_ARTIST_WEIGHT: f64 = 0.5;
+const GENRE_WEIGHT: f64 = 1.0;
+const YEAR_WEIGHT: f64 = 0.5;
+const BPM_WEIGHT: f64 = 1.0;
+const COMPOSER_WEIGHT: f64 = 0.25;
+
+// differences at which songs are considered completely dissimilar
+const YEAR_RANGE: f64 = 20.0;
+const BPM_RANGE: f64 = 60.0;
+
+/// Music analyzer which compares songs by their tags instead of their audio.
+///
+/// Songs are similar when they share an artist, album artist, genre or composer,
+/// and when they were released around the same year or have a similar BPM tag.
+/// This is much less accurate than audio analysis, but it's fast and needs no extra dependencies.
+/// Missing tags count as half different, so songs with few tags end up in the middle.
+#[derive(Debug, Default, Clone)]
+pub struct MetadataAnalyzer {}
+
+impl MetadataAnalyzer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn tempo_distance(from: &Item, to: &Item) -> f64 {
+        range_distance(
+            number_field(from, "bpm"),
+            number_field(to, "bpm"),
+            BPM_RANGE,
+        )
+    }
+
+    // everything except tempo
+    fn tag_distance(from: &Item, to: &Item) -> f64 {
+        list_distance(from, to, "artist") * ARTIST_WEIGHT
+            + list_distance(from, to, "albumartist") * ALBUM_ARTIST_WEIGHT
+            + list_distance(from, to, "genre") * GENRE_WEIGHT
+            + list_distance(from, to, "composer") * COMPOSER_WEIGHT
+            + range_distance(year(from), year(to), YEAR_RANGE) * YEAR_WEIGHT
+    }
+
+    fn metadata_distance(from: &Item, to: &Item) -> f64 {
+        Self::tag_distance(from, to) + Self::tempo_distance(from, to) * BPM_WEIGHT
+    }
+}
+
+impl MusicAnalyzer for MetadataAnalyzer {
+    fn prepare_distance(&mut self, _from: &Item, _to: &Item) -> Result<(), RuntimeMsg> {
+        Ok(())
+    }
+
+    fn prepare_item(&mut self, _item: &Item) -> Result<(), RuntimeMsg> {
+        Ok(())
+    }
+
+    fn get_distance(&mut self, from: &Item, to: &Item) -> Result<f64, RuntimeMsg> {
+        Ok(Self::metadata_distance(from, to))
+    }
+
+    fn get_custom_distance(
+        &mut self,
+        from: &Item,
+        to: &Item,
+        compare: MusicAnalyzerDistance,
+    ) -> Result<f64, RuntimeMsg> {
+        match compare {
+            MusicAnalyzerDistance::Tempo => Ok(Self::tempo_distance(from, to)),
+            MusicAnalyzerDistance::Weighted(options) => {
+                // tempo is the only feature with a tag equivalent, so the other weights apply to the rest of the tags
+                let tempo_weight = options.weight("tempo");
+                let other_weight = (options.weights.iter().sum::<f64>() - tempo_weight)
+                    / (FEATURE_GROUPS.len() - 1) as f64;
+                Ok(Self::tempo_distance(from, to) * BPM_WEIGHT * tempo_weight
+                    + Self::tag_distance(from, to) * other_weight)
+            }
+            // tags say nothing about the rest of the audio
+            _ => Ok(Self::metadata_distance(from, to)),
+        }
+    }
+
+    fn get_features(&mut self, _item: &Item) -> Result<Vec<(&'static str, f64)>, RuntimeMsg> {
+        Ok(Vec::new())
+    }
+
+    fn clear_cache(&mut self) -> Result<(), RuntimeMsg> {
+        Ok(())
+    }
+}
+
+// 0 when any value is shared (e.g. one of several artists), 1 when none are, 0.5 when unknown
+fn list_distance(from: &Item, to: &Item, field: &str) -> f64 {
+    let values = |item: &Item| -> Option<Vec<String>> {
+        match item.field(field) {
+            Some(TypePrimitive::String(s)) => {
+                let values: Vec<String> = s
+                    .split([',', ';', '/'])
+                    .map(sanitise_string)
+                    .filter(|s| !s.is_empty())
+                    .collect();
+                if values.is_empty() {
+                    None
+                } else {
+                    Some(values)
+                }
+            }
+            _ => None,
+        }
+    };
+    match (values(from), values(to)) {
+        (Some(a), Some(b)) => {
+            if a.iter().any(|x| b.contains(x)) {
+                0.0
+            } else {
+                1.0
+            }
+        }
+        _ => 0.5,
+    }
+}
+
+// 0 for the same value, up to 1 for values `range` or more apart, 0.5 when unknown
+fn range_distance(from: Option<f64>, to: Option<f64>, range: f64) -> f64 {
+    match (from, to) {
+        (Some(a), Some(b)) => ((a - b).abs() / range).min(1.0),
+        _ => 0.5,
+    }
+}
+
+fn number_field(item: &Item, field: &str) -> Option<f64> {
+    match item.field(field)? {
+        TypePrimitive::UInt(x) => Some(*x as f64),
+        TypePrimitive::Int(x) => Some(*x as f64),
+        TypePrimitive::Float(x) => Some(*x),
+        TypePrimitive::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+// year from the database, or the start of a date tag like 2016-11-18
+fn year(item: &Item) -> Option<f64> {
+    number_field(item, "year")
+        .filter(|year| *year > 0.0)
+        .or_else(|| match item.field("date")? {
+            TypePrimitive::String(date) => date.get(..4)?.parse().ok(),
+            _ => number_field(item, "date"),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::advanced::SimilarityOptions;
+
+    fn song(fields: &[(&str, TypePrimitive)]) -> Item {
+        let mut item = Item::new();
+        for (field, value) in fields {
+            item.set_field(field, value.clone());
+        }
+        item
+    }
+
+    #[test]
+    fn list_distance_shared_values() {
+        let duo = song(&[(
+            "artist",
+            TypePrimitive::String("Bruno Mars; Anderson .Paak".into()),
+        )]);
+        let solo = song(&[("artist", TypePrimitive::String("bruno mars".into()))]);
+        let other = song(&[("artist", TypePrimitive::String("Thundercat".into()))]);
+        let unknown = song(&[]);
+        assert_eq!(list_distance(&duo, &solo, "artist"), 0.0);
+        assert_eq!(list_distance(&duo, &other, "artist"), 1.0);
+        assert_eq!(list_distance(&duo, &unknown, "artist"), 0.5);
+        assert_eq!(list_distance(&unknown, &unknown, "artist"), 0.5);
+    }
+
+    #[test]
+    fn range_distance_scales_and_caps() {
+        assert_eq!(range_distance(Some(2000.0), Some(2010.0), YEAR_RANGE), 0.5);
+        assert_eq!(range_distance(Some(2010.0), Some(2000.0), YEAR_RANGE), 0.5);
+        assert_eq!(range_distance(Some(1960.0), Some(2020.0), YEAR_RANGE), 1.0);
+        assert_eq!(range_distance(Some(120.0), None, BPM_RANGE), 0.5);
+    }
+
+    #[test]
+    fn year_from_year_or_date() {
+        let tagged = song(&[("year", TypePrimitive::UInt(2016))]);
+        let dated = song(&[
+            ("year", TypePrimitive::UInt(0)),
+            ("date", TypePrimitive::String("2016-11-18".into())),
+        ]);
+        let numbered = song(&[("date", TypePrimitive::Int(2016))]);
+        assert_eq!(year(&tagged), Some(2016.0));
+        assert_eq!(year(&dated), Some(2016.0));
+        assert_eq!(year(&numbered), Some(2016.0));
+        assert_eq!(year(&song(&[])), None);
+    }
+
+    #[test]
+    fn tempo_distance_from_bpm() -> Result<(), RuntimeMsg> {
+        let slow = song(&[("bpm", TypePrimitive::UInt(90))]);
+        let fast = song(&[("bpm", TypePrimitive::String("120".into()))]);
+        let unknown = song(&[]);
+        let mut analyzer = MetadataAnalyzer::new();
+        assert_eq!(
+            analyzer.get_custom_distance(&slow, &fast, MusicAnalyzerDistance::Tempo)?,
+            0.5
+        );
+        assert_eq!(
+            analyzer.get_custom_distance(&slow, &slow, MusicAnalyzerDistance::Tempo)?,
+            0.0
+        );
+        assert_eq!(
+            analyzer.get_custom_distance(&slow, &unknown, MusicAnalyzerDistance::Tempo)?,
+            0.5
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn weighted_distance() -> Result<(), RuntimeMsg> {
+        let a = song(&[
+            ("artist", TypePrimitive::String("Bruno Mars".into())),
+            ("bpm", TypePrimitive::UInt(100)),
+        ]);
+        let b = song(&[
+            ("artist", TypePrimitive::String("Thundercat".into())),
+            ("bpm", TypePrimitive::UInt(130)),
+        ]);
+        let mut analyzer = MetadataAnalyzer::new();
+        // equal weights are the same as the default distance
+        let even = SimilarityOptions::default();
+        assert_eq!(
+            analyzer.get_custom_distance(&a, &b, MusicAnalyzerDistance::Weighted(even))?,
+            analyzer.get_distance(&a, &b)?
+        );
+        // only tempo
+        let mut weights = [0.0; 4];
+        weights[SimilarityOptions::group_index("tempo").unwrap()] = 1.0;
+        let tempo = SimilarityOptions {
+            weights: weights,
+            ..Default::default()
+        };
+        assert_eq!(
+            analyzer.get_custom_distance(&a, &b, MusicAnalyzerDistance::Weighted(tempo))?,
+            0.5 * BPM_WEIGHT
+        );
+        // everything except tempo
+        let mut tags = SimilarityOptions::default();
+        tags.weights[SimilarityOptions::group_index("tempo").unwrap()] = 0.0;
+        assert_eq!(
+            analyzer.get_custom_distance(&a, &b, MusicAnalyzerDistance::Weighted(tags))?,
+            MetadataAnalyzer::tag_distance(&a, &b)
+        );
+        Ok(())
+    }
+}
@@ -1,11 +1,23 @@
+#[cfg(feature = "advanced")]
+mod analysis_cache;
+#[cfg(all(feature = "advanced", feature = "bliss-audio-symphonia"))]
+mod bulk_analysis;
+mod exclude;
 mod filesystem;
 #[cfg(feature = "mpd")]
 mod mpd;
 #[cfg(feature = "advanced")]
+mod metadata_analysis;
+#[cfg(feature = "advanced")]
 mod music_analysis;
+// feature group names are needed to parse similarity options, even without analysis
+#[cfg_attr(not(feature = "advanced"), allow(dead_code))]
+mod similarity;
 mod sql;
 mod variables;
 
+pub(crate) use similarity::{feature_group_index, FEATURE_GROUP_NAMES};
+
 //pub type OpGetter = dyn FnMut() -> crate::lang::PseudoOp;
 
 pub mod database {
@@ -16,16 +28,28 @@ pub mod database {
     pub use super::sql::{SQLiteExecutor};
     #[cfg(feature = "fakesql")]
     pub use super::sql::{SQLiteTranspileExecutor};
+    #[cfg(all(feature = "fakesql", feature = "music_library"))]
+    pub use super::sql::{LibraryIndex, SharedLibraryIndex};
     #[cfg(all(not(feature = "fakesql"), not(feature = "sql")))]
     pub use super::sql::{SQLErrExecutor};
 }
 
 pub mod general {
+    pub use super::exclude::{ExcludeRules, IGNORE_FILENAME};
     pub use super::filesystem::{FileIter, FilesystemExecutor, FilesystemQuerier};
-    pub use super::variables::{OpStorage, Type, VariableStorer};
+    pub use super::variables::{FileOpStorage, OpStorage, Type, VariableStorer};
 }
 
 #[cfg(feature = "advanced")]
 pub mod advanced {
-    pub use super::music_analysis::{DefaultAnalyzer, MusicAnalyzer, MusicAnalyzerDistance};
+    pub use super::analysis_cache::AnalysisCache;
+    #[cfg(feature = "bliss-audio-symphonia")]
+    pub use super::bulk_analysis::{AnalysisEvent, AnalysisProgress, BulkAnalyzer};
+    pub use super::metadata_analysis::MetadataAnalyzer;
+    pub use super::music_analysis::{
+        DefaultAnalyzer, MusicAnalyzer, MusicAnalyzerDistance, FEATURE_NAMES,
+    };
+    pub use super::similarity::{
+        DistanceMetric, SimilarityOptions, SimilarityPresets, FEATURE_GROUPS,
+    };
 }
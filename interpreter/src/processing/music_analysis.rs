@@ -3,11 +3,15 @@ use core::fmt::Debug;
 use std::collections::{HashMap, HashSet};
 #[cfg(feature = "bliss-audio-symphonia")]
 use std::sync::mpsc::{channel, Receiver, Sender};
+#[cfg(feature = "bliss-audio-symphonia")]
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "bliss-audio-symphonia")]
 use crate::lang::TypePrimitive;
 #[cfg(feature = "bliss-audio-symphonia")]
-use bliss_audio_symphonia::{BlissError, Song, AnalysisIndex};
+use bliss_audio_symphonia::{Analysis, BlissError, Song, AnalysisIndex};
+#[cfg(feature = "bliss-audio-symphonia")]
+use super::analysis_cache::AnalysisCache;
 
 // assumed processor threads
 const DEFAULT_PARALLELISM: usize = 2;
@@ -18,17 +22,50 @@ const MAX_SONG_CACHE_SIZE: usize = 10000;
 // maximum length of distance cache (takes up significantly less memory than songs)
 const MAX_DISTANCE_CACHE_SIZE: usize = MAX_SONG_CACHE_SIZE * MAX_SONG_CACHE_SIZE;
 
+// minimum time between writes of the on-disk analysis cache
+#[cfg(feature = "bliss-audio-symphonia")]
+const ANALYSIS_CACHE_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
 use crate::lang::RuntimeMsg;
 use crate::Item;
+#[cfg(feature = "bliss-audio-symphonia")]
+use super::similarity::DistanceMetric;
+use super::similarity::SimilarityOptions;
 
 const PATH_FIELD: &str = "filename";
 
+/// Item field names for each of the audio features, in bliss analysis order.
+pub const FEATURE_NAMES: [&str; 20] = [
+    "tempo",
+    "zcr",
+    "spectral_centroid",
+    "spectral_centroid_deviation",
+    "spectral_rolloff",
+    "spectral_rolloff_deviation",
+    "spectral_flatness",
+    "spectral_flatness_deviation",
+    "loudness",
+    "loudness_deviation",
+    "chroma1",
+    "chroma2",
+    "chroma3",
+    "chroma4",
+    "chroma5",
+    "chroma6",
+    "chroma7",
+    "chroma8",
+    "chroma9",
+    "chroma10",
+];
+
 #[derive(Debug, Clone)]
 pub enum MusicAnalyzerDistance {
     Tempo,
     Spectrum,
     Loudness,
     Chroma,
+    /// Weighted combination of all features
+    Weighted(SimilarityOptions),
 }
 
 pub trait MusicAnalyzer: Debug + Send {
@@ -40,6 +77,9 @@ pub trait MusicAnalyzer: Debug + Send {
 
     fn get_custom_distance(&mut self, from: &Item, to: &Item, compare: MusicAnalyzerDistance) -> Result<f64, RuntimeMsg>;
 
+    /// Audio features of the item, named like `FEATURE_NAMES`
+    fn get_features(&mut self, item: &Item) -> Result<Vec<(&'static str, f64)>, RuntimeMsg>;
+
     fn clear_cache(&mut self) -> Result<(), RuntimeMsg>;
 }
 
@@ -48,6 +88,8 @@ pub trait MusicAnalyzer: Debug + Send {
 pub struct DefaultAnalyzer {
     requests: Sender<RequestType>,
     responses: Receiver<ResponseType>,
+    // loaded when first needed for Mahalanobis distance
+    inverse_covariance: Option<Option<Vec<Vec<f64>>>>,
 }
 
 #[cfg(feature = "bliss-audio-symphonia")]
@@ -62,6 +104,7 @@ impl std::default::Default for DefaultAnalyzer {
         Self {
             requests: req_tx,
             responses: resp_rx,
+            inverse_covariance: None,
         }
     }
 }
@@ -115,6 +158,25 @@ impl DefaultAnalyzer {
             .map_err(|e| RuntimeMsg(format!("Channel send error: {}", e)))
     }
 
+    // how features vary across every analysed song
+    fn inverse_covariance(&mut self) -> Option<&[Vec<f64>]> {
+        if self.inverse_covariance.is_none() {
+            let samples: Vec<Vec<f64>> = AnalysisCache::open(AnalysisCache::default_path())
+                .map(|cache| {
+                    cache
+                        .all_features()
+                        .filter(|features| features.len() == bliss_audio_symphonia::NUMBER_FEATURES)
+                        .map(|features| features.iter().map(|x| *x as f64).collect())
+                        .collect()
+                })
+                .unwrap_or_default();
+            self.inverse_covariance = Some(super::similarity::inverse_covariance(
+                samples.iter().map(|x| x.as_slice()),
+            ));
+        }
+        self.inverse_covariance.as_ref().and_then(|x| x.as_deref())
+    }
+
     fn bliss_song_to_array(song: &Song) -> [f64; bliss_audio_symphonia::NUMBER_FEATURES] {
         let analysis = &song.analysis;
         [
@@ -217,38 +279,13 @@ impl MusicAnalyzer for DefaultAnalyzer {
         if to_song.is_some() && from_song.is_some() {
             let to_arr = Self::bliss_song_to_array(&to_song.unwrap());
             let from_arr = Self::bliss_song_to_array(&from_song.unwrap());
-            Ok(match compare {
-                MusicAnalyzerDistance::Tempo => (
-                    (to_arr[AnalysisIndex::Tempo as usize] - from_arr[AnalysisIndex::Tempo as usize]).powi(2)
-                    + (to_arr[AnalysisIndex::Zcr as usize] - from_arr[AnalysisIndex::Zcr as usize]).powi(2)
-                ).sqrt(),
-                MusicAnalyzerDistance::Spectrum => (
-                    (to_arr[AnalysisIndex::MeanSpectralCentroid as usize] - from_arr[AnalysisIndex::MeanSpectralCentroid as usize]).powi(2)
-                    + (to_arr[AnalysisIndex::StdDeviationSpectralCentroid as usize] - from_arr[AnalysisIndex::StdDeviationSpectralCentroid as usize]).powi(2)
-                    + (to_arr[AnalysisIndex::MeanSpectralRolloff as usize] - from_arr[AnalysisIndex::MeanSpectralRolloff as usize]).powi(2)
-                    + (to_arr[AnalysisIndex::StdDeviationSpectralRolloff as usize] - from_arr[AnalysisIndex::StdDeviationSpectralRolloff as usize]).powi(2)
-                    + (to_arr[AnalysisIndex::MeanSpectralFlatness as usize] - from_arr[AnalysisIndex::MeanSpectralFlatness as usize]).powi(2)
-                    + (to_arr[AnalysisIndex::StdDeviationSpectralFlatness as usize] - from_arr[AnalysisIndex::StdDeviationSpectralFlatness as usize]).powi(2)
-                ).sqrt(),
-                MusicAnalyzerDistance::Loudness => {
-                    let mean_delta = to_arr[AnalysisIndex::MeanLoudness as usize] - from_arr[AnalysisIndex::MeanLoudness as usize];
-                    let deviation_delta = to_arr[AnalysisIndex::StdDeviationLoudness as usize] - from_arr[AnalysisIndex::StdDeviationLoudness as usize];
-
-                    (mean_delta.powi(2) + deviation_delta.powi(2)).sqrt()
-                },
-                MusicAnalyzerDistance::Chroma => (
-                    (to_arr[AnalysisIndex::Chroma1 as usize] - from_arr[AnalysisIndex::Chroma1 as usize]).powi(2)
-                    + (to_arr[AnalysisIndex::Chroma2 as usize] - from_arr[AnalysisIndex::Chroma2 as usize]).powi(2)
-                    + (to_arr[AnalysisIndex::Chroma3 as usize] - from_arr[AnalysisIndex::Chroma3 as usize]).powi(2)
-                    + (to_arr[AnalysisIndex::Chroma4 as usize] - from_arr[AnalysisIndex::Chroma4 as usize]).powi(2)
-                    + (to_arr[AnalysisIndex::Chroma5 as usize] - from_arr[AnalysisIndex::Chroma5 as usize]).powi(2)
-                    + (to_arr[AnalysisIndex::Chroma6 as usize] - from_arr[AnalysisIndex::Chroma6 as usize]).powi(2)
-                    + (to_arr[AnalysisIndex::Chroma7 as usize] - from_arr[AnalysisIndex::Chroma7 as usize]).powi(2)
-                    + (to_arr[AnalysisIndex::Chroma8 as usize] - from_arr[AnalysisIndex::Chroma8 as usize]).powi(2)
-                    + (to_arr[AnalysisIndex::Chroma9 as usize] - from_arr[AnalysisIndex::Chroma9 as usize]).powi(2)
-                    + (to_arr[AnalysisIndex::Chroma10 as usize] - from_arr[AnalysisIndex::Chroma10 as usize]).powi(2)
-                ).sqrt(),
-            })
+            let options = SimilarityOptions::from(compare);
+            let inverse_covariance = if options.metric == DistanceMetric::Mahalanobis {
+                self.inverse_covariance()
+            } else {
+                None
+            };
+            Ok(options.distance(&from_arr, &to_arr, inverse_covariance))
         } else {
             Err(RuntimeMsg(
                 "Channel closed without complete response: internal error".to_owned(),
@@ -256,39 +293,43 @@ impl MusicAnalyzer for DefaultAnalyzer {
         }
     }
 
+    fn get_features(&mut self, item: &Item) -> Result<Vec<(&'static str, f64)>, RuntimeMsg> {
+        self.request_song(item, true)?;
+        let path = Self::get_path(item)?;
+        for response in self.responses.iter() {
+            match response {
+                ResponseType::Distance { .. } => {}
+                ResponseType::Song { path: path2, song } => {
+                    if path2 == path {
+                        let song = song.map_err(|e| RuntimeMsg(format!("Bliss error: {}", e)))?;
+                        let features = Self::bliss_song_to_array(&song);
+                        return Ok(FEATURE_NAMES.into_iter().zip(features).collect());
+                    }
+                }
+                ResponseType::UnsupportedSong { path: path2, msg } => {
+                    if path2 == path {
+                        return Err(RuntimeMsg(format!("Bliss error: {}", msg)));
+                    }
+                }
+            }
+        }
+        Err(RuntimeMsg(
+            "Channel closed without response: internal error".to_owned(),
+        ))
+    }
+
     fn clear_cache(&mut self) -> Result<(), RuntimeMsg> {
+        // the analysis cache may have grown since
+        self.inverse_covariance = None;
         self.requests
             .send(RequestType::Clear {})
             .map_err(|e| RuntimeMsg(format!("Channel send error: {}", e)))
     }
 }
 
+/// Without bliss, songs are compared by their tags instead.
 #[cfg(not(feature = "bliss-audio-symphonia"))]
-#[derive(Default, Debug)]
-pub struct DefaultAnalyzer {}
-
-#[cfg(not(feature = "bliss-audio-symphonia"))]
-impl MusicAnalyzer for DefaultAnalyzer {
-    fn prepare_distance(&mut self, _from: &Item, _to: &Item) -> Result<(), RuntimeMsg> {
-        Ok(())
-    }
-
-    fn prepare_item(&mut self, _item: &Item) -> Result<(), RuntimeMsg> {
-        Ok(())
-    }
-
-    fn get_distance(&mut self, _from: &Item, _to: &Item) -> Result<f64, RuntimeMsg> {
-        Ok(f64::MAX)
-    }
-
-    fn get_custom_distance(&mut self, _from: &Item, _to: &Item, _compare: MusicAnalyzerDistance) -> Result<f64, RuntimeMsg> {
-        Ok(f64::MAX)
-    }
-
-    fn clear_cache(&mut self) -> Result<(), RuntimeMsg> {
-        Ok(())
-    }
-}
+pub type DefaultAnalyzer = super::metadata_analysis::MetadataAnalyzer;
 
 #[cfg(feature = "bliss-audio-symphonia")]
 enum RequestType {
@@ -328,6 +369,8 @@ struct CacheThread {
     distance_in_progress: HashSet<(String, String)>,
     song_cache: HashMap<String, Result<Song, BlissError>>,
     song_in_progress: HashSet<String>,
+    analysis_cache: AnalysisCache,
+    last_save: Instant,
     //requests: Receiver<RequestType>,
     responses: Sender<ResponseType>,
 }
@@ -340,11 +383,43 @@ impl CacheThread {
             distance_in_progress: HashSet::new(),
             song_cache: HashMap::new(),
             song_in_progress: HashSet::new(),
+            analysis_cache: AnalysisCache::open(AnalysisCache::default_path())
+                .unwrap_or_else(|_| AnalysisCache::empty(AnalysisCache::default_path())),
+            last_save: Instant::now(),
             //requests: requests,
             responses: responses,
         }
     }
 
+    // populate song cache from the on-disk analysis cache, to avoid re-analysing the song
+    fn load_cached_song(&mut self, path: &str) {
+        if self.song_cache.contains_key(path) || self.song_in_progress.contains(path) {
+            return;
+        }
+        if let Some(features) = self.analysis_cache.get(path) {
+            if let Ok(analysis) = features.try_into() {
+                let song = Song {
+                    path: path.into(),
+                    analysis: Analysis::new(analysis),
+                    ..Default::default()
+                };
+                self.song_cache.insert(path.to_owned(), Ok(song));
+            } else {
+                // wrong number of features, probably from a different version of bliss
+                self.analysis_cache.remove(path);
+            }
+        }
+    }
+
+    fn save_analysis_cache(&mut self, force: bool) {
+        if self.analysis_cache.is_dirty()
+            && (force || self.last_save.elapsed() > ANALYSIS_CACHE_SAVE_INTERVAL)
+        {
+            self.analysis_cache.save().unwrap_or(());
+            self.last_save = Instant::now();
+        }
+    }
+
     fn non_blocking_read_some(&mut self, results: &Receiver<ResponseType>) {
         for result in results.try_iter() {
             match result {
@@ -365,6 +440,9 @@ impl CacheThread {
 
     fn insert_song(&mut self, path: String, song_result: Result<Box<Song>, BlissError>) {
         self.song_in_progress.remove(&path);
+        if let Ok(song) = &song_result {
+            self.analysis_cache.insert(&path, song.analysis.as_vec());
+        }
         if self.song_cache.len() > MAX_SONG_CACHE_SIZE {
             // avoid using too much memory -- songs are big memory objects
             self.song_cache.clear();
@@ -526,6 +604,8 @@ impl CacheThread {
                     }
                 }
                 let results = worker_tx.clone();
+                self.load_cached_song(&path1);
+                self.load_cached_song(&path2);
                 let song1_clone = self.get_song_option(&path1, true, worker_results);
                 let song2_clone = self.get_song_option(&path2, true, worker_results);
                 std::thread::spawn(move || {
@@ -622,6 +702,7 @@ impl CacheThread {
             }
             return false;
         };
+        self.load_cached_song(&path);
         if let Some(song) = self.song_cache.get(&path) {
             if ack {
                 let song = song.to_owned();
@@ -760,7 +841,10 @@ impl CacheThread {
                     self.song_cache.clear();
                 }
             }
+            self.save_analysis_cache(false);
         }
+        self.non_blocking_read_some(&worker_results);
+        self.save_analysis_cache(true);
     }
 }
 
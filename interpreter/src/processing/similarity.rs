@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Error, Formatter};
+
+#[cfg(feature = "advanced")]
+use super::music_analysis::{MusicAnalyzerDistance, FEATURE_NAMES};
+
+/// Feature groups which can be weighted (each followed by its aliases), in the same order as the analyzer's features.
+pub(crate) const FEATURE_GROUP_NAMES: [&[&str]; 4] = [
+    &["tempo", "beat"],
+    &["spectrum", "s"],
+    &["loudness", "volume"],
+    &["chroma", "c"],
+];
+
+pub(crate) fn feature_group_index(name: &str) -> Option<usize> {
+    FEATURE_GROUP_NAMES
+        .iter()
+        .position(|aliases| aliases.contains(&name))
+}
+
+/// Groups of audio features which can be weighted, in `FEATURE_NAMES` order.
+pub const FEATURE_GROUPS: [&str; 4] = [
+    FEATURE_GROUP_NAMES[0][0],
+    FEATURE_GROUP_NAMES[1][0],
+    FEATURE_GROUP_NAMES[2][0],
+    FEATURE_GROUP_NAMES[3][0],
+];
+
+// number of features in each group, in FEATURE_GROUPS order
+const GROUP_SIZES: [usize; 4] = [2, 6, 2, 10];
+
+// songs needed before the covariance of features is meaningful
+#[cfg(feature = "advanced")]
+const MIN_COVARIANCE_SAMPLES: usize = FEATURE_NAMES.len() * 2;
+
+// added to the variances so the covariance matrix can always be inverted
+const COVARIANCE_REGULARISATION: f64 = 1e-6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistanceMetric {
+    Euclidean,
+    /// Angle between feature vectors, ignoring their magnitude
+    Cosine,
+    /// Euclidean distance after accounting for how features vary (and vary together) across the library
+    Mahalanobis,
+}
+
+impl Default for DistanceMetric {
+    fn default() -> Self {
+        Self::Euclidean
+    }
+}
+
+impl DistanceMetric {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "euclidean" => Some(Self::Euclidean),
+            "cosine" => Some(Self::Cosine),
+            "mahalanobis" => Some(Self::Mahalanobis),
+            _ => None,
+        }
+    }
+}
+
+impl Display for DistanceMetric {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            Self::Euclidean => write!(f, "euclidean"),
+            Self::Cosine => write!(f, "cosine"),
+            Self::Mahalanobis => write!(f, "mahalanobis"),
+        }
+    }
+}
+
+/// How to compare the audio features of songs: a weight for each feature group and a distance metric.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimilarityOptions {
+    /// Weights in `FEATURE_GROUPS` order
+    pub weights: [f64; 4],
+    pub metric: DistanceMetric,
+}
+
+impl Default for SimilarityOptions {
+    fn default() -> Self {
+        Self {
+            weights: [1.0; 4],
+            metric: DistanceMetric::default(),
+        }
+    }
+}
+
+impl SimilarityOptions {
+    /// Index of a feature group (or one of its aliases) in `FEATURE_GROUPS`.
+    pub fn group_index(name: &str) -> Option<usize> {
+        feature_group_index(name)
+    }
+
+    pub fn weight(&self, group: &str) -> f64 {
+        Self::group_index(group)
+            .map(|i| self.weights[i])
+            .unwrap_or(0.0)
+    }
+
+    /// Weight of each feature, in `FEATURE_NAMES` order.
+    pub fn feature_weights(&self) -> Vec<f64> {
+        self.weights
+            .iter()
+            .zip(GROUP_SIZES.iter())
+            .flat_map(|(weight, size)| std::iter::repeat(*weight).take(*size))
+            .collect()
+    }
+
+    /// Distance between two songs' features (in `FEATURE_NAMES` order).
+    /// Mahalanobis distance needs the inverse covariance of features, otherwise it's the same as euclidean distance.
+    pub fn distance(&self, a: &[f64], b: &[f64], inverse_covariance: Option<&[Vec<f64>]>) -> f64 {
+        // weights scale the squared differences, like a weighted sum of squares
+        let scales: Vec<f64> = self.feature_weights().into_iter().map(f64::sqrt).collect();
+        let a: Vec<f64> = a.iter().zip(scales.iter()).map(|(x, s)| x * s).collect();
+        let b: Vec<f64> = b.iter().zip(scales.iter()).map(|(x, s)| x * s).collect();
+        let delta: Vec<f64> = a.iter().zip(b.iter()).map(|(x, y)| x - y).collect();
+        match (self.metric, inverse_covariance) {
+            (DistanceMetric::Cosine, _) => {
+                let dot: f64 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+                let norms = dot_self(&a).sqrt() * dot_self(&b).sqrt();
+                if norms == 0.0 {
+                    // nothing to compare
+                    0.0
+                } else {
+                    1.0 - (dot / norms).clamp(-1.0, 1.0)
+                }
+            }
+            (DistanceMetric::Mahalanobis, Some(inverse)) => {
+                let mut sum = 0.0;
+                for (i, row) in inverse.iter().enumerate() {
+                    for (j, x) in row.iter().enumerate() {
+                        sum += delta[i] * x * delta[j];
+                    }
+                }
+                sum.max(0.0).sqrt()
+            }
+            _ => dot_self(&delta).sqrt(),
+        }
+    }
+}
+
+#[cfg(feature = "advanced")]
+impl From<MusicAnalyzerDistance> for SimilarityOptions {
+    fn from(compare: MusicAnalyzerDistance) -> Self {
+        let only = |group: usize| {
+            let mut weights = [0.0; 4];
+            weights[group] = 1.0;
+            Self {
+                weights: weights,
+                metric: DistanceMetric::Euclidean,
+            }
+        };
+        match compare {
+            MusicAnalyzerDistance::Tempo => only(0),
+            MusicAnalyzerDistance::Spectrum => only(1),
+            MusicAnalyzerDistance::Loudness => only(2),
+            MusicAnalyzerDistance::Chroma => only(3),
+            MusicAnalyzerDistance::Weighted(options) => options,
+        }
+    }
+}
+
+impl Display for SimilarityOptions {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        for (group, weight) in FEATURE_GROUPS.iter().zip(self.weights.iter()) {
+            write!(f, "{}={} ", group, weight)?;
+        }
+        write!(f, "{}", self.metric)
+    }
+}
+
+/// Named similarity options, defined once and used by name in similarity sorters.
+#[derive(Debug, Clone, Default)]
+pub struct SimilarityPresets {
+    presets: HashMap<String, SimilarityOptions>,
+}
+
+impl SimilarityPresets {
+    pub fn define(&mut self, name: String, options: SimilarityOptions) {
+        self.presets.insert(name, options);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SimilarityOptions> {
+        self.presets.get(name)
+    }
+}
+
+/// Inverse of the covariance matrix of song features, for Mahalanobis distance.
+/// None when there aren't enough songs to tell how the features vary.
+#[cfg(feature = "advanced")]
+pub fn inverse_covariance<'a, I: IntoIterator<Item = &'a [f64]>>(
+    samples: I,
+) -> Option<Vec<Vec<f64>>> {
+    let samples: Vec<&[f64]> = samples.into_iter().collect();
+    if samples.len() < MIN_COVARIANCE_SAMPLES {
+        return None;
+    }
+    let dimensions = samples[0].len();
+    let count = samples.len() as f64;
+    let mut mean = vec![0.0; dimensions];
+    for sample in samples.iter() {
+        for (m, x) in mean.iter_mut().zip(sample.iter()) {
+            *m += x / count;
+        }
+    }
+    let mut covariance = vec![vec![0.0; dimensions]; dimensions];
+    for sample in samples.iter() {
+        for i in 0..dimensions {
+            for j in 0..dimensions {
+                covariance[i][j] += (sample[i] - mean[i]) * (sample[j] - mean[j]) / (count - 1.0);
+            }
+        }
+    }
+    for (i, row) in covariance.iter_mut().enumerate() {
+        row[i] += COVARIANCE_REGULARISATION;
+    }
+    invert(covariance)
+}
+
+// Gauss-Jordan elimination with partial pivoting
+fn invert(mut matrix: Vec<Vec<f64>>) -> Option<Vec<Vec<f64>>> {
+    let n = matrix.len();
+    let mut inverse: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|a, b| {
+            matrix[*a][col]
+                .abs()
+                .partial_cmp(&matrix[*b][col].abs())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })?;
+        if matrix[pivot][col].abs() < f64::EPSILON {
+            return None;
+        }
+        matrix.swap(col, pivot);
+        inverse.swap(col, pivot);
+        let divisor = matrix[col][col];
+        for j in 0..n {
+            matrix[col][j] /= divisor;
+            inverse[col][j] /= divisor;
+        }
+        for row in 0..n {
+            if row != col {
+                let factor = matrix[row][col];
+                if factor != 0.0 {
+                    for j in 0..n {
+                        matrix[row][j] -= factor * matrix[col][j];
+                        inverse[row][j] -= factor * inverse[col][j];
+                    }
+                }
+            }
+        }
+    }
+    Some(inverse)
+}
+
+#[inline]
+fn dot_self(v: &[f64]) -> f64 {
+    v.iter().map(|x| x * x).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{invert, DistanceMetric, SimilarityOptions, FEATURE_GROUPS};
+
+    #[test]
+    fn weighted_distances() {
+        let a = vec![0.0; 20];
+        let mut b = vec![0.0; 20];
+        b[0] = 1.0; // tempo
+        b[19] = 1.0; // chroma10
+        let mut options = SimilarityOptions::default();
+        assert!((options.distance(&a, &b, None) - 2f64.sqrt()).abs() < 1e-9);
+        options.weights = [4.0, 1.0, 1.0, 0.0];
+        assert!((options.distance(&a, &b, None) - 2.0).abs() < 1e-9);
+        options.metric = DistanceMetric::Cosine;
+        let mut c = b.clone();
+        c[0] = 2.0;
+        assert!(options.distance(&b, &c, None).abs() < 1e-9);
+        options.metric = DistanceMetric::Mahalanobis;
+        let identity: Vec<Vec<f64>> = (0..20)
+            .map(|i| (0..20).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+            .collect();
+        assert!((options.distance(&a, &b, Some(&identity)) - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn matrix_inverse() {
+        let inverse = invert(vec![vec![4.0, 7.0], vec![2.0, 6.0]]).unwrap();
+        let expected = [[0.6, -0.7], [-0.2, 0.4]];
+        for i in 0..2 {
+            for j in 0..2 {
+                assert!((inverse[i][j] - expected[i][j]).abs() < 1e-9);
+            }
+        }
+        assert!(invert(vec![vec![1.0, 2.0], vec![2.0, 4.0]]).is_none());
+    }
+
+    #[test]
+    fn group_names() {
+        assert_eq!(FEATURE_GROUPS, ["tempo", "spectrum", "loudness", "chroma"]);
+        assert_eq!(SimilarityOptions::group_index("loudness"), Some(2));
+        assert_eq!(SimilarityOptions::group_index("volume"), Some(2));
+        assert_eq!(SimilarityOptions::group_index("bass"), None);
+        let mut options = SimilarityOptions::default();
+        options.weights[0] = 3.0;
+        assert_eq!(options.weight("beat"), 3.0);
+    }
+}
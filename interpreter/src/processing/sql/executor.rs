@@ -17,6 +17,42 @@ use crate::Item;
 
 pub type QueryResult = Result<Box<dyn Op>, RuntimeMsg>;
 
+// `folder` init params can list several folders, separated like `PATH`, and can be given more than once
+#[cfg(any(feature = "sql", all(feature = "fakesql", feature = "music_library")))]
+fn folder_list(param: &str) -> Vec<std::path::PathBuf> {
+    param
+        .lines()
+        .flat_map(std::env::split_paths)
+        .filter(|p| !p.as_os_str().is_empty())
+        .collect()
+}
+
+// the music library only sees canonical paths, so exclude rules must be rooted at them too
+#[cfg(any(feature = "sql", all(feature = "fakesql", feature = "music_library")))]
+fn canonical_folders(folders: Vec<std::path::PathBuf>) -> Vec<std::path::PathBuf> {
+    folders
+        .into_iter()
+        .map(|folder| {
+            #[cfg(feature = "shellexpand")]
+            let folder = match folder.to_str() {
+                Some(s) => std::path::PathBuf::from(shellexpand::tilde(s).into_owned()),
+                None => folder,
+            };
+            folder.canonicalize().unwrap_or(folder)
+        })
+        .collect()
+}
+
+// `exclude` init params are gitignore-style patterns, one for each time the param is given
+#[cfg(any(feature = "sql", all(feature = "fakesql", feature = "music_library")))]
+fn exclude_list(param: &str) -> Vec<String> {
+    param
+        .lines()
+        .map(|s| s.trim().to_owned())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
 /// SQL querying functionality, loosely de-coupled from any specific SQL dialect (excluding raw call)
 pub trait DatabaseQuerier: Debug + Send {
     /// raw SQL call, assumed (but not guaranteed) to retrieved music items
@@ -38,6 +74,7 @@ pub trait DatabaseQuerier: Debug + Send {
     /// `"folder" = "path"` - path to root music directory
     /// `"database" = "uri"` - connection URI for database (for SQLite this is just a filepath)
     /// `"generate" = "true"|"yes"|"false"|"no"` - whether to populate the database using the music directory
+    /// `"watch" = "true"|"false"` - whether to keep the database up to date with changes to the music directory
     /// it is up to the specific implementation to use/ignore these parameters
     fn init_with_params(&mut self, params: &HashMap<String, String>) -> Result<(), RuntimeMsg>;
 }
@@ -143,7 +180,11 @@ impl DatabaseQuerier for SQLiteExecutor {
                 match key as &str {
                     "folder" | "dir" => {
                         match_found = true;
-                        settings.music_path = Some(val.clone());
+                        settings.music_paths = folder_list(val);
+                    }
+                    "exclude" => {
+                        match_found = true;
+                        settings.excludes = exclude_list(val);
                     }
                     "database" | "db" => {
                         match_found = true;
@@ -160,6 +201,18 @@ impl DatabaseQuerier for SQLiteExecutor {
                             ))),
                         }?;
                     }
+                    #[cfg(feature = "watch")]
+                    "watch" => {
+                        match_found = true;
+                        settings.watch = match val as &str {
+                            "true" => Ok(true),
+                            "false" => Ok(false),
+                            x => Err(RuntimeMsg(format!(
+                                "Unrecognised right hand side of param \"{}\" = \"{}\"",
+                                key, x
+                            ))),
+                        }?;
+                    }
                     _ => {}
                 }
                 if match_found {
@@ -185,6 +238,13 @@ impl DatabaseQuerier for SQLiteExecutor {
                     concat_keys
                 )));
             }
+            if settings.music_paths.is_empty() {
+                settings.music_paths = crate::lang::utility::music_folders();
+            }
+            settings.music_paths = canonical_folders(settings.music_paths);
+            // check patterns now, since there's no good way to report them as a connection error
+            crate::processing::general::ExcludeRules::for_folders(&settings.music_paths, &settings.excludes)
+                .map_err(RuntimeMsg)?;
             self.sqlite_connection = Some(
                 settings
                     .try_into()
@@ -197,18 +257,24 @@ impl DatabaseQuerier for SQLiteExecutor {
 
 #[cfg(feature = "sql")]
 struct SqliteSettings {
-    music_path: Option<String>,
+    music_paths: Vec<std::path::PathBuf>,
+    excludes: Vec<String>,
     db_path: Option<String>,
     auto_generate: bool,
+    #[cfg(feature = "watch")]
+    watch: bool,
 }
 
 #[cfg(feature = "sql")]
 impl std::default::Default for SqliteSettings {
     fn default() -> Self {
         SqliteSettings {
-            music_path: None,
+            music_paths: Vec::new(),
+            excludes: Vec::new(),
             db_path: None,
             auto_generate: true,
+            #[cfg(feature = "watch")]
+            watch: false,
         }
     }
 }
@@ -218,14 +284,28 @@ impl std::convert::TryInto<rusqlite::Connection> for SqliteSettings {
     type Error = rusqlite::Error;
 
     fn try_into(self) -> Result<rusqlite::Connection, Self::Error> {
-        let music_path = self
-            .music_path
-            .map(std::path::PathBuf::from)
-            .unwrap_or_else(crate::lang::utility::music_folder);
+        let music_paths = canonical_folders(if self.music_paths.is_empty() {
+            crate::lang::utility::music_folders()
+        } else {
+            self.music_paths
+        });
+        let excludes =
+            crate::processing::general::ExcludeRules::for_folders(&music_paths, &self.excludes)
+                .unwrap_or_default();
         let sqlite_path = self
             .db_path
             .unwrap_or_else(|| crate::lang::db::DEFAULT_SQLITE_FILEPATH.to_string());
-        crate::lang::db::generate_db(music_path, sqlite_path, self.auto_generate)
+        let conn = crate::lang::db::generate_db(
+            &music_paths,
+            &excludes,
+            &sqlite_path,
+            self.auto_generate,
+        )?;
+        #[cfg(feature = "watch")]
+        if self.watch {
+            crate::lang::db::watch_db(&music_paths, &excludes, sqlite_path);
+        }
+        Ok(conn)
     }
 }
 
@@ -316,7 +396,16 @@ fn rows_to_item(
         .set_field_chain("genre", genre.title.into())
         // music metadata
         .set_field_chain("track", meta.track.into())
-        .set_field_chain("year", meta.date.into());
+        .set_field_chain("year", meta.date.into())
+        .set_field_chain("disc", meta.disc.into())
+        .set_field_chain("duration", meta.duration.into())
+        .set_field_chain("added", meta.added.into());
+    if let Some(composer) = meta.composer {
+        item.set_field("composer", composer.into());
+    }
+    if let Some(bitrate) = meta.bitrate {
+        item.set_field("bitrate", bitrate.into());
+    }
     item
 }
 
@@ -351,33 +440,83 @@ impl DatabaseQuerier for SQLErrExecutor {
     }
 }
 
+/// SQL querier which transpiles queries into filters over the music library.
+/// With the `music_library` feature, the library is read once into an in-memory index
+/// which is shared by every query.
 #[cfg(feature = "fakesql")]
-#[derive(Default, Debug)]
-pub struct SQLiteTranspileExecutor;
+#[derive(Debug)]
+pub struct SQLiteTranspileExecutor {
+    #[cfg(feature = "music_library")]
+    index: super::SharedLibraryIndex,
+}
+
+#[cfg(feature = "fakesql")]
+impl std::default::Default for SQLiteTranspileExecutor {
+    fn default() -> Self {
+        Self {
+            #[cfg(feature = "music_library")]
+            index: super::LibraryIndex::new(crate::lang::utility::music_folders()).shared(),
+        }
+    }
+}
+
+#[cfg(feature = "fakesql")]
+impl SQLiteTranspileExecutor {
+    fn simple_query(&self, field: &str, query: &str) -> QueryResult {
+        let emitted = super::SimpleSqlQuery::emit(field, query);
+        #[cfg(feature = "music_library")]
+        let emitted = emitted.with_index(self.index.clone());
+        Ok(Box::new(emitted))
+    }
+}
 
 #[cfg(feature = "fakesql")]
 impl DatabaseQuerier for SQLiteTranspileExecutor {
     fn raw(&mut self, query: &str) -> QueryResult {
-        Ok(Box::new(super::RawSqlQuery::emit(query)?))
+        let emitted = super::RawSqlQuery::emit(query)?;
+        #[cfg(feature = "music_library")]
+        let emitted = emitted.with_index(self.index.clone());
+        Ok(Box::new(emitted))
     }
 
     fn artist_like(&mut self, query: &str) -> QueryResult {
-        Ok(Box::new(super::SimpleSqlQuery::emit("artist", query)))
+        self.simple_query("artist", query)
     }
 
     fn album_like(&mut self, query: &str) -> QueryResult {
-        Ok(Box::new(super::SimpleSqlQuery::emit("album", query)))
+        self.simple_query("album", query)
     }
 
     fn song_like(&mut self, query: &str) -> QueryResult {
-        Ok(Box::new(super::SimpleSqlQuery::emit("title", query)))
+        self.simple_query("title", query)
     }
 
     fn genre_like(&mut self, query: &str) -> QueryResult {
-        Ok(Box::new(super::SimpleSqlQuery::emit("genre", query)))
+        self.simple_query("genre", query)
     }
 
-    fn init_with_params(&mut self, _params: &HashMap<String, String>) -> Result<(), RuntimeMsg> {
+    fn init_with_params(&mut self, params: &HashMap<String, String>) -> Result<(), RuntimeMsg> {
+        // other params only make sense for a real database, so they're ignored
+        #[cfg(feature = "music_library")]
+        {
+            let folders = params
+                .get("folder")
+                .or_else(|| params.get("dir"))
+                .map(|folder| folder_list(folder))
+                .filter(|folders| !folders.is_empty())
+                .unwrap_or_else(crate::lang::utility::music_folders);
+            let folders = canonical_folders(folders);
+            let excludes = params
+                .get("exclude")
+                .map(|exclude| exclude_list(exclude))
+                .unwrap_or_default();
+            self.index
+                .lock()
+                .map_err(|e| RuntimeMsg(format!("Library index lock failed: {}", e)))?
+                .set_roots(folders, excludes)?;
+        }
+        #[cfg(not(feature = "music_library"))]
+        let _ = params;
         Ok(())
     }
 }
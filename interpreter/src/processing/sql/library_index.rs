@@ -0,0 +1,340 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Debug, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use regex::Regex;
+
+use crate::lang::vocabulary::filters::utility::sanitise_string;
+use crate::lang::{RuntimeMsg, TypePrimitive};
+use crate::music::Library;
+#[cfg(feature = "watch")]
+use crate::music::LibraryWatcher;
+use crate::processing::filesystem::{populate_item_from_path, DEFAULT_REGEX};
+use crate::processing::general::ExcludeRules;
+use crate::Item;
+
+const LIBRARY_DEPTH: usize = usize::MAX;
+
+const INDEXED_FIELDS: [&str; 4] = ["artist", "album", "genre", "title"];
+
+/// Library index shared between a database querier and the queries it emits.
+pub type SharedLibraryIndex = Arc<Mutex<LibraryIndex>>;
+
+#[derive(Debug)]
+struct IndexEntry {
+    sanitised: String,
+    songs: BTreeSet<u64>,
+}
+
+/// Songs by the (exact) value of one field.
+#[derive(Debug, Default)]
+struct FieldIndex {
+    entries: HashMap<String, IndexEntry>,
+}
+
+impl FieldIndex {
+    fn insert(&mut self, value: &str, song_id: u64) {
+        self.entries
+            .entry(value.to_owned())
+            .or_insert_with(|| IndexEntry {
+                sanitised: sanitise_string(value),
+                songs: BTreeSet::new(),
+            })
+            .songs
+            .insert(song_id);
+    }
+
+    fn remove(&mut self, value: &str, song_id: u64) {
+        if let Some(entry) = self.entries.get_mut(value) {
+            entry.songs.remove(&song_id);
+            if entry.songs.is_empty() {
+                self.entries.remove(value);
+            }
+        }
+    }
+
+    fn exact(&self, value: &str) -> Vec<u64> {
+        self.entries
+            .get(value)
+            .map(|entry| entry.songs.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    fn like(&self, sanitised: &str) -> Vec<u64> {
+        // only distinct values need to be compared, not every song
+        self.entries
+            .values()
+            .filter(|entry| entry.sanitised.contains(sanitised))
+            .flat_map(|entry| entry.songs.iter().copied())
+            .collect()
+    }
+}
+
+/// In-memory music library with secondary indexes on artist, album, genre and title.
+///
+/// The library is read from the music folders on first use and kept up to date with
+/// changes to the filesystem, so only new and modified files are ever re-read.
+pub struct LibraryIndex {
+    roots: Vec<PathBuf>,
+    excludes: Vec<String>,
+    library: Library,
+    // every file in the music folders, by id
+    items: HashMap<u64, Item>,
+    file_ids: HashMap<PathBuf, u64>,
+    next_id: u64,
+    fields: HashMap<&'static str, FieldIndex>,
+    like_cache: HashMap<(String, String), Vec<u64>>,
+    path_pattern: Regex,
+    is_built: bool,
+    #[cfg(feature = "watch")]
+    watcher: Option<LibraryWatcher>,
+}
+
+impl LibraryIndex {
+    pub fn new<P: AsRef<Path>, I: IntoIterator<Item = P>>(roots: I) -> Self {
+        Self {
+            roots: roots
+                .into_iter()
+                .map(|root| root.as_ref().to_path_buf())
+                .collect(),
+            excludes: Vec::new(),
+            library: Library::new(),
+            items: HashMap::new(),
+            file_ids: HashMap::new(),
+            next_id: 0,
+            fields: INDEXED_FIELDS
+                .iter()
+                .map(|field| (*field, FieldIndex::default()))
+                .collect(),
+            like_cache: HashMap::new(),
+            path_pattern: Regex::new(DEFAULT_REGEX).unwrap(),
+            is_built: false,
+            #[cfg(feature = "watch")]
+            watcher: None,
+        }
+    }
+
+    pub fn shared(self) -> SharedLibraryIndex {
+        Arc::new(Mutex::new(self))
+    }
+
+    /// Change the music folders and gitignore-style exclude patterns,
+    /// discarding everything indexed so far if either has changed.
+    pub fn set_roots(
+        &mut self,
+        roots: Vec<PathBuf>,
+        excludes: Vec<String>,
+    ) -> Result<(), RuntimeMsg> {
+        if self.roots != roots || self.excludes != excludes {
+            let rules = ExcludeRules::for_folders(&roots, &excludes).map_err(RuntimeMsg)?;
+            *self = Self::new(roots);
+            self.excludes = excludes;
+            self.library.set_excludes(rules);
+        }
+        Ok(())
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn is_indexed(field: &str) -> bool {
+        INDEXED_FIELDS.contains(&field)
+    }
+
+    /// Songs where the (sanitised) field value contains the (sanitised) query,
+    /// in filename order.
+    pub fn like(&mut self, field: &str, query: &str) -> Result<Vec<Item>, RuntimeMsg> {
+        self.refresh()?;
+        let sanitised = sanitise_string(query);
+        let key = (field.to_owned(), sanitised);
+        let ids = if let Some(ids) = self.like_cache.get(&key) {
+            ids.clone()
+        } else {
+            let ids = if let Some(index) = self.fields.get(field) {
+                index.like(&key.1)
+            } else {
+                self.items
+                    .iter()
+                    .filter(|(_, item)| match item.field(field) {
+                        Some(TypePrimitive::String(val)) => sanitise_string(val).contains(&key.1),
+                        _ => false,
+                    })
+                    .map(|(id, _)| *id)
+                    .collect()
+            };
+            self.like_cache.insert(key, ids.clone());
+            ids
+        };
+        Ok(self.items_by_id(ids))
+    }
+
+    /// Songs accepted by `filter`, in filename order.
+    /// When `hint` is an indexed field and value which every accepted song must have,
+    /// only songs with that exact field value are considered.
+    pub fn select<F: FnMut(&Item) -> bool>(
+        &mut self,
+        hint: Option<(&str, &str)>,
+        mut filter: F,
+    ) -> Result<Vec<Item>, RuntimeMsg> {
+        self.refresh()?;
+        let ids: Vec<u64> = match hint.and_then(|(field, val)| Some((self.fields.get(field)?, val)))
+        {
+            Some((index, val)) => index.exact(val),
+            None => self.items.keys().copied().collect(),
+        };
+        let ids = ids
+            .into_iter()
+            .filter(|id| self.items.get(id).map(&mut filter).unwrap_or(false))
+            .collect();
+        Ok(self.items_by_id(ids))
+    }
+
+    /// Bring the index up to date with the music folder.
+    pub fn refresh(&mut self) -> Result<(), RuntimeMsg> {
+        if !self.is_built {
+            // start watching first, so changes made while reading aren't missed
+            #[cfg(feature = "watch")]
+            {
+                self.watcher = self.watch_roots();
+            }
+            self.update_roots()?;
+            self.is_built = true;
+        } else {
+            #[cfg(feature = "watch")]
+            let changes = self.watcher.as_ref().map(|watcher| watcher.changes());
+            #[cfg(not(feature = "watch"))]
+            let changes: Option<Vec<PathBuf>> = None;
+            if let Some(changes) = changes {
+                for path in changes {
+                    // files can disappear or be half-written mid-copy;
+                    // they'll be picked up by the next change event
+                    self.library.update_file(path).unwrap_or(());
+                }
+            } else {
+                // no watcher, so check modification times instead (tags are only re-read for modified files)
+                self.update_roots()?;
+            }
+        }
+        if self.library.is_modified() {
+            self.apply_library_changes();
+            self.library.clear_modified();
+        }
+        Ok(())
+    }
+
+    fn update_roots(&mut self) -> Result<(), RuntimeMsg> {
+        for root in self.roots.iter() {
+            self.library
+                .update_path(root, LIBRARY_DEPTH)
+                .map_err(|e| RuntimeMsg(format!("Music library read error: {}", e)))?;
+        }
+        Ok(())
+    }
+
+    // every root must be watched, otherwise changes could be missed
+    #[cfg(feature = "watch")]
+    fn watch_roots(&self) -> Option<LibraryWatcher> {
+        let (first, others) = self.roots.split_first()?;
+        let mut watcher = LibraryWatcher::new(first).ok()?;
+        for root in others {
+            watcher.add_root(root).ok()?;
+        }
+        Some(watcher)
+    }
+
+    fn apply_library_changes(&mut self) {
+        let removed: Vec<u64> = self
+            .library
+            .removed_files()
+            .into_iter()
+            .filter_map(|path| self.file_ids.remove(path))
+            .collect();
+        for id in removed {
+            self.remove_item(id);
+        }
+        let changed: Vec<PathBuf> = self
+            .library
+            .changed_files()
+            .into_iter()
+            .map(|(path, _)| path.to_path_buf())
+            .collect();
+        for path in changed {
+            let item = self.file_item(&path);
+            let id = *self.file_ids.entry(path).or_insert_with(|| {
+                self.next_id += 1;
+                self.next_id
+            });
+            self.remove_item(id);
+            for (field, index) in self.fields.iter_mut() {
+                if let Some(TypePrimitive::String(val)) = item.field(field) {
+                    index.insert(val, id);
+                }
+            }
+            self.items.insert(id, item);
+        }
+        self.like_cache.clear();
+    }
+
+    // same fields as reading the file with files()
+    fn file_item(&self, path: &Path) -> Item {
+        let mut item = match self.library.changed_tags(path) {
+            Some(tags) => tags.clone(),
+            None => {
+                let mut item = Item::new();
+                if let Ok(tags) = Library::read_media_tags(path) {
+                    tags.export_to_item(&mut item, true);
+                }
+                item
+            }
+        };
+        let path_str = path.to_string_lossy();
+        populate_item_from_path(
+            &mut item,
+            &path_str,
+            self.path_pattern.captures(&path_str),
+            self.path_pattern.capture_names(),
+        );
+        item
+    }
+
+    fn remove_item(&mut self, id: u64) {
+        if let Some(item) = self.items.remove(&id) {
+            for (field, index) in self.fields.iter_mut() {
+                if let Some(TypePrimitive::String(val)) = item.field(field) {
+                    index.remove(val, id);
+                }
+            }
+        }
+    }
+
+    fn items_by_id(&self, ids: Vec<u64>) -> Vec<Item> {
+        let mut items: Vec<&Item> = ids.iter().filter_map(|id| self.items.get(id)).collect();
+        // same order as reading the music folder
+        items.sort_by_cached_key(|item| match item.field("filename") {
+            Some(TypePrimitive::String(filename)) => filename.to_lowercase(),
+            _ => String::new(),
+        });
+        items.into_iter().cloned().collect()
+    }
+}
+
+impl Debug for LibraryIndex {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("LibraryIndex")
+            .field("roots", &self.roots)
+            .field("excludes", &self.excludes)
+            .field("songs", &self.items.len())
+            .field("is_built", &self.is_built)
+            .finish()
+    }
+}
@@ -1,10 +1,14 @@
 mod executor;
+#[cfg(all(feature = "fakesql", feature = "music_library"))]
+mod library_index;
 #[cfg(feature = "fakesql")]
 mod raw_emit;
 #[cfg(feature = "fakesql")]
 mod simple_emit;
 
 pub use executor::*;
+#[cfg(all(feature = "fakesql", feature = "music_library"))]
+pub use library_index::{LibraryIndex, SharedLibraryIndex};
 #[cfg(feature = "fakesql")]
 pub use raw_emit::RawSqlQuery;
 #[cfg(feature = "fakesql")]
@@ -1,9 +1,13 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::fmt::{Debug, Display, Error, Formatter};
 use std::iter::Iterator;
 use std::collections::VecDeque;
 
+use regex::Regex;
 use sqlparser::{parser::Parser, dialect::SQLiteDialect};
-use sqlparser::ast::{Statement, SetExpr, Expr, OrderByExpr, Value, BinaryOperator};
+use sqlparser::ast::{Statement, SetExpr, Expr, OrderByExpr, Value, BinaryOperator, UnaryOperator, FunctionArg, FunctionArgExpr};
+use sqlparser::tokenizer::{Token as SqlToken, Tokenizer};
 
 use crate::Context;
 
@@ -12,22 +16,31 @@ use crate::lang::{RuntimeError, RuntimeOp, RuntimeMsg, TypePrimitive};
 use crate::processing::general::FileIter;
 use crate::Item;
 
+// sqlparser doesn't know about GLOB, so `x GLOB 'p'` is rewritten to `x LIKE '<marker>p'`
+const GLOB_MARKER: char = '\u{E000}';
+
 #[derive(Debug)]
 pub struct RawSqlQuery {
     context: Option<Context>,
     file_iter: Option<FileIter>,
     match_rule: Option<MatchRule>,
     sort_by: Option<SortRule>,
+    limit: Option<usize>,
+    offset: usize,
     items_buffer: VecDeque<IteratorItem>,
     raw_query: String,
     has_tried: bool,
+    skipped: usize,
+    emitted: usize,
+    #[cfg(feature = "music_library")]
+    index: Option<super::SharedLibraryIndex>,
 }
 
 impl RawSqlQuery {
     pub fn emit(query_str: &str) -> Result<Self, RuntimeMsg> {
-        let mut statements = Parser::parse_sql(&SQLiteDialect{}, query_str).map_err(|e| RuntimeMsg(format!("Could not parse SQL query: {}", e)))?;
+        let mut statements = parse_sql(query_str).map_err(|e| RuntimeMsg(format!("Could not parse SQL query: {}", e)))?;
         if statements.len() == 1 {
-            if let Statement::Query(mut query) = statements.remove(0) {
+            if let Statement::Query(query) = statements.remove(0) {
                 let matching = if let SetExpr::Select(select) = *query.body {
                     if let Some(selection) = select.selection {
                         Some(MatchRule::from_parsed(selection)?)
@@ -38,18 +51,32 @@ impl RawSqlQuery {
                     return Err(RuntimeMsg("Unsupported SELECT syntax in SQL".to_owned()));
                 };
                 let ordering = if !query.order_by.is_empty() {
-                    Some(SortRule::from_parsed(query.order_by.remove(0))?)
+                    Some(SortRule::from_parsed(query.order_by)?)
                 } else {
                     None
                 };
+                let limit = match query.limit {
+                    Some(limit) => Some(expr_to_count(limit, "LIMIT")?),
+                    None => None,
+                };
+                let offset = match query.offset {
+                    Some(offset) => expr_to_count(offset.value, "OFFSET")?,
+                    None => 0,
+                };
                 Ok(Self {
                     context: None,
                     file_iter: None,
                     match_rule: matching,
                     sort_by: ordering,
+                    limit: limit,
+                    offset: offset,
                     items_buffer: VecDeque::new(),
                     raw_query: query_str.to_owned(),
                     has_tried: false,
+                    skipped: 0,
+                    emitted: 0,
+                    #[cfg(feature = "music_library")]
+                    index: None,
                 })
             } else {
                 Err(RuntimeMsg("Expected SQL SELECT statement".to_owned()))
@@ -59,6 +86,22 @@ impl RawSqlQuery {
         }
     }
 
+    /// Answer the query from a library index instead of reading the music folder.
+    #[cfg(feature = "music_library")]
+    pub fn with_index(mut self, index: super::SharedLibraryIndex) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    #[cfg(feature = "music_library")]
+    fn query_index(&self, index: &super::SharedLibraryIndex) -> Result<Vec<Item>, RuntimeMsg> {
+        let hint = self.match_rule.as_ref().and_then(|rule| rule.index_hint());
+        index
+            .lock()
+            .map_err(|e| RuntimeMsg(format!("Library index lock failed: {}", e)))?
+            .select(hint, |item| self.matches_filters(item))
+    }
+
     #[inline]
     fn matches_filters(&self, item: &Item) -> bool {
         if let Some(match_rule) = &self.match_rule {
@@ -67,32 +110,23 @@ impl RawSqlQuery {
             true
         }
     }
-}
 
-impl Display for RawSqlQuery {
-    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        write!(f, "sql(`{}`)", self.raw_query)
-    }
-}
-
-impl std::clone::Clone for RawSqlQuery {
-    fn clone(&self) -> Self {
-        Self {
-            context: None,
-            file_iter: None,
-            match_rule: self.match_rule.clone(),
-            sort_by: self.sort_by.clone(),
-            items_buffer: VecDeque::with_capacity(self.items_buffer.len()),
-            raw_query: self.raw_query.clone(),
-            has_tried: self.has_tried,
+    /// Next item which matches the query, before LIMIT and OFFSET are applied
+    fn next_match(&mut self) -> Option<IteratorItem> {
+        #[cfg(feature = "music_library")]
+        if let Some(index) = &self.index {
+            if !self.has_tried {
+                self.has_tried = true;
+                match self.query_index(index) {
+                    Ok(items) => self.items_buffer.extend(items.into_iter().map(Ok)),
+                    Err(e) => return Some(Err(e.with(RuntimeOp(PseudoOp::from_printable(self))))),
+                }
+                if let Some(sort_by) = &self.sort_by {
+                    sort_by.sort_vecdeque(&mut self.items_buffer);
+                }
+            }
+            return self.items_buffer.pop_front();
         }
-    }
-}
-
-impl Iterator for RawSqlQuery {
-    type Item = IteratorItem;
-
-    fn next(&mut self) -> Option<Self::Item> {
         if self.file_iter.is_none() {
             if self.has_tried {
                 return None;
@@ -151,8 +185,73 @@ impl Iterator for RawSqlQuery {
             None
         }
     }
+}
+
+impl Display for RawSqlQuery {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "sql(`{}`)", self.raw_query)
+    }
+}
+
+impl std::clone::Clone for RawSqlQuery {
+    fn clone(&self) -> Self {
+        Self {
+            context: None,
+            file_iter: None,
+            match_rule: self.match_rule.clone(),
+            sort_by: self.sort_by.clone(),
+            limit: self.limit,
+            offset: self.offset,
+            items_buffer: VecDeque::with_capacity(self.items_buffer.len()),
+            raw_query: self.raw_query.clone(),
+            has_tried: self.has_tried,
+            skipped: self.skipped,
+            emitted: self.emitted,
+            #[cfg(feature = "music_library")]
+            index: self.index.clone(),
+        }
+    }
+}
+
+impl Iterator for RawSqlQuery {
+    type Item = IteratorItem;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.skipped < self.offset {
+            match self.next_match() {
+                Some(Ok(_)) => self.skipped += 1,
+                Some(Err(e)) => return Some(Err(e)),
+                None => return None,
+            }
+        }
+        if let Some(limit) = self.limit {
+            if self.emitted >= limit {
+                return None;
+            }
+        }
+        let item = self.next_match();
+        if let Some(Ok(_)) = item {
+            self.emitted += 1;
+        }
+        item
+    }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
+        #[cfg(feature = "music_library")]
+        if self.index.is_some() {
+            if !self.has_tried {
+                return (0, None); // the query hasn't run yet
+            }
+            // everything left is buffered, but OFFSET and LIMIT still apply
+            let mut remaining = self
+                .items_buffer
+                .len()
+                .saturating_sub(self.offset.saturating_sub(self.skipped));
+            if let Some(limit) = self.limit {
+                remaining = remaining.min(limit.saturating_sub(self.emitted));
+            }
+            return (remaining, Some(remaining));
+        }
         self.file_iter.as_ref().map(|x| x.size_hint()).unwrap_or_default()
     }
 }
@@ -171,6 +270,12 @@ impl Op for RawSqlQuery {
     }
 
     fn reset(&mut self) -> Result<(), RuntimeError> {
+        // the query runs again, so OFFSET and LIMIT start again too
+        self.file_iter = None;
+        self.items_buffer.clear();
+        self.has_tried = false;
+        self.skipped = 0;
+        self.emitted = 0;
         Ok(())
     }
 
@@ -179,65 +284,144 @@ impl Op for RawSqlQuery {
     }
 }
 
+fn parse_sql(query_str: &str) -> Result<Vec<Statement>, String> {
+    let dialect = SQLiteDialect{};
+    let tokens = Tokenizer::new(&dialect, query_str).tokenize().map_err(|e| format!("{:?}", e))?;
+    // rewrite `GLOB 'pattern'` as `LIKE '<marker>pattern'`
+    let mut rewritten = Vec::with_capacity(tokens.len());
+    let mut is_glob = false;
+    for token in tokens {
+        match token {
+            SqlToken::Word(word) if word.quote_style.is_none() && word.value.eq_ignore_ascii_case("GLOB") => {
+                is_glob = true;
+                rewritten.push(SqlToken::make_keyword("LIKE"));
+            },
+            SqlToken::Whitespace(_) => rewritten.push(token),
+            SqlToken::SingleQuotedString(s) if is_glob => {
+                is_glob = false;
+                rewritten.push(SqlToken::SingleQuotedString(format!("{}{}", GLOB_MARKER, s)));
+            },
+            token if is_glob => return Err(format!("Expected string pattern after GLOB, got {}", token)),
+            token => rewritten.push(token),
+        }
+    }
+    let mut parser = Parser::new(rewritten, &dialect);
+    let mut statements = Vec::new();
+    loop {
+        while parser.consume_token(&SqlToken::SemiColon) {}
+        if parser.peek_token() == SqlToken::EOF {
+            break;
+        }
+        statements.push(parser.parse_statement().map_err(|e| e.to_string())?);
+    }
+    Ok(statements)
+}
+
+#[inline]
+fn expr_to_count(expr: Expr, clause: &str) -> Result<usize, RuntimeMsg> {
+    match expr {
+        Expr::Value(Value::Number(s, _)) => s.parse().map_err(|_| RuntimeMsg(format!("Invalid SQL {} value: {}", clause, s))),
+        x => Err(RuntimeMsg(format!("Unsupported SQL {} syntax: {}", clause, x)))
+    }
+}
+
+/// Value used in a condition or ordering
+#[derive(Debug, Clone)]
+enum Operand {
+    Field(String),
+    Value(TypePrimitive),
+    Lower(Box<Operand>),
+    Upper(Box<Operand>),
+    Length(Box<Operand>),
+}
+
+impl Operand {
+    #[inline]
+    fn evaluate<'a>(&'a self, item: &'a Item) -> Cow<'a, TypePrimitive> {
+        match self {
+            Self::Field(field) => match item.field(field) {
+                Some(val) => Cow::Borrowed(val),
+                None => Cow::Owned(TypePrimitive::Empty),
+            },
+            Self::Value(val) => Cow::Borrowed(val),
+            Self::Lower(x) => match x.evaluate(item).as_ref() {
+                TypePrimitive::String(s) => Cow::Owned(TypePrimitive::String(s.to_lowercase())),
+                other => Cow::Owned(other.clone()),
+            },
+            Self::Upper(x) => match x.evaluate(item).as_ref() {
+                TypePrimitive::String(s) => Cow::Owned(TypePrimitive::String(s.to_uppercase())),
+                other => Cow::Owned(other.clone()),
+            },
+            Self::Length(x) => match x.evaluate(item).as_ref() {
+                TypePrimitive::Empty => Cow::Owned(TypePrimitive::Empty),
+                TypePrimitive::String(s) => Cow::Owned(TypePrimitive::UInt(s.chars().count() as u64)),
+                other => Cow::Owned(TypePrimitive::UInt(other.to_string().chars().count() as u64)),
+            },
+        }
+    }
+
+    fn from_parsed(expr: Expr) -> Result<Self, RuntimeMsg> {
+        match expr {
+            Expr::Identifier(id) => Ok(Self::Field(id.value)),
+            // table.field -- there's only one table
+            Expr::CompoundIdentifier(mut ids) if !ids.is_empty() => Ok(Self::Field(ids.pop().unwrap().value)),
+            Expr::Value(val) => Ok(Self::Value(value_to_primitive(val)?)),
+            Expr::Nested(x) => Self::from_parsed(*x),
+            Expr::UnaryOp { op: UnaryOperator::Minus, expr } => match *expr {
+                Expr::Value(Value::Number(s, _)) => Ok(Self::Value(TypePrimitive::parse(format!("-{}", s)))),
+                x => Err(RuntimeMsg(format!("Unsupported SQL operator syntax: -{}", x)))
+            },
+            Expr::Function(mut func) => {
+                let name = func.name.to_string().to_lowercase();
+                if func.args.len() != 1 {
+                    return Err(RuntimeMsg(format!("Unsupported SQL function syntax: {}() expects 1 parameter, got {}", name, func.args.len())));
+                }
+                let arg = match func.args.remove(0) {
+                    FunctionArg::Unnamed(FunctionArgExpr::Expr(arg)) => Self::from_parsed(arg)?,
+                    x => return Err(RuntimeMsg(format!("Unsupported SQL function parameter syntax: {}", x)))
+                };
+                match &name as &str {
+                    "lower" => Ok(Self::Lower(Box::new(arg))),
+                    "upper" => Ok(Self::Upper(Box::new(arg))),
+                    "length" => Ok(Self::Length(Box::new(arg))),
+                    x => Err(RuntimeMsg(format!("Unsupported SQL function: {}()", x)))
+                }
+            },
+            x => Err(RuntimeMsg(format!("Unsupported SQL value syntax: {}", x)))
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 enum MatchRule {
-    Like { field: String, pattern: LikePattern, negated: bool },
-    CompareVal { field: String, value: TypePrimitive, comparison: [i8; 2] },
-    CompareFields { field_a: String, field_b: String, comparison: [i8; 2] },
+    Like { operand: Operand, pattern: WildcardPattern, negated: bool },
+    Compare { a: Operand, b: Operand, comparison: [i8; 2] },
+    In { operand: Operand, values: Vec<Operand>, negated: bool },
     And { a: Box<MatchRule>, b: Box<MatchRule> },
     Or { a: Box<MatchRule>, b: Box<MatchRule> },
+    Not(Box<MatchRule>),
 }
 
 impl MatchRule {
     #[inline]
     fn is_match(&self, item: &Item) -> bool {
         match self {
-            Self::Like { field, pattern, negated } => {
-                if let Some(TypePrimitive::String(val)) = item.field(field) {
+            Self::Like { operand, pattern, negated } => {
+                if let TypePrimitive::String(val) = operand.evaluate(item).as_ref() {
                     pattern.is_match(val) != *negated
                 } else {
                     *negated
                 }
             },
-            Self::CompareVal { field, value, comparison } => {
-                if let Some(val) = item.field(field) {
-                    match val.compare(value) {
-                        Ok(cmp) => comparison[0] == cmp || comparison[1] == cmp,
-                        Err(_) => comparison[0] != 0 && comparison[1] != 0,
-                    }
-                } else {
-                    match TypePrimitive::Empty.compare(value) {
-                        Ok(cmp) => comparison[0] == cmp || comparison[1] == cmp,
-                        Err(_) => comparison[0] != 0 && comparison[1] != 0,
-                    }
+            Self::Compare { a, b, comparison } => {
+                match a.evaluate(item).compare(&b.evaluate(item)) {
+                    Ok(cmp) => comparison[0] == cmp || comparison[1] == cmp,
+                    Err(_) => comparison[0] != 0 && comparison[1] != 0,
                 }
             },
-            Self::CompareFields { field_a, field_b, comparison} => {
-                if let Some(val_a) = item.field(field_a) {
-                    if let Some(val_b) = item.field(field_b) {
-                        match val_a.compare(val_b) {
-                            Ok(cmp) => comparison[0] == cmp || comparison[1] == cmp,
-                            Err(_) => comparison[0] != 0 && comparison[1] != 0,
-                        }
-                    } else {
-                        match val_a.compare(&TypePrimitive::Empty) {
-                            Ok(cmp) => comparison[0] == cmp || comparison[1] == cmp,
-                            Err(_) => comparison[0] != 0 && comparison[1] != 0,
-                        }
-                    }
-                } else {
-                    if let Some(val_b) = item.field(field_b) {
-                        match TypePrimitive::Empty.compare(val_b) {
-                            Ok(cmp) => comparison[0] == cmp || comparison[1] == cmp,
-                            Err(_) => comparison[0] != 0 && comparison[1] != 0,
-                        }
-                    } else {
-                        match TypePrimitive::Empty.compare(&TypePrimitive::Empty) {
-                            Ok(cmp) => comparison[0] == cmp || comparison[1] == cmp,
-                            Err(_) => comparison[0] != 0 && comparison[1] != 0,
-                        }
-                    }
-                }
+            Self::In { operand, values, negated } => {
+                let val = operand.evaluate(item);
+                values.iter().any(|x| val.compare(&x.evaluate(item)) == Ok(0)) != *negated
             },
             Self::And { a, b } => {
                 a.is_match(item) && b.is_match(item)
@@ -245,52 +429,72 @@ impl MatchRule {
             Self::Or { a, b } => {
                 a.is_match(item) || b.is_match(item)
             },
+            Self::Not(x) => !x.is_match(item),
+        }
+    }
+
+    /// An indexed field and exact value which every matching item must have, if there is one.
+    #[cfg(feature = "music_library")]
+    fn index_hint(&self) -> Option<(&str, &str)> {
+        match self {
+            Self::Compare { a: Operand::Field(field), b: Operand::Value(TypePrimitive::String(value)), comparison: [0, 0] }
+                if super::LibraryIndex::is_indexed(field) => Some((field, value)),
+            Self::And { a, b } => a.index_hint().or_else(|| b.index_hint()),
+            _ => None,
         }
     }
 
+    #[inline]
+    fn compare_to(x: Expr, value: TypePrimitive, comparison: [i8; 2]) -> Result<Self, RuntimeMsg> {
+        Ok(Self::Compare { a: Operand::from_parsed(x)?, b: Operand::Value(value), comparison: comparison })
+    }
+
     #[inline]
     fn from_parsed(expr: Expr) -> Result<Self, RuntimeMsg> {
         match expr {
-            Expr::IsFalse(x) => if let Expr::Identifier(id) = *x {
-                Ok(Self::CompareVal{ field: id.value, value:TypePrimitive::Bool(false), comparison: [0, 0] })
-            } else {
-                Err(RuntimeMsg(format!("Unsupported SQL IS FALSE syntax: {}", x)))
-            },
-            Expr::IsNotFalse(x) => if let Expr::Identifier(id) = *x {
-                Ok(Self::CompareVal{ field: id.value, value:TypePrimitive::Bool(false), comparison: [1, -1] })
-            } else {
-                Err(RuntimeMsg(format!("Unsupported SQL IS NOT FALSE syntax: {}", x)))
-            },
-            Expr::IsTrue(x) => if let Expr::Identifier(id) = *x {
-                Ok(Self::CompareVal{ field: id.value, value:TypePrimitive::Bool(true), comparison: [0, 0] })
-            } else {
-                Err(RuntimeMsg(format!("Unsupported SQL IS TRUE syntax: {}", x)))
-            },
-            Expr::IsNotTrue(x) => if let Expr::Identifier(id) = *x {
-                Ok(Self::CompareVal{ field: id.value, value:TypePrimitive::Bool(true), comparison: [1, -1] })
-            } else {
-                Err(RuntimeMsg(format!("Unsupported SQL IS NOT TRUE syntax: {}", x)))
-            },
-            Expr::IsNull(x) => if let Expr::Identifier(id) = *x {
-                Ok(Self::CompareVal{ field: id.value, value:TypePrimitive::Empty, comparison: [0, 0] })
-            } else {
-                Err(RuntimeMsg(format!("Unsupported SQL IS NULL syntax: {}", x)))
-            },
-            Expr::IsNotNull(x) => if let Expr::Identifier(id) = *x {
-                Ok(Self::CompareVal{ field: id.value, value:TypePrimitive::Empty, comparison: [1, -1] })
-            } else {
-                Err(RuntimeMsg(format!("Unsupported SQL IS NOT NULL syntax: {}", x)))
+            Expr::IsFalse(x) => Self::compare_to(*x, TypePrimitive::Bool(false), [0, 0]),
+            Expr::IsNotFalse(x) => Self::compare_to(*x, TypePrimitive::Bool(false), [1, -1]),
+            Expr::IsTrue(x) => Self::compare_to(*x, TypePrimitive::Bool(true), [0, 0]),
+            Expr::IsNotTrue(x) => Self::compare_to(*x, TypePrimitive::Bool(true), [1, -1]),
+            Expr::IsNull(x) => Self::compare_to(*x, TypePrimitive::Empty, [0, 0]),
+            Expr::IsNotNull(x) => Self::compare_to(*x, TypePrimitive::Empty, [1, -1]),
+            Expr::Like { negated, expr, pattern, escape_char } => match *pattern {
+                Expr::Value(Value::SingleQuotedString(pattern)) => {
+                    let pattern = if let Some(glob) = pattern.strip_prefix(GLOB_MARKER) {
+                        if escape_char.is_some() {
+                            return Err(RuntimeMsg("Unsupported SQL syntax: ESCAPE with GLOB".to_owned()));
+                        }
+                        WildcardPattern::glob(glob)?
+                    } else {
+                        WildcardPattern::like(&pattern, escape_char)?
+                    };
+                    Ok(Self::Like{ operand: Operand::from_parsed(*expr)?, negated: negated, pattern: pattern })
+                },
+                y => Err(RuntimeMsg(format!("Unsupported SQL LIKE syntax: {} LIKE {}", expr, y)))
             },
-            Expr::Like { negated, expr, pattern, .. } => match (*expr, *pattern) {
-                (Expr::Identifier(expr), Expr::Value(Value::SingleQuotedString(pattern))) =>
-                    Ok(Self::Like{ field: expr.value, negated: negated, pattern: LikePattern::from_string(pattern) }),
-                (x, y) => Err(RuntimeMsg(format!("Unsupported SQL LIKE syntax: {} LIKE {}", x, y)))
+            Expr::ILike { negated, expr, pattern, escape_char } => match *pattern {
+                Expr::Value(Value::SingleQuotedString(pattern)) =>
+                    Ok(Self::Like{ operand: Operand::from_parsed(*expr)?, negated: negated, pattern: WildcardPattern::like(&pattern, escape_char)? }),
+                y => Err(RuntimeMsg(format!("Unsupported SQL ILIKE syntax: {} ILIKE {}", expr, y)))
             },
-            Expr::ILike { negated, expr, pattern, .. } => match (*expr, *pattern) {
-                (Expr::Identifier(expr), Expr::Value(Value::SingleQuotedString(pattern))) =>
-                    Ok(Self::Like{ field: expr.value, negated: negated, pattern: LikePattern::from_string(pattern) }),
-                (x, y) => Err(RuntimeMsg(format!("Unsupported SQL ILIKE syntax: {} ILIKE {}", x, y)))
+            Expr::InList { expr, list, negated } => Ok(Self::In {
+                operand: Operand::from_parsed(*expr)?,
+                values: list.into_iter().map(Operand::from_parsed).collect::<Result<_, _>>()?,
+                negated: negated,
+            }),
+            Expr::Between { expr, negated, low, high } => {
+                let operand = Operand::from_parsed(*expr)?;
+                let between = Self::And {
+                    a: Box::new(Self::Compare { a: operand.clone(), b: Operand::from_parsed(*low)?, comparison: [1, 0] }),
+                    b: Box::new(Self::Compare { a: operand, b: Operand::from_parsed(*high)?, comparison: [-1, 0] }),
+                };
+                if negated {
+                    Ok(Self::Not(Box::new(between)))
+                } else {
+                    Ok(between)
+                }
             },
+            Expr::UnaryOp { op: UnaryOperator::Not, expr } => Ok(Self::Not(Box::new(Self::from_parsed(*expr)?))),
             Expr::Nested(x) => Self::from_parsed(*x),
             Expr::BinaryOp { left, op, right } => {
                 if let BinaryOperator::And = op {
@@ -298,21 +502,11 @@ impl MatchRule {
                 } else if let BinaryOperator::Or = op {
                     Ok(Self::Or { a: Box::new(Self::from_parsed(*left)?), b: Box::new(Self::from_parsed(*right)?) })
                 } else {
-                    match (*left, *right) {
-                        (Expr::Identifier(left), Expr::Value(right)) =>
-                            Ok(Self::CompareVal {
-                                field: left.value,
-                                value: value_to_primitive(right)?,
-                                comparison: binary_op_to_compare(op)?,
-                            }),
-                        (Expr::Identifier(left), Expr::Identifier(right)) =>
-                            Ok(Self::CompareFields {
-                                field_a: left.value,
-                                field_b: right.value,
-                                comparison: binary_op_to_compare(op)?,
-                            }),
-                        (x, y) => Err(RuntimeMsg(format!("Unsupported SQL operator syntax: {} {} {}", x, op, y)))
-                    }
+                    Ok(Self::Compare {
+                        comparison: binary_op_to_compare(op)?,
+                        a: Operand::from_parsed(*left)?,
+                        b: Operand::from_parsed(*right)?,
+                    })
                 }
             },
             x => Err(RuntimeMsg(format!("Unsupported SQL WHERE syntax: {}", x)))
@@ -345,94 +539,162 @@ fn value_to_primitive(val: Value) -> Result<TypePrimitive, RuntimeMsg> {
     }
 }
 
+/// LIKE (case-insensitive, `%` and `_`) or GLOB (case-sensitive, `*`, `?` and `[...]`) pattern
 #[derive(Debug, Clone)]
-enum LikePattern {
-    EndsWith(String),
-    StartWith(String),
-    Contains(String),
-    Is(String),
+struct WildcardPattern {
+    regex: Regex,
 }
 
-impl LikePattern {
+impl WildcardPattern {
     #[inline]
     fn is_match(&self, text: &str) -> bool {
-        match self {
-            Self::EndsWith(p) => text.to_lowercase().ends_with(p),
-            Self::StartWith(p) => text.to_lowercase().starts_with(p),
-            Self::Contains(p) => text.to_lowercase().contains(p),
-            Self::Is(p) => &text.to_lowercase() == p,
+        self.regex.is_match(text)
+    }
+
+    fn like(pattern: &str, escape: Option<char>) -> Result<Self, RuntimeMsg> {
+        let mut re = "(?is)^".to_owned();
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                // the character after the escape character is matched literally, even % and _
+                c if Some(c) == escape => match chars.next() {
+                    Some(c) => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+                    None => return Err(RuntimeMsg(format!("Invalid SQL pattern: `{}` ends with the escape character", pattern))),
+                },
+                '%' => re.push_str(".*"),
+                '_' => re.push('.'),
+                c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+            }
         }
+        re.push('$');
+        Self::compile(&re)
     }
 
-    #[inline]
-    fn from_string(pattern: String) -> Self {
-        match (pattern.starts_with('%'), pattern.ends_with('%')) {
-            (false, true) => Self::EndsWith(pattern[..pattern.len()-1].to_owned()),
-            (true, false) => Self::StartWith(pattern[1..].to_owned()),
-            (true, true) => Self::Contains(pattern[1..pattern.len()-1].to_owned()),
-            (false, false) => Self::Is(pattern),
+    fn glob(pattern: &str) -> Result<Self, RuntimeMsg> {
+        let mut re = "(?s)^".to_owned();
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '*' => re.push_str(".*"),
+                '?' => re.push('.'),
+                '[' => {
+                    // character class, where a leading ^ negates and a leading ] is literal
+                    let mut end = i + 1;
+                    if end < chars.len() && chars[end] == '^' {
+                        end += 1;
+                    }
+                    if end < chars.len() && chars[end] == ']' {
+                        end += 1;
+                    }
+                    while end < chars.len() && chars[end] != ']' {
+                        end += 1;
+                    }
+                    if end >= chars.len() {
+                        // unterminated, so not a class
+                        re.push_str(r"\[");
+                    } else {
+                        re.push('[');
+                        let mut start = i + 1;
+                        if chars[start] == '^' {
+                            re.push('^');
+                            start += 1;
+                        }
+                        for c in &chars[start..end] {
+                            match c {
+                                '\\' | '[' | ']' | '&' | '~' | '^' => {
+                                    re.push('\\');
+                                    re.push(*c);
+                                },
+                                c => re.push(*c),
+                            }
+                        }
+                        re.push(']');
+                        i = end;
+                    }
+                },
+                c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+            }
+            i += 1;
         }
+        re.push('$');
+        Self::compile(&re)
+    }
+
+    fn compile(re: &str) -> Result<Self, RuntimeMsg> {
+        Ok(Self {
+            regex: Regex::new(re).map_err(|e| RuntimeMsg(format!("Invalid SQL pattern: {}", e)))?,
+        })
     }
 }
 
 #[derive(Debug, Clone)]
-enum SortRule {
-    Ascending(String),
-    Descending(String),
+struct SortKey {
+    operand: Operand,
+    descending: bool,
+    nulls_first: bool,
+}
+
+/// ORDER BY keys, in order of priority
+#[derive(Debug, Clone)]
+struct SortRule {
+    keys: Vec<SortKey>,
 }
 
 impl SortRule {
     #[inline]
     fn sort_vecdeque(&self, list: &mut VecDeque<IteratorItem>) {
         let buffer = list.make_contiguous();
-        match self {
-            Self::Ascending(field) => {
-                buffer.sort_by(|b, a| {
-                    if let Ok(a) = a {
-                        if let Some(a_field) = a.field(field) {
-                            if let Ok(b) = b {
-                                if let Some(b_field) = b.field(field) {
-                                    return a_field
-                                        .for_compare()
-                                        .partial_cmp(&b_field.for_compare())
-                                        .unwrap_or(std::cmp::Ordering::Equal);
-                                }
-                            }
-                        }
+        buffer.sort_by(|a, b| {
+            if let (Ok(a), Ok(b)) = (a, b) {
+                for key in self.keys.iter() {
+                    let ordering = key.compare(a, b);
+                    if ordering != Ordering::Equal {
+                        return ordering;
                     }
-                    std::cmp::Ordering::Equal
-                });
-            },
-            Self::Descending(field) => {
-                buffer.sort_by(|a, b| {
-                    if let Ok(a) = a {
-                        if let Some(a_field) = a.field(field) {
-                            if let Ok(b) = b {
-                                if let Some(b_field) = b.field(field) {
-                                    return a_field
-                                        .for_compare()
-                                        .partial_cmp(&b_field.for_compare())
-                                        .unwrap_or(std::cmp::Ordering::Equal);
-                                }
-                            }
-                        }
-                    }
-                    std::cmp::Ordering::Equal
-                });
+                }
             }
-        }
+            Ordering::Equal
+        });
     }
 
-    fn from_parsed(order: OrderByExpr) -> Result<Self, RuntimeMsg> {
-        let field = if let Expr::Identifier(id) = order.expr {
-            id.value
-        } else {
-            return Err(RuntimeMsg(format!("Unsupported SQL syntax: ORDER BY value must be a field identifier")));
-        };
-        if order.asc.unwrap_or(true) {
-            Ok(Self::Ascending(field))
-        } else {
-            Ok(Self::Descending(field))
+    fn from_parsed(order_by: Vec<OrderByExpr>) -> Result<Self, RuntimeMsg> {
+        let keys = order_by
+            .into_iter()
+            .map(|order| {
+                let descending = !order.asc.unwrap_or(true);
+                Ok(SortKey {
+                    operand: Operand::from_parsed(order.expr)?,
+                    descending: descending,
+                    // NULL is the smallest value in SQLite
+                    nulls_first: order.nulls_first.unwrap_or(!descending),
+                })
+            })
+            .collect::<Result<_, RuntimeMsg>>()?;
+        Ok(Self { keys: keys })
+    }
+}
+
+impl SortKey {
+    #[inline]
+    fn compare(&self, a: &Item, b: &Item) -> Ordering {
+        let a = self.operand.evaluate(a);
+        let b = self.operand.evaluate(b);
+        match (a.as_ref(), b.as_ref()) {
+            (TypePrimitive::Empty, TypePrimitive::Empty) => Ordering::Equal,
+            (TypePrimitive::Empty, _) => if self.nulls_first { Ordering::Less } else { Ordering::Greater },
+            (_, TypePrimitive::Empty) => if self.nulls_first { Ordering::Greater } else { Ordering::Less },
+            (a, b) => {
+                let ordering = a
+                    .for_compare()
+                    .partial_cmp(&b.for_compare())
+                    .unwrap_or(Ordering::Equal);
+                if self.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            }
         }
     }
 }
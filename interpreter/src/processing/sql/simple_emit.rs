@@ -1,11 +1,15 @@
+#[cfg(feature = "music_library")]
+use std::collections::VecDeque;
 use std::fmt::{Debug, Display, Error, Formatter};
 use std::iter::Iterator;
 
 use crate::Context;
 
 use crate::lang::{IteratorItem, Op, PseudoOp};
-use crate::lang::{RuntimeError, RuntimeOp, TypePrimitive};
+use crate::lang::{RuntimeError, RuntimeMsg, RuntimeOp, TypePrimitive};
 use crate::processing::general::FileIter;
+#[cfg(feature = "music_library")]
+use crate::Item;
 
 #[derive(Debug)]
 pub struct SimpleSqlQuery {
@@ -14,6 +18,10 @@ pub struct SimpleSqlQuery {
     field_name: String,
     val: String,
     has_tried: bool,
+    #[cfg(feature = "music_library")]
+    index: Option<super::SharedLibraryIndex>,
+    #[cfg(feature = "music_library")]
+    items_buffer: VecDeque<Item>,
 }
 
 impl SimpleSqlQuery {
@@ -24,8 +32,19 @@ impl SimpleSqlQuery {
             field_name: field.to_owned(),
             val: crate::lang::vocabulary::filters::utility::sanitise_string(value),
             has_tried: false,
+            #[cfg(feature = "music_library")]
+            index: None,
+            #[cfg(feature = "music_library")]
+            items_buffer: VecDeque::new(),
         }
     }
+
+    /// Answer the query from a library index instead of reading the music folder.
+    #[cfg(feature = "music_library")]
+    pub fn with_index(mut self, index: super::SharedLibraryIndex) -> Self {
+        self.index = Some(index);
+        self
+    }
 }
 
 impl Display for SimpleSqlQuery {
@@ -42,6 +61,10 @@ impl std::clone::Clone for SimpleSqlQuery {
             field_name: self.field_name.clone(),
             val: self.val.clone(),
             has_tried: self.has_tried,
+            #[cfg(feature = "music_library")]
+            index: self.index.clone(),
+            #[cfg(feature = "music_library")]
+            items_buffer: VecDeque::new(),
         }
     }
 }
@@ -50,6 +73,21 @@ impl Iterator for SimpleSqlQuery {
     type Item = IteratorItem;
 
     fn next(&mut self) -> Option<Self::Item> {
+        #[cfg(feature = "music_library")]
+        if let Some(index) = &self.index {
+            if !self.has_tried {
+                self.has_tried = true;
+                let items = match index.lock() {
+                    Ok(mut index) => index.like(&self.field_name, &self.val),
+                    Err(e) => Err(RuntimeMsg(format!("Library index lock failed: {}", e))),
+                };
+                match items {
+                    Ok(items) => self.items_buffer.extend(items),
+                    Err(e) => return Some(Err(e.with(RuntimeOp(PseudoOp::from_printable(self))))),
+                }
+            }
+            return self.items_buffer.pop_front().map(Ok);
+        }
         if self.file_iter.is_none() {
             if self.has_tried {
                 return None;
@@ -87,6 +125,14 @@ impl Iterator for SimpleSqlQuery {
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
+        #[cfg(feature = "music_library")]
+        if self.index.is_some() {
+            return if self.has_tried {
+                (self.items_buffer.len(), Some(self.items_buffer.len()))
+            } else {
+                (0, None) // the query hasn't run yet
+            };
+        }
         self.file_iter.as_ref().map(|x| x.size_hint()).unwrap_or_default()
     }
 }
@@ -1,12 +1,18 @@
 use std::fmt::{Debug, Display, Error, Formatter};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
+use crate::lang::LanguageDictionary;
 use crate::lang::Op;
 use crate::lang::RuntimeMsg;
 use crate::lang::TypePrimitive;
+use crate::tokens::{TokenReader, Tokenizer};
 use crate::Item;
 
+const PERSIST_FILENAME: &str = "variables.txt";
+
 #[derive(Debug)]
 pub enum Type {
     Op(Box<dyn Op>),
@@ -61,6 +67,22 @@ pub trait VariableStorer: Debug + Send {
     fn exists(&self, name: &str) -> bool {
         self.get_opt(name).is_some()
     }
+
+    /// Save the variable so that it is available in future sessions.
+    fn persist(&mut self, name: &str) -> Result<(), RuntimeMsg> {
+        Err(RuntimeMsg(format!(
+            "Cannot persist variable '{}': variable storage is not persistent",
+            name
+        )))
+    }
+
+    /// Stop saving a previously persisted variable.
+    fn forget(&mut self, name: &str) -> Result<(), RuntimeMsg> {
+        Err(RuntimeMsg(format!(
+            "Cannot forget variable '{}': variable storage is not persistent",
+            name
+        )))
+    }
 }
 
 #[derive(Default, Debug)]
@@ -120,3 +142,241 @@ impl VariableStorer for OpStorage {
         }
     }
 }
+
+/// Variable storage which saves persisted variables to a file.
+///
+/// Ops are saved as their script text and re-parsed when the file is loaded,
+/// so only ops which can be written out as valid MuSS are restored correctly.
+#[derive(Debug)]
+pub struct FileOpStorage {
+    storage: OpStorage,
+    // variable name -> last serialised value (used when the variable is temporarily removed)
+    persisted: HashMap<String, String>,
+    path: PathBuf,
+}
+
+impl FileOpStorage {
+    /// Load variables from the file, or start empty if it does not exist.
+    /// Entries which can no longer be parsed are dropped.
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let mut new = Self {
+            storage: OpStorage::default(),
+            persisted: HashMap::new(),
+            path: path.as_ref().to_path_buf(),
+        };
+        let data = match std::fs::read_to_string(&new.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(new),
+            Err(e) => return Err(e),
+        };
+        let dict = LanguageDictionary::standard();
+        for line in data.lines() {
+            if let Some((name, value)) = deserialise_line(line, &dict) {
+                new.storage.storage.insert(name.clone(), value);
+                new.persisted.insert(name, line.to_owned());
+            }
+        }
+        Ok(new)
+    }
+
+    pub fn default_path() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("./"))
+            .join("muss")
+            .join(PERSIST_FILENAME)
+    }
+
+    fn save(&mut self) -> Result<(), RuntimeMsg> {
+        for (name, line) in self.persisted.iter_mut() {
+            if let Some(value) = self.storage.storage.get(name) {
+                *line = serialise_line(name, value);
+            }
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| {
+                RuntimeMsg(format!("Cannot create folder `{}`: {}", parent.display(), e))
+            })?;
+        }
+        let mut names: Vec<&String> = self.persisted.keys().collect();
+        names.sort();
+        let mut data = String::new();
+        for name in names {
+            data.push_str(&self.persisted[name]);
+            data.push('\n');
+        }
+        std::fs::File::create(&self.path)
+            .and_then(|mut f| f.write_all(data.as_bytes()))
+            .map_err(|e| {
+                RuntimeMsg(format!(
+                    "Cannot save variables to `{}`: {}",
+                    self.path.display(),
+                    e
+                ))
+            })
+    }
+}
+
+impl Drop for FileOpStorage {
+    fn drop(&mut self) {
+        if !self.persisted.is_empty() {
+            self.save().unwrap_or(());
+        }
+    }
+}
+
+impl VariableStorer for FileOpStorage {
+    fn get_opt(&self, key: &str) -> Option<&Type> {
+        self.storage.get_opt(key)
+    }
+
+    fn get_mut_opt(&mut self, key: &str) -> Option<&mut Type> {
+        self.storage.get_mut_opt(key)
+    }
+
+    fn assign(&mut self, key: &str, item: Type) -> Result<(), RuntimeMsg> {
+        self.storage.assign(key, item)?;
+        if self.persisted.contains_key(key) {
+            self.save()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn declare(&mut self, key: &str, item: Type) -> Result<(), RuntimeMsg> {
+        if self.persisted.contains_key(key) {
+            // a script run again in a new session declares its persisted variables again
+            self.storage.swap(key, Some(item));
+            self.save()
+        } else {
+            self.storage.declare(key, item)
+        }
+    }
+
+    fn swap(&mut self, key: &str, item: Option<Type>) -> Option<Type> {
+        self.storage.swap(key, item)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<Type, RuntimeMsg> {
+        // removal is also used to temporarily take ownership of a variable,
+        // so persisted variables are only dropped by forget()
+        self.storage.remove(key)
+    }
+
+    fn persist(&mut self, key: &str) -> Result<(), RuntimeMsg> {
+        let line = serialise_line(key, self.storage.get(key)?);
+        self.persisted.insert(key.to_owned(), line);
+        self.save()
+    }
+
+    fn forget(&mut self, key: &str) -> Result<(), RuntimeMsg> {
+        if self.persisted.remove(key).is_some() {
+            self.save()
+        } else {
+            Err(RuntimeMsg(format!(
+                "Cannot forget non-persisted variable '{}'",
+                key
+            )))
+        }
+    }
+}
+
+// one variable per line: name<TAB>kind<TAB>value...
+fn serialise_line(name: &str, value: &Type) -> String {
+    match value {
+        Type::Op(op) => format!("{}\top\t{}", escape(name), escape(&op.to_string())),
+        Type::Primitive(p) => format!("{}\tprimitive\t{}", escape(name), serialise_primitive(p)),
+        Type::Item(item) => {
+            let mut fields: Vec<&String> = item.iter().collect();
+            fields.sort();
+            let mut line = format!("{}\titem", escape(name));
+            for field in fields {
+                line.push('\t');
+                line.push_str(&escape(field));
+                line.push('\t');
+                line.push_str(&serialise_primitive(item.field(field).unwrap()));
+            }
+            line
+        }
+    }
+}
+
+fn deserialise_line(line: &str, dict: &LanguageDictionary) -> Option<(String, Type)> {
+    let mut parts = line.split('\t');
+    let name = unescape(parts.next()?);
+    let value = match parts.next()? {
+        "op" => {
+            let script = unescape(parts.next()?);
+            let mut tokenizer = Tokenizer::new(script.as_bytes());
+            let mut tokens = VecDeque::new();
+            tokenizer.next_statement(&mut tokens).ok()?;
+            Type::Op(dict.try_build_statement(&mut tokens).ok()?)
+        }
+        "primitive" => Type::Primitive(deserialise_primitive(parts.next()?)?),
+        "item" => {
+            let mut item = Item::new();
+            while let Some(field) = parts.next() {
+                item.set_field(&unescape(field), deserialise_primitive(parts.next()?)?);
+            }
+            Type::Item(item)
+        }
+        _ => return None,
+    };
+    Some((name, value))
+}
+
+fn serialise_primitive(p: &TypePrimitive) -> String {
+    match p {
+        TypePrimitive::String(s) => format!("s{}", escape(s)),
+        TypePrimitive::Int(i) => format!("i{}", i),
+        TypePrimitive::UInt(u) => format!("u{}", u),
+        TypePrimitive::Float(f) => format!("f{}", f),
+        TypePrimitive::Bool(b) => format!("b{}", b),
+        TypePrimitive::Empty => "e".to_owned(),
+    }
+}
+
+fn deserialise_primitive(s: &str) -> Option<TypePrimitive> {
+    let (kind, value) = s.split_at(s.char_indices().nth(1).map(|(i, _)| i).unwrap_or(s.len()));
+    match kind {
+        "s" => Some(TypePrimitive::String(unescape(value))),
+        "i" => value.parse().ok().map(TypePrimitive::Int),
+        "u" => value.parse().ok().map(TypePrimitive::UInt),
+        "f" => value.parse().ok().map(TypePrimitive::Float),
+        "b" => value.parse().ok().map(TypePrimitive::Bool),
+        "e" => Some(TypePrimitive::Empty),
+        _ => None,
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('t') => out.push('\t'),
+                Some('n') => out.push('\n'),
+                Some('r') => out.push('\r'),
+                Some(c) => out.push(c),
+                None => out.push('\\'),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
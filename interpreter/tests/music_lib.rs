@@ -1,5 +1,6 @@
 #[cfg(feature = "music_library")]
 mod music_lib_test {
+    use muss_interpreter::lang::db::*;
     use muss_interpreter::music::*;
 
     #[test]
@@ -8,4 +9,197 @@ mod music_lib_test {
         lib.read_path("/home/ngnius/Music", 10).unwrap();
         println!("generated library size: {}", lib.len());
     }
+
+    #[test]
+    fn update_library() {
+        let dir = std::env::temp_dir().join("muss_test_update_library");
+        std::fs::remove_dir_all(&dir).unwrap_or(());
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "not a song").unwrap();
+        std::fs::write(dir.join("b.txt"), "not a song either").unwrap();
+        let mut lib = Library::new();
+        lib.update_path(&dir, 10).unwrap();
+        assert!(lib.is_modified());
+        assert_eq!(lib.all_files().len(), 2);
+        lib.clear_modified();
+        // nothing has changed, so nothing is re-read
+        lib.update_path(&dir, 10).unwrap();
+        assert!(!lib.is_modified());
+        assert!(lib.changed_files().is_empty());
+        // deleted and new files are noticed
+        std::fs::remove_file(dir.join("a.txt")).unwrap();
+        std::fs::write(dir.join("c.txt"), "").unwrap();
+        lib.update_path(&dir, 10).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        assert!(lib.is_modified());
+        assert_eq!(lib.removed_files(), vec![dir.join("a.txt").as_path()]);
+        let changed: Vec<_> = lib
+            .changed_files()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(changed, vec![dir.join("c.txt").as_path()]);
+        assert_eq!(lib.all_files().len(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn small_library() -> Library {
+        let mut lib = Library::new();
+        lib.add_genre(DbGenreItem {
+            genre_id: 0,
+            title: "Funk".into(),
+        });
+        for (artist_id, name) in [(0, "Bruno Mars"), (1, "Anderson .Paak")] {
+            lib.add_artist(DbArtistItem {
+                artist_id,
+                name: name.into(),
+                genre: 0,
+            });
+        }
+        for (album_id, title) in [(0, "24K Magic"), (1, "An Evening with Silk Sonic")] {
+            lib.add_album(DbAlbumItem {
+                album_id,
+                title: title.into(),
+                metadata: 10 + album_id,
+                artist: album_id,
+                genre: 0,
+            });
+            lib.add_metadata(DbMetaItem {
+                meta_id: 10 + album_id,
+                plays: 0,
+                track: 0,
+                disc: 1,
+                duration: 0,
+                date: 2016 + 5 * album_id,
+                composer: None,
+                bitrate: None,
+                added: 0,
+            });
+        }
+        for (song_id, artist, album) in [(0, 0, 0), (1, 1, 1)] {
+            lib.add_song(DbMusicItem {
+                song_id,
+                title: format!("Song, \"{}\"", song_id),
+                artist,
+                album: Some(album),
+                filename: format!("/music/{}.flac", song_id),
+                metadata: song_id,
+                genre: 0,
+                album_artist: Some(artist),
+            });
+            lib.add_metadata(DbMetaItem {
+                meta_id: song_id,
+                plays: 3 + song_id,
+                track: 1 + song_id,
+                disc: 1,
+                duration: 200 + song_id,
+                date: 2016 + 5 * song_id,
+                composer: Some("Bruno Mars".into()),
+                bitrate: Some(320),
+                added: 1_600_000_000 + song_id,
+            });
+        }
+        lib
+    }
+
+    /// Song fields by filename, with artist, album and genre names instead of ids
+    fn song_fields(lib: &Library) -> Vec<String> {
+        let mut songs: Vec<String> = lib
+            .all_songs()
+            .into_iter()
+            .map(|song| {
+                let artist = |id: u64| {
+                    lib.all_artists()
+                        .into_iter()
+                        .find(|x| x.artist_id == id)
+                        .map(|x| x.name.clone())
+                };
+                let album = lib
+                    .all_albums()
+                    .into_iter()
+                    .find(|x| Some(x.album_id) == song.album)
+                    .unwrap();
+                let genre = lib
+                    .all_genres()
+                    .into_iter()
+                    .find(|x| x.genre_id == song.genre)
+                    .map(|x| x.title.clone());
+                let meta = lib
+                    .all_metadata()
+                    .into_iter()
+                    .find(|x| x.meta_id == song.metadata)
+                    .unwrap();
+                let album_meta = lib
+                    .all_metadata()
+                    .into_iter()
+                    .find(|x| x.meta_id == album.metadata)
+                    .unwrap();
+                format!(
+                    "{} {:?} {:?} {:?} {:?} {:?} {} {} {} {} {} {} {:?} {:?} {}",
+                    song.filename,
+                    song.title,
+                    artist(song.artist),
+                    song.album_artist.and_then(artist),
+                    album.title,
+                    genre,
+                    album_meta.date,
+                    meta.plays,
+                    meta.track,
+                    meta.disc,
+                    meta.duration,
+                    meta.date,
+                    meta.composer,
+                    meta.bitrate,
+                    meta.added,
+                )
+            })
+            .collect();
+        songs.sort();
+        songs
+    }
+
+    #[test]
+    fn export_import_library() {
+        let lib = small_library();
+        let expected = song_fields(&lib);
+        assert_eq!(expected.len(), 2);
+        let mut csv = Vec::new();
+        lib.export_csv(&mut csv).unwrap();
+        let mut csv_lib = Library::new();
+        csv_lib.import_csv(csv.as_slice()).unwrap();
+        assert_eq!(song_fields(&csv_lib), expected);
+        assert_eq!(csv_lib.all_artists().len(), 2);
+        assert_eq!(csv_lib.all_albums().len(), 2);
+        assert_eq!(csv_lib.all_genres().len(), 1);
+        #[cfg(feature = "export")]
+        {
+            let mut json = Vec::new();
+            lib.export_json(&mut json).unwrap();
+            let mut json_lib = Library::new();
+            json_lib.import_json(json.as_slice()).unwrap();
+            assert_eq!(song_fields(&json_lib), expected);
+            // ids are kept too, so everything else must match exactly
+            let mut json_again = Vec::new();
+            json_lib.export_json(&mut json_again).unwrap();
+            assert_eq!(json_again, json);
+        }
+    }
+
+    #[test]
+    fn remove_unused_entries() {
+        let mut lib = small_library();
+        lib.clear_modified();
+        lib.remove_path("/music/1.flac");
+        assert_eq!(lib.removed_songs(), vec![1]);
+        assert_eq!(lib.removed_albums(), vec![1]);
+        assert_eq!(lib.removed_artists(), vec![1]);
+        // the other song still has the genre
+        assert!(lib.removed_genres().is_empty());
+        assert_eq!(lib.all_albums().len(), 1);
+        assert_eq!(lib.all_artists().len(), 1);
+        lib.remove_path("/music/0.flac");
+        assert!(lib.all_albums().is_empty());
+        assert!(lib.all_artists().is_empty());
+        assert_eq!(lib.removed_genres(), vec![0]);
+    }
 }
@@ -114,11 +114,31 @@ fn execute_sql_line() -> Result<(), InterpreterError> {
     execute_single_line("sql(`SELECT * FROM songs WHERE artist IS NOT NULL AND format = 'flac' ORDER BY title DESC;`)", false, true)
 }
 
+#[test]
+fn execute_sql_dialect_line() -> Result<(), InterpreterError> {
+    execute_single_line("sql(`SELECT * FROM songs WHERE artist IN ('Bruno Mars', 'Nobody') AND NOT track BETWEEN 20 AND 30;`)", false, true)?;
+    execute_single_line("sql(`SELECT * FROM songs WHERE lower(artist) = 'bruno mars' AND length(title) > 1 ORDER BY album, track DESC LIMIT 5 OFFSET 1;`)", false, true)?;
+    execute_single_line("sql(`SELECT * FROM songs WHERE title GLOB '[A-Z]*' AND filename NOT GLOB '*.txt';`)", false, true)?;
+    // the escaped _ only matches itself, not the space in Bruno Mars
+    execute_single_line("sql(`SELECT * FROM songs WHERE artist LIKE 'Bruno!_Mars' ESCAPE '!';`)", true, true)?;
+    execute_single_line("sql(`SELECT * FROM songs WHERE artist = 'Bruno Mars' LIMIT 0;`)", true, true)
+}
+
 #[test]
 fn execute_simple_sql_line() -> Result<(), InterpreterError> {
     execute_single_line("song(`lov`)", false, true)
 }
 
+#[test]
+fn execute_repeated_sql_line() -> Result<(), InterpreterError> {
+    // later queries are answered from the library index built by the first
+    execute_single_line(
+        "union(artist(`bruno mars`), song(`lov`), sql(`SELECT * FROM songs WHERE artist = 'Bruno Mars' AND format = 'flac';`))",
+        false,
+        true,
+    )
+}
+
 #[test]
 fn execute_comment_line() -> Result<(), InterpreterError> {
     execute_single_line("// this is a comment", true, true)?;
@@ -150,6 +170,11 @@ fn execute_sql_init_line() -> Result<(), InterpreterError> {
         "sql_init(generate = false, folder = `/home/ngnius/Music`)",
         true,
         true,
+    )?;
+    execute_single_line(
+        "sql_init(generate = false, exclude = `audiobooks/`, exclude = `*.wav`)",
+        true,
+        true,
     )
 }
 
@@ -232,7 +257,17 @@ fn execute_files_line() -> Result<(), InterpreterError> {
         false,
         true,
     )?;
-    execute_single_line(r"files().(??)", false, true)
+    execute_single_line(r"files().(??)", false, true)?;
+    execute_single_line(
+        r"files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`, folder=`~/Music/MusicMP3/`, exclude=`*.mp3`, exclude=`!Finesse*`)",
+        false,
+        true,
+    )?;
+    execute_single_line(
+        r"files(folder=`~/Music/MusicFlac/Bruno Mars/24K Magic/`, exclude=`*`)",
+        true,
+        true,
+    )
 }
 
 #[test]
@@ -386,6 +421,11 @@ fn execute_blissfirstsort_line() -> Result<(), InterpreterError> {
         "files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`)~(~bliss_first)",
         false,
         true,
+    )?;
+    execute_single_line(
+        "files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`)~(advanced bliss_first loudness=0 cosine)",
+        false,
+        true,
     )
 }
 
@@ -400,15 +440,159 @@ fn execute_blissnextsort_line() -> Result<(), InterpreterError> {
         "files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`)~(~bliss_next)",
         false,
         true,
+    )?;
+    execute_single_line(
+        "files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`)~(advanced bliss_next tempo=2 mahalanobis)",
+        false,
+        true,
     )
 }
 
+// compares songs by the number in their file name, so similarity doesn't need real audio
+#[cfg(feature = "advanced")]
+#[derive(Debug)]
+struct PositionAnalyzer;
+
+#[cfg(feature = "advanced")]
+impl PositionAnalyzer {
+    fn position(item: &Item) -> Result<f64, lang::RuntimeMsg> {
+        item.field("filename")
+            .and_then(|x| x.to_owned().to_str())
+            .and_then(|x| {
+                std::path::Path::new(&x)
+                    .file_stem()
+                    .and_then(|x| x.to_str()?.parse().ok())
+            })
+            .ok_or_else(|| lang::RuntimeMsg("No position in filename".into()))
+    }
+}
+
+#[cfg(feature = "advanced")]
+impl processing::advanced::MusicAnalyzer for PositionAnalyzer {
+    fn prepare_distance(&mut self, _from: &Item, _to: &Item) -> Result<(), lang::RuntimeMsg> {
+        Ok(())
+    }
+
+    fn prepare_item(&mut self, _item: &Item) -> Result<(), lang::RuntimeMsg> {
+        Ok(())
+    }
+
+    fn get_distance(&mut self, from: &Item, to: &Item) -> Result<f64, lang::RuntimeMsg> {
+        Ok((Self::position(from)? - Self::position(to)?).abs())
+    }
+
+    fn get_custom_distance(
+        &mut self,
+        from: &Item,
+        to: &Item,
+        _compare: processing::advanced::MusicAnalyzerDistance,
+    ) -> Result<f64, lang::RuntimeMsg> {
+        self.get_distance(from, to)
+    }
+
+    fn get_features(&mut self, item: &Item) -> Result<Vec<(&'static str, f64)>, lang::RuntimeMsg> {
+        Ok(vec![("tempo", Self::position(item)?)])
+    }
+
+    fn clear_cache(&mut self) -> Result<(), lang::RuntimeMsg> {
+        Ok(())
+    }
+}
+
+// songs at positions 0, 2, 5, 9 and 12 in one folder, and one at position 8 in another
+#[cfg(feature = "advanced")]
+fn write_position_files(name: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+    let dir = std::env::temp_dir().join(format!("muss_test_{}", name));
+    let other_dir = std::env::temp_dir().join(format!("muss_test_{}_other", name));
+    for (folder, positions) in [(&dir, &[0, 2, 5, 9, 12][..]), (&other_dir, &[8][..])] {
+        std::fs::remove_dir_all(folder).unwrap_or(());
+        std::fs::create_dir_all(folder).unwrap();
+        for position in positions {
+            std::fs::write(folder.join(format!("{}.txt", position)), "").unwrap();
+        }
+    }
+    (dir, other_dir)
+}
+
+// the positions of the songs a script produces, when compared with `PositionAnalyzer`
+#[cfg(feature = "advanced")]
+fn execute_positions(script: &str) -> Result<Vec<u64>, InterpreterError> {
+    println!("--- Executing MPS code: '{}' ---", script);
+    let mut interpreter = Interpreter::with_stream(Cursor::new(script));
+    interpreter.set_context(Context::default().with_analyzer(PositionAnalyzer));
+    let mut positions = Vec::new();
+    for result in interpreter {
+        positions.push(PositionAnalyzer::position(&result?).unwrap() as u64);
+    }
+    Ok(positions)
+}
+
 #[test]
 fn execute_radiosort_line() -> Result<(), InterpreterError> {
     execute_single_line(
         "files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`)~(~radio)",
         false,
         true,
+    )?;
+    execute_single_line(
+        "files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`)~(~radio seed .title like \"magic\" max_distance=2.5)",
+        false,
+        true,
+    )?;
+    execute_single_line(
+        "files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`)~(~radio tempo from file(`~/Music/MusicFlac/Bruno Mars/24K Magic/01 24K Magic.flac`))",
+        false,
+        true,
+    )?;
+    execute_single_line(
+        "files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`)~(~radio tempo=2 chroma=0.5 cosine)",
+        false,
+        true,
+    )
+}
+
+#[cfg(feature = "advanced")]
+#[test]
+fn execute_radioorder_line() -> Result<(), InterpreterError> {
+    let (dir, other_dir) = write_position_files("radio_order");
+    let (dir, other_dir) = (dir.display(), other_dir.display());
+    // each song is the one closest to the song before it
+    assert_eq!(
+        execute_positions(&format!("files(`{}`)~(~radio from `{}/5.txt`)", dir, dir))?,
+        vec![5, 2, 0, 9, 12]
+    );
+    // a seed from outside the iterable is not played, but the radio starts closest to it
+    assert_eq!(
+        execute_positions(&format!(
+            "files(`{}`)~(~radio from `{}/8.txt`)",
+            dir, other_dir
+        ))?,
+        vec![9, 12, 5, 2, 0]
+    );
+    // songs further than max_distance from the last one end the radio
+    assert_eq!(
+        execute_positions(&format!(
+            "files(`{}`)~(~radio from `{}/5.txt` max_distance=4.5)",
+            dir, dir
+        ))?,
+        vec![5, 2, 0]
+    );
+    Ok(())
+}
+
+#[test]
+fn execute_similaritypreset_line() -> Result<(), InterpreterError> {
+    execute_single_line(
+        "preset dance = tempo=2 loudness=0.5 cosine;
+preset loud = preset=dance loudness=3;
+files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`)~(~radio preset=loud)",
+        false,
+        true,
+    )?;
+    execute_single_line(
+        "preset dance = tempo=2; files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`)~(advanced bliss_next preset=dance euclidean)",
+        false,
+        true,
     )
 }
 
@@ -505,6 +689,88 @@ fn execute_intersectionfn_line() -> Result<(), InterpreterError> {
     )
 }
 
+#[test]
+fn execute_clusterfn_line() -> Result<(), InterpreterError> {
+    execute_single_line(
+        "cluster(files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`))",
+        false,
+        true,
+    )?;
+    execute_single_line(
+        "cluster(files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`), k = 2).(.cluster == 0)~(.cluster_distance)",
+        false,
+        true,
+    )?;
+    execute_single_line(
+        "cluster(union(empty(), files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`)), 3)",
+        false,
+        true,
+    )
+}
+
+#[cfg(feature = "advanced")]
+#[test]
+fn execute_clusterorder_line() -> Result<(), InterpreterError> {
+    let (dir, _) = write_position_files("cluster_order");
+    let dir = dir.display();
+    // without parameters, there are more clusters than songs
+    assert_eq!(
+        execute_positions(&format!("cluster(files(`{}`))", dir))?.len(),
+        5
+    );
+    let mut near = execute_positions(&format!("cluster(files(`{}`), k = 2).(.cluster == 0)", dir))?;
+    near.sort_unstable();
+    assert_eq!(near, vec![0, 2, 5]);
+    let mut far = execute_positions(&format!("cluster(files(`{}`), 2).(.cluster == 1)", dir))?;
+    far.sort_unstable();
+    assert_eq!(far, vec![9, 12]);
+    Ok(())
+}
+
+#[test]
+fn execute_pathfn_line() -> Result<(), InterpreterError> {
+    execute_single_line(
+        "path(files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`), from = file(`~/Music/MusicFlac/Bruno Mars/24K Magic/01 24K Magic.flac`), to = file(`~/Music/MusicFlac/Bruno Mars/24K Magic/09 Too Good to Say Goodbye.flac`), length = 5)",
+        false,
+        true,
+    )?;
+    execute_single_line(
+        "path(empty(), from = `~/Music/MusicFlac/Bruno Mars/24K Magic/01 24K Magic.flac`, to = `~/Music/MusicFlac/Bruno Mars/24K Magic/09 Too Good to Say Goodbye.flac`)",
+        false,
+        true,
+    )
+}
+
+#[cfg(feature = "advanced")]
+#[test]
+fn execute_pathorder_line() -> Result<(), InterpreterError> {
+    let (dir, _) = write_position_files("path_order");
+    let dir = dir.display();
+    // each step moves closer to the last song, a bit at a time
+    assert_eq!(
+        execute_positions(&format!(
+            "path(files(`{}`), from = `{}/0.txt`, to = `{}/12.txt`, length = 4)",
+            dir, dir, dir
+        ))?,
+        vec![0, 2, 5, 12]
+    );
+    assert_eq!(
+        execute_positions(&format!(
+            "path(files(`{}`), from = `{}/0.txt`, to = `{}/12.txt`, length = 5)",
+            dir, dir, dir
+        ))?,
+        vec![0, 2, 5, 9, 12]
+    );
+    assert_eq!(
+        execute_positions(&format!(
+            "path(files(`{}`), from = `{}/0.txt`, to = `{}/12.txt`, length = 2)",
+            dir, dir, dir
+        ))?,
+        vec![0, 12]
+    );
+    Ok(())
+}
+
 #[test]
 fn execute_declareitemop_line() -> Result<(), InterpreterError> {
     execute_single_line(
@@ -835,6 +1101,17 @@ fn execute_fileitemop_line() -> Result<(), InterpreterError> {
     )
 }
 
+#[test]
+fn execute_featuresitemop_line() -> Result<(), InterpreterError> {
+    execute_single_line(
+        "files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`).{
+    item = features(item),
+}.(.tempo < 1000)~(.loudness)",
+        false,
+        true,
+    )
+}
+
 #[test]
 fn execute_emptiesop_line() -> Result<(), InterpreterError> {
     execute_single_line(
@@ -885,3 +1162,59 @@ fn execute_playlist_line() -> Result<(), InterpreterError> {
     )?;
     execute_single_line(r"playlist(`~/Music/Playlists/empty.m3u8`)", true, true)
 }
+
+#[test]
+fn execute_persistvariable_line() -> Result<(), InterpreterError> {
+    let path = std::env::temp_dir().join("muss_test_persist_variables.txt");
+    std::fs::remove_file(&path).unwrap_or(());
+    let mut interpreter = Interpreter::with_stream(Cursor::new(
+        "let songs = files(`~/Music/MusicFlac/Bruno Mars/24K Magic/`); let answer = 42; persist songs; persist answer;",
+    ));
+    interpreter.set_context(
+        Context::default().with_variables(processing::general::FileOpStorage::open(&path).unwrap()),
+    );
+    for result in interpreter {
+        result?;
+    }
+    // new session
+    let mut interpreter = Interpreter::with_stream(Cursor::new("forget answer; songs"));
+    interpreter.set_context(
+        Context::default().with_variables(processing::general::FileOpStorage::open(&path).unwrap()),
+    );
+    let mut count = 0;
+    for result in interpreter {
+        result?;
+        count += 1;
+    }
+    assert_ne!(count, 0);
+    // answer is no longer persisted
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(saved.contains("songs"));
+    assert!(!saved.contains("answer"));
+    std::fs::remove_file(&path).unwrap_or(());
+    Ok(())
+}
+
+#[test]
+fn execute_redeclarepersisted_line() -> Result<(), InterpreterError> {
+    let path = std::env::temp_dir().join("muss_test_redeclare_variables.txt");
+    std::fs::remove_file(&path).unwrap_or(());
+    for script in ["let answer = 42; persist answer;", "let answer = 43;"] {
+        let mut interpreter = Interpreter::with_stream(Cursor::new(script));
+        interpreter.set_context(
+            Context::default()
+                .with_variables(processing::general::FileOpStorage::open(&path).unwrap()),
+        );
+        for result in interpreter {
+            result?;
+        }
+    }
+    // the new value replaces the old one, and it's still persisted
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(saved.contains("43"));
+    assert!(!saved.contains("42"));
+    std::fs::remove_file(&path).unwrap_or(());
+    // only a missing file is treated as having no variables
+    assert!(processing::general::FileOpStorage::open(std::env::temp_dir()).is_err());
+    Ok(())
+}
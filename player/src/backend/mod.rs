@@ -0,0 +1,96 @@
+//! Audio output for a Player.
+
+#[cfg(feature = "mpd")]
+mod mpd_backend;
+mod null_backend;
+mod rodio_backend;
+
+#[cfg(feature = "mpd")]
+pub use mpd_backend::MpdBackend;
+pub use null_backend::{BackendAction, NullBackend, NullHandle};
+pub use rodio_backend::RodioBackend;
+
+use std::time::Duration;
+
+use rodio::Source;
+
+use super::{PlayerError, SongDecoder};
+
+/// Changes decoded audio before it's played, for backends which decode songs themselves.
+pub type Modifier<'a> = &'a dyn Fn(SongDecoder) -> Box<dyn Source<Item = i16> + Send>;
+
+/// Somewhere for a Player to play songs.
+/// Songs are queued one after the other, and the first song in the queue is the one playing.
+pub trait Backend {
+    /// Queue a song to play after the songs which are already queued.
+    /// This returns the length of the song, when it's known.
+    fn append(&mut self, filename: &str, modify: Modifier)
+        -> Result<Option<Duration>, PlayerError>;
+
+    fn pause(&mut self) -> Result<(), PlayerError>;
+
+    fn resume(&mut self) -> Result<(), PlayerError>;
+
+    fn is_paused(&self) -> bool;
+
+    /// Stop playing and empty the queue.
+    fn stop(&mut self) -> Result<(), PlayerError>;
+
+    /// Empty the queue, keeping the volume and whether playback is paused.
+    fn clear(&mut self) -> Result<(), PlayerError>;
+
+    fn set_volume(&mut self, volume: f32) -> Result<(), PlayerError>;
+
+    fn volume(&self) -> f32;
+
+    /// Number of queued songs, including the one playing.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Playback time of the song which is playing, when the backend keeps track of it.
+    fn position(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Whether songs are decoded by this process, so that `append`'s modifier is used.
+    /// Otherwise playback progress has to come from `position` instead.
+    fn decodes(&self) -> bool {
+        true
+    }
+
+    /// Move within the song which is playing.
+    /// This returns false when the backend can't, so the song has to be queued again instead.
+    fn seek(&mut self, _position: Duration) -> Result<bool, PlayerError> {
+        Ok(false)
+    }
+
+    /// Drop the queued songs after the first `len` songs.
+    /// This returns false when the backend can't, so the queue has to be replaced instead.
+    fn truncate(&mut self, _len: usize) -> Result<bool, PlayerError> {
+        Ok(false)
+    }
+
+    fn sleep_until_end(&self) {
+        while !self.is_empty() {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    /// Keep playing the queued songs while fading them out, and start a new (empty) queue.
+    /// This returns false, leaving the queue alone, when the backend can't fade.
+    fn fade_out(&mut self, _fade: Duration) -> Result<bool, PlayerError> {
+        Ok(false)
+    }
+
+    fn is_fading(&self) -> bool {
+        false
+    }
+
+    /// This should be called regularly (several times a second) during playback.
+    fn update(&mut self) -> Result<(), PlayerError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,263 @@
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use mpd::status::State;
+use mpd::{Client, Song};
+
+use super::{Backend, Modifier};
+use crate::uri::Uri;
+use crate::{PlayerError, UriError};
+
+/// How often the server's status is checked when nothing else has been asked of the server
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Playback on an MPD server, which plays songs from its own library.
+/// Songs are queued after the songs which were already in the server's queue, which are left alone.
+/// Other MPD clients can still control playback, since the server's status is checked regularly.
+pub struct MpdBackend {
+    client: Client<TcpStream>,
+    // local songs in this folder are played from MPD's library
+    music_dir: Option<PathBuf>,
+    paused: bool,
+    volume: f32,
+    // songs in the server's queue before the first song queued by this backend
+    offset: u32,
+    // whether the server has started playing this backend's songs since they were last cleared
+    started: bool,
+    // from the last status update, since getting the status needs a request to the server
+    state: State,
+    queue_len: u32,
+    len: usize,
+    elapsed: Option<Duration>,
+    refreshed_at: Instant,
+}
+
+impl MpdBackend {
+    pub fn new(client: Client<TcpStream>) -> Result<Self, PlayerError> {
+        let mut backend = Self {
+            client: client,
+            music_dir: None,
+            paused: false,
+            volume: 1.0,
+            offset: 0,
+            started: false,
+            state: State::Stop,
+            queue_len: 0,
+            len: 0,
+            elapsed: None,
+            refreshed_at: Instant::now(),
+        };
+        backend.refresh()?;
+        backend.offset = backend.queue_len;
+        Ok(backend)
+    }
+
+    pub fn connect(addr: std::net::SocketAddr) -> Result<Self, PlayerError> {
+        Self::new(Client::connect(addr).map_err(PlayerError::from_err_mpd)?)
+    }
+
+    /// Play local songs in `music_dir` as the same songs in MPD's library.
+    /// This only works when it's the folder which MPD uses as its music directory.
+    pub fn set_music_dir<P: AsRef<Path>>(&mut self, music_dir: P) {
+        let music_dir = music_dir.as_ref();
+        self.music_dir = Some(
+            music_dir
+                .canonicalize()
+                .unwrap_or_else(|_| music_dir.to_owned()),
+        );
+    }
+
+    // path of a song in MPD's library
+    fn library_path(&self, filename: &str) -> Result<String, PlayerError> {
+        let uri = Uri::new(filename);
+        let path = match uri.scheme() {
+            Some(s) => match &s.to_lowercase() as &str {
+                "mpd:" => return Ok(uri.path().to_owned()),
+                "file:" => uri.without_scheme(),
+                scheme => return Err(UriError::Unsupported(scheme.to_owned()).into()),
+            },
+            //default
+            None => uri.path(),
+        };
+        let path = Path::new(path);
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+        self.music_dir
+            .as_ref()
+            .and_then(|music_dir| path.strip_prefix(music_dir).ok())
+            .and_then(|relative| relative.to_str())
+            // MPD uses forward slashes on every platform
+            .map(|relative| relative.replace(std::path::MAIN_SEPARATOR, "/"))
+            .ok_or_else(|| {
+                PlayerError::from_err_mpd(format!(
+                    "Cannot play `{}` with MPD since it's not in MPD's music folder",
+                    path.display()
+                ))
+            })
+    }
+
+    fn refresh(&mut self) -> Result<(), PlayerError> {
+        let status = self.client.status().map_err(PlayerError::from_err_mpd)?;
+        self.refreshed_at = Instant::now();
+        // finished songs stay in the server's queue, so only the songs from the current one onwards count
+        self.len = match &status.song {
+            Some(place) if place.pos >= self.offset => {
+                status.queue_len.saturating_sub(place.pos) as usize
+            }
+            None if self.started => 0,
+            // the server is playing (or stopped before) songs which were queued by something else
+            _ => status.queue_len.saturating_sub(self.offset) as usize,
+        };
+        self.queue_len = status.queue_len;
+        self.elapsed = status.song.and(status.elapsed);
+        self.state = status.state;
+        // other clients may have paused or changed the volume
+        match self.state {
+            State::Play => self.paused = false,
+            State::Pause => self.paused = true,
+            State::Stop => {}
+        }
+        if status.volume >= 0 {
+            self.volume = status.volume as f32 / 100.0;
+        }
+        Ok(())
+    }
+}
+
+impl Backend for MpdBackend {
+    fn append(
+        &mut self,
+        filename: &str,
+        _modify: Modifier,
+    ) -> Result<Option<Duration>, PlayerError> {
+        let path = self.library_path(filename)?;
+        //println!("Pushing {} into MPD queue", path);
+        let song = Song {
+            file: path,
+            ..Default::default()
+        };
+        let id = self.client.push(song).map_err(PlayerError::from_err_mpd)?;
+        self.refresh()?;
+        if self.state == State::Stop || !self.started {
+            // the server stops at the end of its queue, so it has to be started again
+            // (and it may be playing someone else's songs to begin with)
+            self.client.switch(id).map_err(PlayerError::from_err_mpd)?;
+            if self.paused {
+                self.client.pause(true).map_err(PlayerError::from_err_mpd)?;
+            }
+            self.started = true;
+            self.refresh()?;
+        }
+        let song = self
+            .client
+            .playlistid(id)
+            .map_err(PlayerError::from_err_mpd)?;
+        Ok(song.and_then(|song| song.duration))
+    }
+
+    fn pause(&mut self) -> Result<(), PlayerError> {
+        self.paused = true;
+        self.client.pause(true).map_err(PlayerError::from_err_mpd)?;
+        self.refresh()
+    }
+
+    fn resume(&mut self) -> Result<(), PlayerError> {
+        self.paused = false;
+        self.refresh()?;
+        match self.state {
+            State::Stop if !self.started && self.len != 0 => {
+                self.client
+                    .switch(self.offset)
+                    .map_err(PlayerError::from_err_mpd)?;
+                self.started = true;
+            }
+            State::Stop => {}
+            _ => self
+                .client
+                .pause(false)
+                .map_err(PlayerError::from_err_mpd)?,
+        }
+        self.refresh()
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    fn stop(&mut self) -> Result<(), PlayerError> {
+        self.client.stop().map_err(PlayerError::from_err_mpd)?;
+        self.clear()
+    }
+
+    fn clear(&mut self) -> Result<(), PlayerError> {
+        self.refresh()?;
+        // only this backend's songs, including the ones it has finished playing
+        if self.queue_len > self.offset {
+            self.client
+                .delete(self.offset..self.queue_len)
+                .map_err(PlayerError::from_err_mpd)?;
+        }
+        self.started = false;
+        self.refresh()
+    }
+
+    fn set_volume(&mut self, volume: f32) -> Result<(), PlayerError> {
+        self.volume = volume;
+        let percent = (volume * 100.0).round().clamp(0.0, 100.0) as i8;
+        self.client
+            .volume(percent)
+            .map_err(PlayerError::from_err_mpd)
+    }
+
+    fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn position(&self) -> Option<Duration> {
+        match self.state {
+            // the server has kept playing since the last status update
+            State::Play => self.elapsed.map(|x| x + self.refreshed_at.elapsed()),
+            _ => self.elapsed,
+        }
+    }
+
+    fn decodes(&self) -> bool {
+        false
+    }
+
+    fn seek(&mut self, position: Duration) -> Result<bool, PlayerError> {
+        self.refresh()?;
+        if self.elapsed.is_none() {
+            return Ok(false); // no song to seek in
+        }
+        self.client
+            .rewind(position)
+            .map_err(PlayerError::from_err_mpd)?;
+        self.refresh()?;
+        Ok(true)
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<bool, PlayerError> {
+        self.refresh()?;
+        if self.len > len {
+            let end = self.queue_len;
+            let start = end - (self.len - len) as u32;
+            self.client
+                .delete(start..end)
+                .map_err(PlayerError::from_err_mpd)?;
+            self.refresh()?;
+        }
+        Ok(true)
+    }
+
+    fn update(&mut self) -> Result<(), PlayerError> {
+        if self.refreshed_at.elapsed() >= POLL_INTERVAL {
+            self.refresh()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use super::{Backend, Modifier};
+use crate::PlayerError;
+
+/// Something a Player asked its backend to do.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BackendAction {
+    Append(String),
+    Pause,
+    Resume,
+    Stop,
+    Clear,
+    SetVolume(f32),
+}
+
+#[derive(Debug)]
+struct NullState {
+    queue: VecDeque<String>,
+    paused: bool,
+    volume: f32,
+    actions: Vec<BackendAction>,
+}
+
+/// Backend which doesn't play anything, for running a Player without an audio device (e.g. in tests).
+/// Songs aren't even opened. They stay queued until `NullHandle::finish` is called, and every action is recorded.
+pub struct NullBackend {
+    state: Arc<Mutex<NullState>>,
+}
+
+/// Access to a NullBackend after it's been given to a Player.
+#[derive(Clone)]
+pub struct NullHandle {
+    state: Arc<Mutex<NullState>>,
+}
+
+impl NullBackend {
+    pub fn new() -> (Self, NullHandle) {
+        let state = Arc::new(Mutex::new(NullState {
+            queue: VecDeque::new(),
+            paused: false,
+            volume: 1.0,
+            actions: Vec::new(),
+        }));
+        (
+            Self {
+                state: state.clone(),
+            },
+            NullHandle { state: state },
+        )
+    }
+
+    fn state(&self) -> MutexGuard<'_, NullState> {
+        // a panic while holding the lock doesn't leave the state half-changed
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn act(&mut self, action: BackendAction) -> MutexGuard<'_, NullState> {
+        let mut state = self.state();
+        state.actions.push(action);
+        state
+    }
+}
+
+impl Backend for NullBackend {
+    fn append(
+        &mut self,
+        filename: &str,
+        _modify: Modifier,
+    ) -> Result<Option<Duration>, PlayerError> {
+        self.act(BackendAction::Append(filename.to_owned()))
+            .queue
+            .push_back(filename.to_owned());
+        Ok(None)
+    }
+
+    fn pause(&mut self) -> Result<(), PlayerError> {
+        self.act(BackendAction::Pause).paused = true;
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<(), PlayerError> {
+        self.act(BackendAction::Resume).paused = false;
+        Ok(())
+    }
+
+    fn is_paused(&self) -> bool {
+        self.state().paused
+    }
+
+    fn stop(&mut self) -> Result<(), PlayerError> {
+        self.act(BackendAction::Stop).queue.clear();
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), PlayerError> {
+        self.act(BackendAction::Clear).queue.clear();
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: f32) -> Result<(), PlayerError> {
+        self.act(BackendAction::SetVolume(volume)).volume = volume;
+        Ok(())
+    }
+
+    fn volume(&self) -> f32 {
+        self.state().volume
+    }
+
+    fn len(&self) -> usize {
+        self.state().queue.len()
+    }
+
+    fn decodes(&self) -> bool {
+        false
+    }
+}
+
+impl NullHandle {
+    /// Finish the song which is playing, returning its filename.
+    pub fn finish(&self) -> Option<String> {
+        self.state().queue.pop_front()
+    }
+
+    /// Filenames of the queued songs, starting with the one playing.
+    pub fn queue(&self) -> Vec<String> {
+        self.state().queue.iter().cloned().collect()
+    }
+
+    /// Everything the backend has been asked to do, in order.
+    pub fn actions(&self) -> Vec<BackendAction> {
+        self.state().actions.clone()
+    }
+
+    /// Filenames of every song which was queued, in order (including songs queued again).
+    pub fn appended(&self) -> Vec<String> {
+        self.state()
+            .actions
+            .iter()
+            .filter_map(|action| match action {
+                BackendAction::Append(filename) => Some(filename.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state().paused
+    }
+
+    fn state(&self) -> MutexGuard<'_, NullState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rodio::{OutputStream, OutputStreamHandle, Sample, Sink, Source};
+
+use super::{Backend, Modifier};
+use crate::player::decode_file;
+use crate::uri::Uri;
+use crate::{PlayerError, UriError};
+
+/// Local playback through the default audio device.
+pub struct RodioBackend {
+    sink: Sink,
+    #[allow(dead_code)]
+    output_stream: OutputStream, // this is required for playback, so it must live as long as this struct instance
+    output_handle: OutputStreamHandle,
+    // whether to drop each song in the sink which hasn't finished yet, in queue order
+    dropped: VecDeque<Arc<AtomicBool>>,
+    // the previous songs while they fade out
+    fading: Option<Fading>,
+}
+
+// a queued song which ends early once it's dropped, since the sink can't remove songs itself
+struct Droppable<S: Source>
+where
+    S::Item: Sample,
+{
+    source: S,
+    dropped: Arc<AtomicBool>,
+}
+
+impl<S: Source> Iterator for Droppable<S>
+where
+    S::Item: Sample,
+{
+    type Item = S::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<S::Item> {
+        if self.dropped.load(Ordering::Relaxed) {
+            None
+        } else {
+            self.source.next()
+        }
+    }
+}
+
+impl<S: Source> Source for Droppable<S>
+where
+    S::Item: Sample,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.dropped.load(Ordering::Relaxed) {
+            Some(0)
+        } else {
+            self.source.current_frame_len()
+        }
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+struct Fading {
+    sink: Sink,
+    fade: Duration,
+    // fade time so far, excluding the time since it was last resumed
+    elapsed: Duration,
+    resumed_at: Option<Instant>,
+}
+
+impl Fading {
+    fn new(sink: Sink, fade: Duration) -> Self {
+        let resumed_at = if sink.is_paused() {
+            None
+        } else {
+            Some(Instant::now())
+        };
+        Self {
+            sink: sink,
+            fade: fade,
+            elapsed: Duration::ZERO,
+            resumed_at: resumed_at,
+        }
+    }
+
+    // the fade only progresses while playing
+    fn progress(&self) -> f32 {
+        let elapsed = match self.resumed_at {
+            Some(resumed_at) => self.elapsed + resumed_at.elapsed(),
+            None => self.elapsed,
+        };
+        elapsed.as_secs_f32() / self.fade.as_secs_f32()
+    }
+
+    fn pause(&mut self) {
+        if let Some(resumed_at) = self.resumed_at.take() {
+            self.elapsed += resumed_at.elapsed();
+        }
+        self.sink.pause();
+    }
+
+    fn play(&mut self) {
+        if self.resumed_at.is_none() {
+            self.resumed_at = Some(Instant::now());
+        }
+        self.sink.play();
+    }
+}
+
+impl RodioBackend {
+    pub fn new() -> Result<Self, PlayerError> {
+        let (stream, output_handle) =
+            OutputStream::try_default().map_err(PlayerError::from_err_playback)?;
+        Ok(Self {
+            sink: Sink::try_new(&output_handle).map_err(PlayerError::from_err_playback)?,
+            output_stream: stream,
+            output_handle: output_handle,
+            dropped: VecDeque::new(),
+            fading: None,
+        })
+    }
+
+    // forget songs which the sink is done with
+    fn prune_dropped(&mut self) {
+        while self.dropped.len() > self.sink.len() {
+            self.dropped.pop_front();
+        }
+    }
+
+    fn replace_sink(&mut self, sink: Sink) -> Sink {
+        self.dropped.clear();
+        std::mem::replace(&mut self.sink, sink)
+    }
+
+    // a new sink with the same volume and paused state
+    fn new_sink(&self) -> Result<Sink, PlayerError> {
+        let sink = Sink::try_new(&self.output_handle).map_err(PlayerError::from_err_playback)?;
+        if self.sink.is_paused() {
+            sink.pause();
+        }
+        sink.set_volume(self.sink.volume());
+        Ok(sink)
+    }
+}
+
+impl Backend for RodioBackend {
+    fn append(
+        &mut self,
+        filename: &str,
+        modify: Modifier,
+    ) -> Result<Option<Duration>, PlayerError> {
+        let uri = Uri::new(filename);
+        let path = match uri.scheme() {
+            Some(s) => match &s.to_lowercase() as &str {
+                "file:" => uri.without_scheme(),
+                scheme => return Err(UriError::Unsupported(scheme.to_owned()).into()),
+            },
+            //default
+            None => uri.path(),
+        };
+        let source = decode_file(path)?;
+        let duration = source.total_duration();
+        let dropped = Arc::new(AtomicBool::new(false));
+        self.sink.append(Droppable {
+            source: modify(source),
+            dropped: dropped.clone(),
+        });
+        self.prune_dropped();
+        self.dropped.push_back(dropped);
+        Ok(duration)
+    }
+
+    fn pause(&mut self) -> Result<(), PlayerError> {
+        if let Some(fading) = &mut self.fading {
+            fading.pause();
+        }
+        self.sink.pause();
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<(), PlayerError> {
+        if let Some(fading) = &mut self.fading {
+            fading.play();
+        }
+        self.sink.play();
+        Ok(())
+    }
+
+    fn is_paused(&self) -> bool {
+        self.sink.is_paused()
+    }
+
+    fn stop(&mut self) -> Result<(), PlayerError> {
+        if let Some(fading) = self.fading.take() {
+            fading.sink.stop();
+        }
+        self.sink.stop();
+        self.dropped.clear();
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), PlayerError> {
+        // the sink can't drop queued songs, so it's replaced instead
+        let sink = self.new_sink()?;
+        self.stop()?;
+        self.replace_sink(sink);
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: f32) -> Result<(), PlayerError> {
+        self.sink.set_volume(volume);
+        Ok(())
+    }
+
+    fn volume(&self) -> f32 {
+        self.sink.volume()
+    }
+
+    fn len(&self) -> usize {
+        // dropped songs are still in the sink until it gets to them
+        let finished = self.dropped.len().saturating_sub(self.sink.len());
+        self.dropped
+            .iter()
+            .skip(finished)
+            .filter(|dropped| !dropped.load(Ordering::Relaxed))
+            .count()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<bool, PlayerError> {
+        self.prune_dropped();
+        for dropped in self
+            .dropped
+            .iter()
+            .filter(|dropped| !dropped.load(Ordering::Relaxed))
+            .skip(len)
+        {
+            dropped.store(true, Ordering::Relaxed);
+        }
+        Ok(true)
+    }
+
+    fn sleep_until_end(&self) {
+        self.sink.sleep_until_end()
+    }
+
+    fn fade_out(&mut self, fade: Duration) -> Result<bool, PlayerError> {
+        // the old sink keeps playing the end of the current song while the new one starts the rest of the queue
+        let new_sink = self.new_sink()?;
+        let old_sink = self.replace_sink(new_sink);
+        if let Some(fading) = self.fading.replace(Fading::new(old_sink, fade)) {
+            fading.sink.stop();
+        }
+        Ok(true)
+    }
+
+    fn is_fading(&self) -> bool {
+        self.fading.is_some()
+    }
+
+    fn update(&mut self) -> Result<(), PlayerError> {
+        if let Some(fading) = &self.fading {
+            let progress = fading.progress();
+            if progress >= 1.0 || fading.sink.empty() {
+                fading.sink.stop();
+                self.fading = None;
+            } else if !self.sink.is_paused() {
+                fading
+                    .sink
+                    .set_volume(self.sink.volume() * (1.0 - progress));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn droppable(samples: Vec<i16>) -> (Droppable<SamplesBuffer<i16>>, Arc<AtomicBool>) {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let source = Droppable {
+            source: SamplesBuffer::new(1, 1000, samples),
+            dropped: dropped.clone(),
+        };
+        (source, dropped)
+    }
+
+    #[test]
+    fn drop_queued() {
+        // the same kind of queue as a sink, but without an audio device
+        let (queue, output) = rodio::queue::queue(false);
+        let (first, _) = droppable(vec![1, 2, 3]);
+        let (second, second_dropped) = droppable(vec![4, 5]);
+        let (third, _) = droppable(vec![6]);
+        queue.append(first);
+        queue.append(second);
+        queue.append(third);
+        second_dropped.store(true, Ordering::Relaxed);
+        assert_eq!(output.collect::<Vec<i16>>(), vec![1, 2, 3, 6]);
+    }
+}
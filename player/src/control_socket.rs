@@ -0,0 +1,490 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use muss_interpreter::{item_from_json, item_to_json, Context, Interpreter, Item};
+
+use super::player::LoopStatus;
+use super::player_wrapper::PlayerStatus;
+use super::{Controller, PlayerError};
+
+const SOCKET_FILENAME: &str = "muss.sock";
+
+// where to send the reply to a request
+type Reply = Sender<Result<Response, PlayerError>>;
+
+// builds the context that enqueued scripts run in
+type ContextBuilder = Arc<dyn Fn() -> Context + Send + Sync>;
+
+// something for the controller to do
+enum Pending {
+    Request(Request, Reply),
+    // the songs of a script from an enqueue request, once it's done running
+    Enqueue(Result<Vec<Item>, PlayerError>, Reply),
+    // stop answering requests on the serving thread
+    Stop,
+}
+
+/// How long to wait for playback to end before checking for requests again
+const SERVE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Something to ask a running player to do, sent as a line of JSON like `{"command": "play"}`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    Play,
+    Pause,
+    Next,
+    Previous,
+    /// Out of 1.0
+    SetVolume(f32),
+    Status,
+    Queue,
+    /// Run a script and play its songs after the queued songs
+    Enqueue(String),
+}
+
+/// What a running player replied to a request with.
+#[derive(Clone, Debug)]
+pub enum Response {
+    Done,
+    Status(PlayerStatus),
+    Queue(Vec<Item>),
+}
+
+/// Unix socket for controlling a Player from other processes.
+/// Each request is answered with a line of JSON, which is `{"ok": true}` plus any results,
+/// or `{"ok": false, "error": "..."}`.
+pub struct ControlServer {
+    path: PathBuf,
+    requests: Receiver<Pending>,
+    // for scripts to send their songs back once they're done running
+    enqueued: Sender<Pending>,
+    context: ContextBuilder,
+}
+
+/// A ControlServer answering requests on another thread (see ControlServer::serve_shared).
+/// Dropping this stops it and removes its socket.
+pub struct SharedControlServer {
+    stop: Sender<Pending>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ControlServer {
+    pub fn default_path() -> PathBuf {
+        match dirs::runtime_dir() {
+            Some(dir) => dir.join(SOCKET_FILENAME),
+            // unlike the runtime folder, every user shares the temporary folder
+            None => {
+                let user = std::env::var("USER")
+                    .or_else(|_| std::env::var("LOGNAME"))
+                    .unwrap_or_else(|_| {
+                        // whoever owns the home folder, which is almost always the user
+                        dirs::home_dir()
+                            .and_then(|home| fs::metadata(home).ok())
+                            .map(|x| x.uid().to_string())
+                            .unwrap_or_default()
+                    });
+                std::env::temp_dir().join(format!("muss-{}.sock", user))
+            }
+        }
+    }
+
+    pub fn bind<P: AsRef<Path>>(path: P) -> Result<Self, PlayerError> {
+        let path = path.as_ref();
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(PlayerError::from_file_err_playback(
+                    "Control socket is already in use",
+                    path,
+                ));
+            }
+            // left behind by a muss which didn't exit cleanly
+            fs::remove_file(path).map_err(|e| PlayerError::from_file_err_playback(e, path))?;
+        }
+        let listener =
+            UnixListener::bind(path).map_err(|e| PlayerError::from_file_err_playback(e, path))?;
+        let (tx, rx) = channel();
+        let enqueued = tx.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let tx = tx.clone();
+                thread::spawn(move || handle_connection(stream, tx));
+            }
+        });
+        Ok(Self {
+            path: path.to_owned(),
+            requests: rx,
+            enqueued: enqueued,
+            context: Arc::new(Context::default),
+        })
+    }
+
+    /// Run enqueued scripts in contexts made by `context`, instead of the default context,
+    /// so that they run like the script which is playing.
+    pub fn set_context<F: Fn() -> Context + Send + Sync + 'static>(&mut self, context: F) {
+        self.context = Arc::new(context);
+    }
+
+    /// Answer the requests received since the last time, using the controller.
+    /// This is non-blocking.
+    pub fn serve(&self, ctrl: &Controller) {
+        for pending in self.requests.try_iter() {
+            self.handle(pending, ctrl);
+        }
+    }
+
+    /// Answer requests on another thread, for when the controller is busy being used elsewhere (like in the REPL).
+    /// The controller is only locked while answering a request, and this keeps going until the returned SharedControlServer is dropped.
+    pub fn serve_shared(self, ctrl: Arc<Mutex<Controller>>) -> SharedControlServer {
+        let stop = self.enqueued.clone();
+        let thread = thread::spawn(move || {
+            for pending in self.requests.iter() {
+                if let Pending::Stop = pending {
+                    break;
+                }
+                let ctrl = ctrl.lock().unwrap_or_else(|e| e.into_inner());
+                self.handle(pending, &ctrl);
+            }
+        });
+        SharedControlServer {
+            stop: stop,
+            thread: Some(thread),
+        }
+    }
+
+    fn handle(&self, pending: Pending, ctrl: &Controller) {
+        match pending {
+            Pending::Request(Request::Enqueue(script), reply) => {
+                // scripts can take a while to run, and other requests shouldn't have to wait for them
+                let enqueued = self.enqueued.clone();
+                let context = self.context.clone();
+                thread::spawn(move || {
+                    let mut runner = Interpreter::with_stream(io::Cursor::new(script));
+                    runner.set_context(context());
+                    // the whole script runs first, so a broken script doesn't queue anything
+                    let items = runner
+                        .collect::<Result<Vec<Item>, _>>()
+                        .map_err(PlayerError::from_err_playback);
+                    enqueued.send(Pending::Enqueue(items, reply)).unwrap_or(());
+                });
+            }
+            Pending::Request(request, reply) => {
+                reply.send(execute(request, ctrl)).unwrap_or(());
+            }
+            Pending::Enqueue(items, reply) => {
+                let result = items.and_then(|items| {
+                    for item in items {
+                        ctrl.append(item)?;
+                    }
+                    Ok(Response::Done)
+                });
+                reply.send(result).unwrap_or(());
+            }
+            Pending::Stop => {}
+        }
+    }
+
+    /// Like Controller::wait_for_done(), but requests are answered while waiting.
+    pub fn serve_until_done(&self, ctrl: &Controller) -> Result<(), PlayerError> {
+        while !ctrl.wait_for_done_timeout(SERVE_INTERVAL)? {
+            self.serve(ctrl);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).unwrap_or(());
+    }
+}
+
+impl Drop for SharedControlServer {
+    fn drop(&mut self) {
+        self.stop.send(Pending::Stop).unwrap_or(());
+        // the ControlServer is dropped (removing the socket) once the serving thread is done
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap_or(());
+        }
+    }
+}
+
+/// Send a request to the player listening on the control socket at `path`.
+pub fn send_request<P: AsRef<Path>>(path: P, request: &Request) -> Result<Response, PlayerError> {
+    let path = path.as_ref();
+    let map_err = |e| PlayerError::from_file_err_playback(e, path);
+    let mut stream = UnixStream::connect(path).map_err(map_err)?;
+    writeln!(stream, "{}", request_to_json(request)).map_err(map_err)?;
+    let mut line = String::new();
+    io::BufReader::new(stream)
+        .read_line(&mut line)
+        .map_err(map_err)?;
+    let value: Value =
+        serde_json::from_str(&line).map_err(|e| PlayerError::from_file_err_playback(e, path))?;
+    response_from_json(&value)
+}
+
+fn handle_connection(stream: UnixStream, requests: Sender<Pending>) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    for line in io::BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let result = serde_json::from_str(&line)
+            .map_err(PlayerError::from_err_playback)
+            .and_then(|value| request_from_json(&value))
+            .and_then(|request| {
+                let (tx, rx) = channel();
+                requests
+                    .send(Pending::Request(request, tx))
+                    .map_err(PlayerError::from_err_playback)?;
+                rx.recv().map_err(PlayerError::from_err_playback)?
+            });
+        let value = match result {
+            Ok(response) => response_to_json(response),
+            Err(e) => json!({"ok": false, "error": e.to_string()}),
+        };
+        if writeln!(writer, "{}", value).is_err() {
+            break;
+        }
+    }
+}
+
+fn execute(request: Request, ctrl: &Controller) -> Result<Response, PlayerError> {
+    match request {
+        Request::Play => ctrl.play().map(|_| Response::Done),
+        Request::Pause => ctrl.pause().map(|_| Response::Done),
+        Request::Next => ctrl.next().map(|_| Response::Done),
+        Request::Previous => ctrl.previous().map(|_| Response::Done),
+        Request::SetVolume(volume) => ctrl.set_volume(volume).map(|_| Response::Done),
+        Request::Status => ctrl.status().map(Response::Status),
+        Request::Queue => ctrl.queue().map(Response::Queue),
+        // scripts are run by ControlServer::handle instead, without the controller
+        Request::Enqueue(_) => unreachable!(),
+    }
+}
+
+fn request_to_json(request: &Request) -> Value {
+    match request {
+        Request::Play => json!({"command": "play"}),
+        Request::Pause => json!({"command": "pause"}),
+        Request::Next => json!({"command": "next"}),
+        Request::Previous => json!({"command": "previous"}),
+        Request::SetVolume(volume) => json!({"command": "volume", "volume": volume}),
+        Request::Status => json!({"command": "status"}),
+        Request::Queue => json!({"command": "queue"}),
+        Request::Enqueue(script) => json!({"command": "enqueue", "script": script}),
+    }
+}
+
+fn request_from_json(value: &Value) -> Result<Request, PlayerError> {
+    let command = value
+        .get("command")
+        .and_then(|x| x.as_str())
+        .ok_or_else(|| PlayerError::from_err_playback("Missing request field `command`"))?;
+    Ok(match command {
+        "play" => Request::Play,
+        "pause" => Request::Pause,
+        "next" => Request::Next,
+        "previous" => Request::Previous,
+        "volume" => Request::SetVolume(
+            value
+                .get("volume")
+                .and_then(|x| x.as_f64())
+                .ok_or_else(|| PlayerError::from_err_playback("Missing request field `volume`"))?
+                as f32,
+        ),
+        "status" => Request::Status,
+        "queue" => Request::Queue,
+        "enqueue" => Request::Enqueue(
+            value
+                .get("script")
+                .and_then(|x| x.as_str())
+                .ok_or_else(|| PlayerError::from_err_playback("Missing request field `script`"))?
+                .to_owned(),
+        ),
+        _ => {
+            return Err(PlayerError::from_err_playback(format!(
+                "Unknown command `{}`",
+                command
+            )))
+        }
+    })
+}
+
+fn response_to_json(response: Response) -> Value {
+    match response {
+        Response::Done => json!({"ok": true}),
+        Response::Status(status) => json!({
+            "ok": true,
+            "status": {
+                "playing": status.playing.map(item_to_json),
+                "position": status.position.as_secs_f64(),
+                "duration": status.duration.map(|x| x.as_secs_f64()),
+                "paused": status.paused,
+                "volume": status.volume,
+                "loop": loop_status_name(status.loop_status),
+                "shuffle": status.shuffle,
+            },
+        }),
+        Response::Queue(items) => json!({
+            "ok": true,
+            "queue": items.into_iter().map(item_to_json).collect::<Vec<Value>>(),
+        }),
+    }
+}
+
+fn response_from_json(value: &Value) -> Result<Response, PlayerError> {
+    if value.get("ok").and_then(|x| x.as_bool()) != Some(true) {
+        let error = value
+            .get("error")
+            .and_then(|x| x.as_str())
+            .unwrap_or("Invalid response");
+        return Err(PlayerError::from_err_playback(error));
+    }
+    if let Some(status) = value.get("status") {
+        let seconds = |key: &str| {
+            status
+                .get(key)
+                .and_then(|x| x.as_f64())
+                .filter(|x| x.is_finite() && *x >= 0.0)
+                .map(Duration::from_secs_f64)
+        };
+        let playing = match status.get("playing") {
+            Some(Value::Null) | None => None,
+            Some(item) => Some(item_from_json(item).map_err(PlayerError::from_err_playback)?),
+        };
+        Ok(Response::Status(PlayerStatus {
+            playing: playing,
+            position: seconds("position").unwrap_or(Duration::ZERO),
+            duration: seconds("duration"),
+            paused: status
+                .get("paused")
+                .and_then(|x| x.as_bool())
+                .unwrap_or(false),
+            volume: status.get("volume").and_then(|x| x.as_f64()).unwrap_or(1.0) as f32,
+            loop_status: status
+                .get("loop")
+                .and_then(|x| x.as_str())
+                .and_then(loop_status_from_name)
+                .unwrap_or_default(),
+            shuffle: status
+                .get("shuffle")
+                .and_then(|x| x.as_bool())
+                .unwrap_or(false),
+        }))
+    } else if let Some(queue) = value.get("queue").and_then(|x| x.as_array()) {
+        queue
+            .iter()
+            .map(|x| item_from_json(x).map_err(PlayerError::from_err_playback))
+            .collect::<Result<Vec<Item>, _>>()
+            .map(Response::Queue)
+    } else {
+        Ok(Response::Done)
+    }
+}
+
+#[inline]
+fn loop_status_name(loop_status: LoopStatus) -> &'static str {
+    match loop_status {
+        LoopStatus::None => "none",
+        LoopStatus::Track => "track",
+        LoopStatus::Playlist => "playlist",
+    }
+}
+
+#[inline]
+fn loop_status_from_name(name: &str) -> Option<LoopStatus> {
+    match name {
+        "none" => Some(LoopStatus::None),
+        "track" => Some(LoopStatus::Track),
+        "playlist" => Some(LoopStatus::Playlist),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::NullBackend;
+    use crate::Player;
+
+    #[test]
+    fn request_json() -> Result<(), PlayerError> {
+        let requests = [
+            Request::Play,
+            Request::Pause,
+            Request::Next,
+            Request::Previous,
+            Request::SetVolume(0.5),
+            Request::Status,
+            Request::Queue,
+            Request::Enqueue("files();".to_owned()),
+        ];
+        for request in requests {
+            assert_eq!(request_from_json(&request_to_json(&request))?, request);
+        }
+        assert!(request_from_json(&json!({"command": "dance"})).is_err());
+        assert!(request_from_json(&json!({"command": "volume"})).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn response_json() -> Result<(), PlayerError> {
+        let status = PlayerStatus {
+            playing: Some(Item::new()),
+            position: Duration::from_secs(3),
+            duration: None,
+            paused: true,
+            volume: 0.25,
+            loop_status: LoopStatus::Playlist,
+            shuffle: true,
+        };
+        match response_from_json(&response_to_json(Response::Status(status)))? {
+            Response::Status(status) => {
+                assert_eq!(status.playing, Some(Item::new()));
+                assert_eq!(status.position, Duration::from_secs(3));
+                assert_eq!(status.duration, None);
+                assert!(status.paused);
+                assert_eq!(status.volume, 0.25);
+                assert_eq!(status.loop_status, LoopStatus::Playlist);
+                assert!(status.shuffle);
+            }
+            response => panic!("Expected status response, got {:?}", response),
+        }
+        let error = response_from_json(&json!({"ok": false, "error": "Nope"}));
+        assert_eq!(error.unwrap_err().to_string(), "PlaybackError: Nope");
+        Ok(())
+    }
+
+    #[test]
+    fn shared_stop() -> Result<(), PlayerError> {
+        let path = std::env::temp_dir().join("muss_test_shared_stop.sock");
+        let ctrl = Controller::create_repl(|| {
+            let (backend, _) = NullBackend::new();
+            Player::with_backend(Vec::new().into_iter(), backend)
+        });
+        let server = ControlServer::bind(&path)?.serve_shared(Arc::new(Mutex::new(ctrl)));
+        match send_request(&path, &Request::Queue)? {
+            Response::Queue(items) => assert!(items.is_empty()),
+            response => panic!("Expected queue response, got {:?}", response),
+        }
+        drop(server);
+        // the socket is gone once the server stops
+        assert!(!path.exists());
+        assert!(send_request(&path, &Request::Status).is_err());
+        Ok(())
+    }
+}
@@ -1,10 +1,12 @@
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
 use std::thread::JoinHandle;
+use std::time::Duration;
 
 use muss_interpreter::{InterpreterError, Item};
 
 use super::os_controls::SystemControlWrapper;
-use super::player_wrapper::{ControlAction, PlayerAction, PlayerServer};
+use super::player::LoopStatus;
+use super::player_wrapper::{ControlAction, PlayerAction, PlayerServer, PlayerStatus};
 use super::PlaybackError;
 use super::Player;
 use super::PlayerError;
@@ -74,28 +76,44 @@ impl Controller {
     }
 
     fn send_confirm(&self, to_send: ControlAction) -> Result<(), PlayerError> {
+        self.send_wait(to_send, |_| {})
+    }
+
+    // Send an action and handle events until it's acknowledged, giving any replies to `on_reply`.
+    // Errors are only returned once the acknowledgement has been received,
+    // otherwise it would be left for (and mistaken by) the next action.
+    fn send_wait<F: FnMut(PlayerAction)>(
+        &self,
+        to_send: ControlAction,
+        mut on_reply: F,
+    ) -> Result<(), PlayerError> {
         self.control
             .send(to_send.clone())
             .map_err(PlayerError::from_err_playback)?;
-        let mut response = self.event.recv().map_err(PlayerError::from_err_playback)?;
-        while !response.is_acknowledgement() {
-            self.handle_event(response)?;
-            response = self.event.recv().map_err(PlayerError::from_err_playback)?;
-        }
-        if let PlayerAction::Acknowledge(action) = response {
-            if action == to_send {
-                Ok(())
-            } else {
-                Err(PlaybackError {
-                    msg: "Incorrect acknowledgement received for Controller control action".into(),
+        let mut error = None;
+        loop {
+            match self.event.recv().map_err(PlayerError::from_err_playback)? {
+                PlayerAction::Acknowledge(action) if action == to_send => break,
+                PlayerAction::Acknowledge(_) => {
+                    error.get_or_insert_with(|| {
+                        PlaybackError {
+                            msg: "Incorrect acknowledgement received for Controller control action"
+                                .into(),
+                        }
+                        .into()
+                    });
+                }
+                msg @ PlayerAction::Queue(_) | msg @ PlayerAction::Status(_) => on_reply(msg),
+                msg => {
+                    if let Err(e) = self.handle_event(msg) {
+                        error.get_or_insert(e);
+                    }
                 }
-                .into())
-            }
-        } else {
-            Err(PlaybackError {
-                msg: "Invalid acknowledgement received for Controller control action".into(),
             }
-            .into())
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(()),
         }
     }
 
@@ -105,6 +123,8 @@ impl Controller {
             PlayerAction::Exception(e) => Err(e),
             PlayerAction::End => Ok(()),
             PlayerAction::Empty => Ok(()),
+            PlayerAction::Queue(_) => Ok(()),
+            PlayerAction::Status(_) => Ok(()),
             //PlayerAction::Enqueued(item) => Ok(()),
         }
     }
@@ -136,6 +156,117 @@ impl Controller {
         })
     }
 
+    /// Move forwards (or backwards, when negative) within the current song.
+    pub fn seek(&self, offset_secs: f64) -> Result<(), PlayerError> {
+        self.send_confirm(ControlAction::Seek {
+            offset: (offset_secs * 1_000_000.0) as i64,
+            ack: true,
+        })
+    }
+
+    /// Play the current song from a position.
+    pub fn set_position(&self, position: std::time::Duration) -> Result<(), PlayerError> {
+        self.send_confirm(ControlAction::SetPosition {
+            position: position,
+            ack: true,
+        })
+    }
+
+    /// The song which is playing followed by the songs which will play next.
+    /// The index of each song is its position in the queue, for `remove`, `move_item` and friends.
+    pub fn queue(&self) -> Result<Vec<Item>, PlayerError> {
+        let mut queue = None;
+        self.send_wait(ControlAction::GetQueue { ack: true }, |msg| {
+            if let PlayerAction::Queue(items) = msg {
+                queue = Some(items);
+            }
+        })?;
+        queue.ok_or_else(|| {
+            PlaybackError {
+                msg: "No queue received for Controller control action".into(),
+            }
+            .into()
+        })
+    }
+
+    /// What's playing, and how.
+    pub fn status(&self) -> Result<PlayerStatus, PlayerError> {
+        let mut status = None;
+        self.send_wait(ControlAction::GetStatus { ack: true }, |msg| {
+            if let PlayerAction::Status(s) = msg {
+                status = Some(s);
+            }
+        })?;
+        status.ok_or_else(|| {
+            PlaybackError {
+                msg: "No status received for Controller control action".into(),
+            }
+            .into()
+        })
+    }
+
+    /// Set the volume, out of 1.0.
+    pub fn set_volume(&self, volume: f32) -> Result<(), PlayerError> {
+        self.send_confirm(ControlAction::SetVolume {
+            volume: (volume.clamp(0.0, 1.0) * u32::MAX as f32) as u32,
+            ack: true,
+        })
+    }
+
+    /// Remove the song at a queue position. Removing the current song (position 0) skips it.
+    pub fn remove(&self, position: usize) -> Result<(), PlayerError> {
+        self.send_confirm(ControlAction::Remove {
+            position: position,
+            ack: true,
+        })
+    }
+
+    /// Move the song at a queue position to another position after the current song.
+    pub fn move_item(&self, from: usize, to: usize) -> Result<(), PlayerError> {
+        self.send_confirm(ControlAction::Move {
+            from: from,
+            to: to,
+            ack: true,
+        })
+    }
+
+    /// Play a song after the current song.
+    pub fn insert_next(&self, item: Item) -> Result<(), PlayerError> {
+        self.send_confirm(ControlAction::InsertNext {
+            item: item,
+            ack: true,
+        })
+    }
+
+    /// Play a song after the songs which are already queued.
+    pub fn append(&self, item: Item) -> Result<(), PlayerError> {
+        self.send_confirm(ControlAction::Append {
+            item: item,
+            ack: true,
+        })
+    }
+
+    /// Remove every song after the current song.
+    pub fn clear_upcoming(&self) -> Result<(), PlayerError> {
+        self.send_confirm(ControlAction::ClearUpcoming { ack: true })
+    }
+
+    /// Repeat the current song or the whole playlist, or stop repeating.
+    pub fn set_loop_status(&self, loop_status: LoopStatus) -> Result<(), PlayerError> {
+        self.send_confirm(ControlAction::SetLoopStatus {
+            loop_status: loop_status,
+            ack: true,
+        })
+    }
+
+    /// Shuffle the songs after the current song.
+    pub fn set_shuffle(&self, shuffle: bool) -> Result<(), PlayerError> {
+        self.send_confirm(ControlAction::SetShuffle {
+            shuffle: shuffle,
+            ack: true,
+        })
+    }
+
     pub fn ping(&self) -> Result<(), PlayerError> {
         self.send_confirm(ControlAction::NoOp { ack: true })
     }
@@ -164,6 +295,17 @@ impl Controller {
         Ok(())
     }
 
+    /// Like wait_for_done(), but it gives up after `timeout`.
+    /// This returns true once playback is done.
+    pub fn wait_for_done_timeout(&self, timeout: Duration) -> Result<bool, PlayerError> {
+        match self.event.recv_timeout(timeout) {
+            Ok(PlayerAction::End) => Ok(true),
+            Ok(msg) => self.handle_event(msg).map(|_| false),
+            Err(RecvTimeoutError::Timeout) => Ok(false),
+            Err(e) => Err(PlayerError::from_err_playback(e)),
+        }
+    }
+
     pub fn wait_for_empty(&self) -> Result<(), PlayerError> {
         for msg in self.event.try_iter() {
             self.handle_event(msg)?;
@@ -225,3 +367,33 @@ impl Controller {
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::NullBackend;
+    use muss_interpreter::lang::TypePrimitive;
+
+    fn null_controller(filenames: &[&str]) -> Controller {
+        let songs: Vec<Result<Item, InterpreterError>> = filenames
+            .iter()
+            .map(|x| {
+                Ok(Item::new().set_field_chain2("filename", TypePrimitive::String(x.to_string())))
+            })
+            .collect();
+        let (backend, _) = NullBackend::new();
+        Controller::create_repl(move || Player::with_backend(songs.into_iter(), backend))
+    }
+
+    #[test]
+    fn error_then_ack() -> Result<(), PlayerError> {
+        let controller = null_controller(&["a", "b", "c"]);
+        assert!(controller.remove(10).is_err());
+        assert!(controller.move_item(1, 10).is_err());
+        // the acknowledgements of the failed actions aren't left behind for these
+        controller.ping()?;
+        assert_eq!(controller.queue()?.len(), 2);
+        assert!(controller.check().is_empty());
+        Ok(())
+    }
+}
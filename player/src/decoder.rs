@@ -0,0 +1,223 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use rodio::Source;
+
+use symphonia::core::audio::{Channels, SampleBuffer, SignalSpec};
+use symphonia::core::codecs::{Decoder, DecoderOptions};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::{FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::{Time, TimeBase};
+
+use super::PlayerError;
+
+// a few bad packets are skipped, but more than that in a row ends the song
+const MAX_DECODE_ERRORS: usize = 3;
+
+/// A song decoded by symphonia, which (unlike rodio's decoder) can start part way through.
+pub struct SongDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    time_base: Option<TimeBase>,
+    duration: Option<Duration>,
+    spec: SignalSpec,
+    buffer: SampleBuffer<i16>,
+    offset: usize,
+    // frames to drop from the next decoded packets, since seeking lands a little before the position
+    skip_frames: u64,
+}
+
+impl SongDecoder {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, PlayerError> {
+        let path = path.as_ref();
+        let file =
+            fs::File::open(path).map_err(|e| PlayerError::from_file_err_playback(e, path))?;
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|x| x.to_str()) {
+            hint.with_extension(extension);
+        }
+        let probed = symphonia::default::get_probe()
+            .format(&hint, mss, &Default::default(), &Default::default())
+            .map_err(PlayerError::from_err_playback)?;
+        let track = probed
+            .format
+            .default_track()
+            .ok_or_else(|| PlayerError::from_err_playback("No audio track in file"))?;
+        let track_id = track.id;
+        let time_base = track.codec_params.time_base;
+        let duration = match (time_base, track.codec_params.n_frames) {
+            (Some(time_base), Some(frames)) => Some(to_duration(time_base.calc_time(frames))),
+            _ => None,
+        };
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions { verify: true })
+            .map_err(PlayerError::from_err_playback)?;
+        let mut song = Self {
+            track_id: track_id,
+            time_base: time_base,
+            format: probed.format,
+            decoder: decoder,
+            duration: duration,
+            // replaced by the first decoded packet
+            spec: SignalSpec::new(0, Channels::FRONT_LEFT),
+            buffer: SampleBuffer::new(0, SignalSpec::new(0, Channels::FRONT_LEFT)),
+            offset: 0,
+            skip_frames: 0,
+        };
+        // the first packet says how many channels there are, and at what sample rate
+        if !song.decode_next() {
+            return Err(PlayerError::from_err_playback("No audio could be decoded"));
+        }
+        Ok(song)
+    }
+
+    /// Move to `position` in the song.
+    /// Formats which can't seek are decoded up to there instead, so this should only be done before playing.
+    pub fn seek(&mut self, position: Duration) {
+        let to = SeekTo::Time {
+            time: Time::from(position.as_secs_f64()),
+            track_id: Some(self.track_id),
+        };
+        match self.format.seek(SeekMode::Accurate, to) {
+            Ok(seeked) => {
+                self.decoder.reset();
+                let early = seeked.required_ts.saturating_sub(seeked.actual_ts);
+                self.skip_frames = match self.time_base {
+                    Some(time_base) => {
+                        (to_duration(time_base.calc_time(early)).as_secs_f64()
+                            * self.spec.rate as f64) as u64
+                    }
+                    None => early,
+                };
+                // what was decoded before the seek is from the wrong place
+                self.offset = self.buffer.len();
+            }
+            Err(_) => {
+                let samples =
+                    position.as_secs_f64() * self.spec.rate as f64 * self.channels() as f64;
+                self.by_ref().take(samples as usize).for_each(drop);
+            }
+        }
+    }
+
+    // returns false at the end of the song
+    fn decode_next(&mut self) -> bool {
+        let mut errors = 0;
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(_) => return false,
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    self.spec = *decoded.spec();
+                    self.buffer = SampleBuffer::new(decoded.capacity() as u64, self.spec);
+                    self.buffer.copy_interleaved_ref(decoded);
+                    let channels = self.spec.channels.count().max(1);
+                    let frames = (self.buffer.len() / channels) as u64;
+                    let skipped = self.skip_frames.min(frames);
+                    self.skip_frames -= skipped;
+                    self.offset = skipped as usize * channels;
+                    return true;
+                }
+                Err(Error::DecodeError(_)) if errors < MAX_DECODE_ERRORS => errors += 1,
+                Err(_) => return false,
+            }
+        }
+    }
+}
+
+impl Iterator for SongDecoder {
+    type Item = i16;
+
+    #[inline]
+    fn next(&mut self) -> Option<i16> {
+        while self.offset >= self.buffer.len() {
+            if !self.decode_next() {
+                return None;
+            }
+        }
+        let sample = self.buffer.samples()[self.offset];
+        self.offset += 1;
+        Some(sample)
+    }
+}
+
+impl Source for SongDecoder {
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.buffer.len())
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.spec.channels.count() as u16
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.spec.rate
+    }
+
+    /// Length of the whole song, even after seeking.
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.duration
+    }
+}
+
+#[inline]
+fn to_duration(time: Time) -> Duration {
+    Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    // mono 16-bit WAV where each sample's value is its index
+    fn write_counting_wav(path: &Path, sample_rate: u32, len: u16) -> std::io::Result<()> {
+        let data_len = len as u32 * 2;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&1u16.to_le_bytes()); // channels
+        wav.extend_from_slice(&sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        for sample in 0..len {
+            wav.extend_from_slice(&(sample as i16).to_le_bytes());
+        }
+        fs::File::create(path)?.write_all(&wav)
+    }
+
+    #[test]
+    fn seek_wav() -> Result<(), PlayerError> {
+        let path = std::env::temp_dir().join("muss_test_decoder_seek.wav");
+        write_counting_wav(&path, 1000, 3000).map_err(PlayerError::from_err_playback)?;
+        let mut song = SongDecoder::open(&path)?;
+        fs::remove_file(&path).unwrap_or(());
+        assert_eq!(song.total_duration(), Some(Duration::from_secs(3)));
+        assert_eq!((song.channels(), song.sample_rate()), (1, 1000));
+        assert_eq!(song.next(), Some(0));
+        song.seek(Duration::from_millis(1500));
+        assert_eq!(song.next(), Some(1500));
+        assert_eq!(song.count(), 1499);
+        Ok(())
+    }
+}
@@ -6,19 +6,34 @@
 #![allow(clippy::match_like_matches_macro)]
 #![allow(clippy::redundant_field_names)]
 
+mod backend;
+#[cfg(unix)]
+mod control_socket;
 mod controller;
+mod decoder;
 mod errors;
 pub(crate) mod os_controls;
 mod player;
 pub(crate) mod player_wrapper;
+mod render;
+mod session;
 pub(crate) mod uri;
 //mod utility;
 
+#[cfg(feature = "mpd")]
+pub use backend::MpdBackend;
+pub use backend::{Backend, BackendAction, Modifier, NullBackend, NullHandle, RodioBackend};
+#[cfg(unix)]
+pub use control_socket::{send_request, ControlServer, Request, Response, SharedControlServer};
 pub use controller::Controller;
+pub use decoder::SongDecoder;
 pub use errors::{PlaybackError, PlayerError, UriError};
 #[cfg(feature = "mpd")]
 pub use player::mpd_connection;
-pub use player::Player;
+pub use player::{LoopStatus, Player};
+pub use player_wrapper::PlayerStatus;
+pub use render::{render_wav, RenderOptions};
+pub use session::Session;
 //pub use utility::{play_script};
 
 #[cfg(test)]
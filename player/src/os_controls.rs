@@ -6,6 +6,9 @@ use std::thread::JoinHandle;
 #[cfg(all(target_os = "linux", feature = "os-controls", feature = "mpris-player"))]
 use mpris_player::{Metadata, MprisPlayer, PlaybackStatus};
 
+#[cfg(all(target_os = "linux", feature = "os-controls", feature = "mpris-player"))]
+use super::player::LoopStatus;
+
 #[cfg(all(target_os = "linux", feature = "os-controls", feature = "mpris-player"))]
 use muss_interpreter::Item;
 
@@ -38,11 +41,18 @@ pub struct SystemControlWrapper {
     playback_receiver: Option<Receiver<PlaybackAction>>,
 }
 
+// songs which are opened but not playing yet, usually just the next one
+#[cfg(all(target_os = "linux", feature = "os-controls", feature = "mpris-player"))]
+const MAX_UPCOMING_DURATIONS: usize = 4;
+
 #[cfg(all(target_os = "linux", feature = "os-controls", feature = "mpris-player"))]
 enum DbusControl {
     Die,
     SetMetadata(Metadata),
     SetPosition(i64),
+    Seeked(i64),
+    SetLoopStatus(LoopStatus),
+    SetShuffle(bool),
 }
 
 #[cfg(all(target_os = "linux", feature = "os-controls", feature = "mpris-player"))]
@@ -73,7 +83,10 @@ impl SystemControlWrapper {
             dbus_conn.set_can_play(true);
             dbus_conn.set_can_pause(true);
             dbus_conn.set_can_go_next(true);
-            dbus_conn.set_can_seek(false);
+            dbus_conn.set_can_go_previous(true);
+            dbus_conn.set_can_seek(true);
+            dbus_conn.set_loop_status(mpris_player::LoopStatus::None);
+            dbus_conn.set_shuffle(false);
 
             let control_clone = control_clone1.clone();
             dbus_conn.connect_next(move || {
@@ -128,6 +141,51 @@ impl SystemControlWrapper {
                 is_playing = !is_playing;
             });
 
+            let control_clone = control_clone1.clone();
+            dbus_conn.connect_seek(move |offset| {
+                control_clone
+                    .send(ControlAction::Seek {
+                        ack: false,
+                        offset: offset,
+                    })
+                    .unwrap_or(())
+            });
+
+            let control_clone = control_clone1.clone();
+            dbus_conn.connect_set_position(move |_track_id, position| {
+                control_clone
+                    .send(ControlAction::SetPosition {
+                        ack: false,
+                        position: std::time::Duration::from_micros(position.max(0) as u64),
+                    })
+                    .unwrap_or(())
+            });
+
+            let control_clone = control_clone1.clone();
+            dbus_conn.connect_loop_status(move |loop_status| {
+                let loop_status = match loop_status {
+                    mpris_player::LoopStatus::None => LoopStatus::None,
+                    mpris_player::LoopStatus::Track => LoopStatus::Track,
+                    mpris_player::LoopStatus::Playlist => LoopStatus::Playlist,
+                };
+                control_clone
+                    .send(ControlAction::SetLoopStatus {
+                        ack: false,
+                        loop_status: loop_status,
+                    })
+                    .unwrap_or(())
+            });
+
+            let control_clone = control_clone1.clone();
+            dbus_conn.connect_shuffle(move |shuffle| {
+                control_clone
+                    .send(ControlAction::SetShuffle {
+                        ack: false,
+                        shuffle: shuffle,
+                    })
+                    .unwrap_or(())
+            });
+
             let control_clone = control_clone1.clone();
             dbus_conn.connect_volume(move |v| {
                 control_clone
@@ -152,6 +210,20 @@ impl SystemControlWrapper {
                     Ok(DbusControl::SetPosition(pos)) => {
                         dbus_conn.set_position(pos);
                     }
+                    Ok(DbusControl::Seeked(pos)) => {
+                        dbus_conn.set_position(pos);
+                        dbus_conn.seeked(pos);
+                    }
+                    Ok(DbusControl::SetLoopStatus(loop_status)) => {
+                        dbus_conn.set_loop_status(match loop_status {
+                            LoopStatus::None => mpris_player::LoopStatus::None,
+                            LoopStatus::Track => mpris_player::LoopStatus::Track,
+                            LoopStatus::Playlist => mpris_player::LoopStatus::Playlist,
+                        });
+                    }
+                    Ok(DbusControl::SetShuffle(shuffle)) => {
+                        dbus_conn.set_shuffle(shuffle);
+                    }
                 }
             }
         }));
@@ -160,6 +232,9 @@ impl SystemControlWrapper {
         self.playback_event_handler = Some(std::thread::spawn(move || {
             let mut playback_time = 0;
             let mut duration_cache = None;
+            let mut now_playing = None;
+            // lengths of songs which were opened before they started playing
+            let mut upcoming_durations: Vec<(Item, std::time::Duration)> = Vec::new();
             loop {
                 if let Ok(_) = rx.try_recv() {
                     break;
@@ -167,19 +242,57 @@ impl SystemControlWrapper {
                 match playback.recv() {
                     Err(_) => break,
                     Ok(PlaybackAction::Exit) => break,
-                    Ok(PlaybackAction::Enqueued(item)) => {
+                    Ok(PlaybackAction::Enqueued(_item)) => {},
+                    Ok(PlaybackAction::Playing(item)) => {
                         playback_time = 0;
-                        duration_cache = None;
-                        Self::enqueued(item, &dbus_ctrl_tx_clone);
+                        duration_cache = upcoming_durations
+                            .iter()
+                            .position(|(upcoming, _)| upcoming == &item)
+                            .map(|i| upcoming_durations.remove(i).1);
+                        if let Some(duration) = duration_cache {
+                            Self::time(item.clone(), duration, &dbus_ctrl_tx_clone);
+                        } else {
+                            Self::enqueued(item.clone(), &dbus_ctrl_tx_clone);
+                        }
+                        now_playing = Some(item);
+                    },
+                    Ok(PlaybackAction::Empty) => {
+                        now_playing = None;
+                        Self::empty(&dbus_ctrl_tx_clone)
                     },
-                    Ok(PlaybackAction::Empty) => Self::empty(&dbus_ctrl_tx_clone),
                     Ok(PlaybackAction::Time(item, duration)) => {
-                        duration_cache = Some(duration);
-                        Self::time(item, duration, &dbus_ctrl_tx_clone);
+                        if now_playing.as_ref() == Some(&item) {
+                            duration_cache = Some(duration);
+                            Self::time(item, duration, &dbus_ctrl_tx_clone);
+                        } else {
+                            if upcoming_durations.len() >= MAX_UPCOMING_DURATIONS {
+                                upcoming_durations.remove(0);
+                            }
+                            upcoming_durations.push((item, duration));
+                        }
                     },
                     Ok(PlaybackAction::UpdateTick(item)) => {
-                        Self::time_update(item, playback_time, &duration_cache, &dbus_ctrl_tx_clone);
-                        playback_time += 1;
+                        // songs fading in or out tick too
+                        if now_playing.as_ref() == Some(&item) {
+                            Self::time_update(item, playback_time, &duration_cache, &dbus_ctrl_tx_clone);
+                            playback_time += 1;
+                        }
+                    },
+                    Ok(PlaybackAction::Seeked(_item, position)) => {
+                        playback_time = position.as_secs() as i64;
+                        dbus_ctrl_tx_clone
+                            .send(DbusControl::Seeked(position.as_micros() as i64))
+                            .unwrap_or(());
+                    },
+                    Ok(PlaybackAction::LoopStatus(loop_status)) => {
+                        dbus_ctrl_tx_clone
+                            .send(DbusControl::SetLoopStatus(loop_status))
+                            .unwrap_or(());
+                    },
+                    Ok(PlaybackAction::Shuffle(shuffle)) => {
+                        dbus_ctrl_tx_clone
+                            .send(DbusControl::SetShuffle(shuffle))
+                            .unwrap_or(());
                     },
                 }
             }
@@ -1,63 +1,109 @@
-use std::fs;
+use std::cell::Cell;
+use std::collections::{HashSet, VecDeque};
 use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
-use rodio::{decoder::Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+use rodio::Source;
 
 use m3u8_rs::{MediaPlaylist, MediaSegment};
 
+use rand::seq::SliceRandom;
+
 #[cfg(feature = "mpd")]
-use mpd::{error, Client, Song};
+use mpd::{error, Client};
 
-use super::uri::Uri;
+use super::backend::{Backend, RodioBackend};
+use super::decoder::SongDecoder;
+use super::session::Session;
 
 use muss_interpreter::{InterpreterError, Item};
 
 //use super::PlaybackError;
 use super::PlayerError;
-use super::UriError;
 
 //type Interpreter = std::iter::Iterator<Item=Result<Item, InterpreterError>>;
 
+/// Number of played songs remembered for going back to previous songs
+const HISTORY_LEN: usize = 100;
+
+/// Going back restarts the current song instead when it has been playing for longer than this
+const DEFAULT_RESTART_AFTER: Duration = Duration::from_secs(3);
+
+/// What to play again once it's done playing
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LoopStatus {
+    /// Play each song once
+    #[default]
+    None,
+    /// Play the current song over and over
+    Track,
+    /// Play every song again once the script runs out of songs
+    Playlist,
+}
+
 /// Playback functionality for a script.
 /// This takes the output of the runner and plays or saves it.
 pub struct Player<I: std::iter::Iterator<Item = Result<Item, InterpreterError>>> {
     runner: I,
-    sink: Sink,
-    #[allow(dead_code)]
-    output_stream: OutputStream, // this is required for playback, so it must live as long as this struct instance
-    output_handle: OutputStreamHandle,
-    #[cfg(feature = "mpd")]
-    mpd_connection: Option<Client<std::net::TcpStream>>,
+    backend: Box<dyn Backend>,
+    // songs in the backend (and their length, when known) in the same order; the first is the one playing
+    queued: VecDeque<(Item, Option<Duration>)>,
+    // songs to play after the ones in the backend, before the rest of the runner's songs
+    upcoming: VecDeque<Item>,
+    // songs which have finished or been skipped, most recent last
+    history: VecDeque<Item>,
+    restart_after: Option<Duration>,
+    // playback time of the current song, excluding the time since it was last resumed
+    elapsed: Duration,
+    resumed_at: Option<Instant>,
+    crossfade: Option<Duration>,
+    loop_status: LoopStatus,
+    shuffle: bool,
+    // songs to play again when looping the playlist, only kept while it loops
+    playlist: Vec<Item>,
+    // how many results (songs and errors) the runner has produced, and the filenames of its songs in order
+    produced: usize,
+    emitted: Vec<String>,
+    // songs played before resuming the session, when the runner didn't produce the same songs as last time
+    already_played: HashSet<String>,
+    // where to save the session, and the script being played
+    session_file: Option<(PathBuf, String)>,
+    // where the first song starts when resuming a session
+    start_at: Option<Duration>,
 }
 
 impl<I: std::iter::Iterator<Item = Result<Item, InterpreterError>>> Player<I> {
+    /// Play through the default audio device.
     pub fn new(runner: I) -> Result<Self, PlayerError> {
-        let (stream, output_handle) =
-            OutputStream::try_default().map_err(PlayerError::from_err_playback)?;
-        Ok(Self {
-            runner: runner,
-            sink: Sink::try_new(&output_handle).map_err(PlayerError::from_err_playback)?,
-            output_stream: stream,
-            output_handle: output_handle,
-            #[cfg(feature = "mpd")]
-            mpd_connection: None,
-        })
-    }
-
-    #[cfg(feature = "mpd")]
-    pub fn connect_mpd(&mut self, addr: std::net::SocketAddr) -> Result<(), PlayerError> {
-        self.mpd_connection = Some(Client::connect(addr).map_err(PlayerError::from_err_mpd)?);
-        Ok(())
+        Ok(Self::with_backend(runner, RodioBackend::new()?))
     }
 
-    #[cfg(feature = "mpd")]
-    pub fn set_mpd(&mut self, client: Client<std::net::TcpStream>) {
-        self.mpd_connection = Some(client);
+    pub fn with_backend<B: Backend + 'static>(runner: I, backend: B) -> Self {
+        Self {
+            runner: runner,
+            backend: Box::new(backend),
+            queued: VecDeque::new(),
+            upcoming: VecDeque::new(),
+            history: VecDeque::with_capacity(HISTORY_LEN),
+            restart_after: Some(DEFAULT_RESTART_AFTER),
+            elapsed: Duration::ZERO,
+            resumed_at: Some(Instant::now()),
+            crossfade: None,
+            loop_status: LoopStatus::None,
+            shuffle: false,
+            playlist: Vec::new(),
+            produced: 0,
+            emitted: Vec::new(),
+            already_played: HashSet::new(),
+            session_file: None,
+            start_at: None,
+        }
     }
 
     pub fn play_all(&mut self) -> Result<(), PlayerError> {
         while let Some(item) = self.runner.next() {
-            self.sink.sleep_until_end();
+            self.backend.sleep_until_end();
             match item {
                 Ok(music) => {
                     if let Some(filename) =
@@ -74,20 +120,24 @@ impl<I: std::iter::Iterator<Item = Result<Item, InterpreterError>>> Player<I> {
                 Err(e) => Err(PlayerError::from_err_playback(e)),
             }?;
         }
-        self.sink.sleep_until_end();
+        self.backend.sleep_until_end();
         Ok(())
     }
 
+    /// Enqueue every song which hasn't been played yet.
+    /// Nothing is looped, since that would never finish.
     pub fn enqueue_all(&mut self) -> Result<Vec<Item>, PlayerError> {
         let mut enqueued = Vec::new();
-        while let Some(item) = self.runner.next() {
+        while let Some(item) = self.next_unlooped() {
             match item {
                 Ok(music) => {
                     enqueued.push(music.clone());
                     if let Some(filename) =
                         music.field("filename").and_then(|x| x.to_owned().to_str())
                     {
-                        self.append_source(&filename)?;
+                        let queue_len = self.backend.len();
+                        let duration = self.append_source(&filename)?;
+                        self.track_queued(music, queue_len, duration);
                         Ok(())
                     } else {
                         Err(PlayerError::from_err_playback(
@@ -107,14 +157,16 @@ impl<I: std::iter::Iterator<Item = Result<Item, InterpreterError>>> Player<I> {
         if items_left == 0 {
             return Ok(enqueued);
         }
-        while let Some(item) = self.runner.next() {
+        while let Some(item) = self.next_item() {
             match item {
                 Ok(music) => {
                     if let Some(filename) =
                         music.field("filename").and_then(|x| x.to_owned().to_str())
                     {
                         enqueued.push(music.clone());
-                        self.append_source(&filename)?;
+                        let queue_len = self.backend.len();
+                        let duration = self.append_source(&filename)?;
+                        self.track_queued(music, queue_len, duration);
                         items_left -= 1;
                         Ok(())
                     } else {
@@ -133,20 +185,32 @@ impl<I: std::iter::Iterator<Item = Result<Item, InterpreterError>>> Player<I> {
         Ok(enqueued)
     }
 
-    pub fn enqueue_modified(&mut self, count: usize, modify: &dyn Fn(Decoder<io::BufReader<fs::File>>, Item) -> Box<dyn Source<Item=i16> + Send>) -> Result<Vec<Item>, PlayerError> {
+    pub fn enqueue_modified(&mut self, count: usize, modify: &dyn Fn(SongDecoder, Item) -> Box<dyn Source<Item=i16> + Send>) -> Result<Vec<Item>, PlayerError> {
         let mut items_left = count;
         let mut enqueued = Vec::with_capacity(count);
         if items_left == 0 {
             return Ok(enqueued);
         }
-        while let Some(item) = self.runner.next() {
+        while let Some(item) = self.next_item() {
             match item {
                 Ok(music) => {
                     if let Some(filename) =
                         music.field("filename").and_then(|x| x.to_owned().to_str())
                     {
                         enqueued.push(music.clone());
-                        self.append_source_modified(&filename, &|x| modify(x, music.clone()))?;
+                        let queue_len = self.backend.len();
+                        let start_at = self.start_at.take();
+                        let duration = self.append_from_modified(
+                            &filename,
+                            &music,
+                            start_at.unwrap_or(Duration::ZERO),
+                            modify,
+                        )?;
+                        self.track_queued(music, queue_len, duration);
+                        if let Some(position) = start_at {
+                            self.restart_clock();
+                            self.elapsed = position;
+                        }
                         items_left -= 1;
                         Ok(())
                     } else {
@@ -165,28 +229,460 @@ impl<I: std::iter::Iterator<Item = Result<Item, InterpreterError>>> Player<I> {
         Ok(enqueued)
     }
 
-    pub fn resume(&self) {
-        self.sink.play()
+    /// Go back to the previous song, or restart the current song if it has been playing for a while.
+    /// The songs which were queued are played again afterwards.
+    /// This returns the song which is now playing.
+    pub fn previous_modified(
+        &mut self,
+        modify: &dyn Fn(SongDecoder, Item) -> Box<dyn Source<Item=i16> + Send>,
+    ) -> Result<Option<Item>, PlayerError> {
+        self.update_history();
+        let mut to_play: Vec<Item> = self.queued.drain(..).map(|(item, _)| item).collect();
+        if self.loop_status == LoopStatus::Track {
+            // the rest of the queue is repeats of the current song
+            to_play.truncate(1);
+        }
+        let restart = match self.restart_after {
+            Some(restart_after) => !to_play.is_empty() && self.position() > restart_after,
+            None => false,
+        };
+        if !restart {
+            if let Some(previous) = self.history.pop_back() {
+                if self.loop_status == LoopStatus::Track {
+                    // the previous song loops instead, until looping stops
+                    if let Some(current) = to_play.pop() {
+                        self.upcoming.push_front(current);
+                    }
+                }
+                to_play.insert(0, previous);
+            }
+        }
+        self.requeue_modified(to_play, Duration::ZERO, modify)
+    }
+
+    /// Play the current song from `position` instead, keeping the rest of the queue.
+    /// This returns the song which is playing, if there is one.
+    pub fn seek_modified(
+        &mut self,
+        position: Duration,
+        modify: &dyn Fn(SongDecoder, Item) -> Box<dyn Source<Item=i16> + Send>,
+    ) -> Result<Option<Item>, PlayerError> {
+        self.update_history();
+        if self.queued.is_empty() {
+            return Ok(None);
+        }
+        if self.backend.seek(position)? {
+            self.restart_clock();
+            self.elapsed = position;
+            return Ok(self.current().cloned());
+        }
+        let to_play: Vec<Item> = self.queued.drain(..).map(|(item, _)| item).collect();
+        self.requeue_modified(to_play, position, modify)
+    }
+
+    /// Skip to the next song, keeping the rest of the queue.
+    /// This returns the song which is now playing.
+    pub fn skip_modified(
+        &mut self,
+        modify: &dyn Fn(SongDecoder, Item) -> Box<dyn Source<Item=i16> + Send>,
+    ) -> Result<Option<Item>, PlayerError> {
+        self.update_history();
+        if let Some((skipped, _)) = self.queued.pop_front() {
+            self.remember(skipped);
+        }
+        if self.loop_status == LoopStatus::Track {
+            // don't play the skipped song again
+            self.queued.clear();
+        }
+        let to_play: Vec<Item> = self.queued.drain(..).map(|(item, _)| item).collect();
+        self.requeue_modified(to_play, Duration::ZERO, modify)
+    }
+
+    /// Update the backend, and start fading into the next song when the current song is about to end.
+    /// This should be called regularly (several times a second) during playback.
+    /// Songs from the same album are played gaplessly instead of crossfading.
+    pub fn update_modified(
+        &mut self,
+        modify: &dyn Fn(SongDecoder, Item) -> Box<dyn Source<Item=i16> + Send>,
+    ) -> Result<(), PlayerError> {
+        self.backend.update()?;
+        if self.backend.is_fading() {
+            return Ok(());
+        }
+        let crossfade = match self.crossfade {
+            Some(crossfade) => crossfade,
+            None => return Ok(()),
+        };
+        self.update_history();
+        if self.queued.len() < 2 || self.backend.is_paused() {
+            return Ok(());
+        }
+        let remaining = match self.queued[0].1 {
+            Some(duration) => duration.saturating_sub(self.position()),
+            None => return Ok(()), // can't tell when the song ends
+        };
+        if remaining > crossfade
+            || remaining.is_zero()
+            || same_album(&self.queued[0].0, &self.queued[1].0)
+        {
+            return Ok(());
+        }
+        if !self.backend.fade_out(remaining)? {
+            return Ok(());
+        }
+        if let Some((finished, _)) = self.queued.pop_front() {
+            self.remember(finished);
+        }
+        let to_play: Vec<Item> = self.queued.drain(..).map(|(item, _)| item).collect();
+        for (i, music) in to_play.into_iter().enumerate() {
+            if let Some(filename) = music.field("filename").and_then(|x| x.to_owned().to_str()) {
+                let queue_len = self.backend.len();
+                let duration = if i == 0 {
+                    self.append_source_modified(&filename, &|x| {
+                        Box::new(modify(x, music.clone()).fade_in(remaining))
+                    })?
+                } else {
+                    self.append_source_modified(&filename, &|x| modify(x, music.clone()))?
+                };
+                self.track_queued(music, queue_len, duration);
+            }
+        }
+        self.restart_clock();
+        Ok(())
+    }
+
+    /// Overlap the end of each song with the start of the next song, fading between them.
+    /// When None, songs are played one after the other.
+    pub fn set_crossfade(&mut self, crossfade: Option<Duration>) {
+        self.crossfade = crossfade.filter(|x| !x.is_zero());
+    }
+
+    /// The song which is playing, if any.
+    pub fn current(&self) -> Option<&Item> {
+        self.queued.front().map(|(item, _)| item)
+    }
+
+    // replace the queue with `to_play`, starting `position` into the first song
+    fn requeue_modified(
+        &mut self,
+        to_play: Vec<Item>,
+        position: Duration,
+        modify: &dyn Fn(SongDecoder, Item) -> Box<dyn Source<Item=i16> + Send>,
+    ) -> Result<Option<Item>, PlayerError> {
+        self.clear_backend()?;
+        for (i, music) in to_play.into_iter().enumerate() {
+            if let Some(filename) = music.field("filename").and_then(|x| x.to_owned().to_str()) {
+                let queue_len = self.backend.len();
+                let duration = if i == 0 {
+                    self.append_from_modified(&filename, &music, position, modify)?
+                } else {
+                    self.append_source_modified(&filename, &|x| modify(x, music.clone()))?
+                };
+                self.track_queued(music, queue_len, duration);
+            }
+        }
+        self.elapsed = position;
+        Ok(self.current().cloned())
+    }
+
+    /// The current song followed by the songs which will play next, so the index of a song is its queue position.
+    /// This doesn't include songs which the runner hasn't produced yet.
+    pub fn queue(&self) -> Vec<Item> {
+        self.queued
+            .iter()
+            .map(|(item, _)| item.clone())
+            .chain(self.upcoming.iter().cloned())
+            .collect()
+    }
+
+    /// Remove the song at a queue position, returning it.
+    /// Removing the current song (position 0) skips to the next song.
+    pub fn remove_modified(
+        &mut self,
+        position: usize,
+        modify: &dyn Fn(SongDecoder, Item) -> Box<dyn Source<Item=i16> + Send>,
+    ) -> Result<Item, PlayerError> {
+        self.update_history();
+        if position == 0 {
+            if let Some(current) = self.current().cloned() {
+                self.skip_modified(modify)?;
+                return Ok(current);
+            }
+        }
+        self.unqueue_modified(modify)?;
+        // everything except the current song is upcoming now
+        let offset = self.queued.len();
+        position
+            .checked_sub(offset)
+            .and_then(|i| self.upcoming.remove(i))
+            .ok_or_else(|| no_song_at(position))
+    }
+
+    /// Move the song at a queue position to another position.
+    /// The current song (position 0) can't be moved, and nothing can be moved in front of it.
+    pub fn move_modified(
+        &mut self,
+        from: usize,
+        to: usize,
+        modify: &dyn Fn(SongDecoder, Item) -> Box<dyn Source<Item=i16> + Send>,
+    ) -> Result<(), PlayerError> {
+        self.update_history();
+        self.unqueue_modified(modify)?;
+        let offset = self.queued.len();
+        if from < offset || to < offset {
+            return Err(PlayerError::from_err_playback(
+                "Cannot move the song which is playing",
+            ));
+        }
+        if to - offset >= self.upcoming.len() {
+            return Err(no_song_at(to));
+        }
+        let item = self
+            .upcoming
+            .remove(from - offset)
+            .ok_or_else(|| no_song_at(from))?;
+        self.upcoming.insert(to - offset, item);
+        Ok(())
+    }
+
+    /// Play a song after the current song.
+    pub fn insert_next_modified(
+        &mut self,
+        item: Item,
+        modify: &dyn Fn(SongDecoder, Item) -> Box<dyn Source<Item=i16> + Send>,
+    ) -> Result<(), PlayerError> {
+        self.update_history();
+        self.unqueue_modified(modify)?;
+        self.upcoming.push_front(item);
+        Ok(())
+    }
+
+    /// Play a song after the songs which are already queued, before the rest of the runner's songs.
+    pub fn append(&mut self, item: Item) {
+        self.upcoming.push_back(item);
+    }
+
+    /// Remove every song after the current song.
+    /// Songs which the runner hasn't produced yet will still be played.
+    pub fn clear_upcoming_modified(
+        &mut self,
+        modify: &dyn Fn(SongDecoder, Item) -> Box<dyn Source<Item=i16> + Send>,
+    ) -> Result<(), PlayerError> {
+        self.update_history();
+        self.unqueue_modified(modify)?;
+        self.upcoming.clear();
+        Ok(())
     }
 
-    pub fn pause(&self) {
-        self.sink.pause()
+    // take the songs after the current one out of the backend, so they can be changed
+    fn unqueue_modified(
+        &mut self,
+        modify: &dyn Fn(SongDecoder, Item) -> Box<dyn Source<Item=i16> + Send>,
+    ) -> Result<(), PlayerError> {
+        if self.queued.len() < 2 {
+            return Ok(());
+        }
+        let after_current: Vec<Item> = self.queued.drain(1..).map(|(item, _)| item).collect();
+        // when looping the track, those are just repeats of the current song
+        if self.loop_status != LoopStatus::Track {
+            for item in after_current.into_iter().rev() {
+                self.upcoming.push_front(item);
+            }
+        }
+        if self.backend.truncate(1)? {
+            return Ok(());
+        }
+        // the current song continues after replacing the queue
+        let position = self.position();
+        let to_play: Vec<Item> = self.queued.drain(..).map(|(item, _)| item).collect();
+        self.requeue_modified(to_play, position, modify)?;
+        Ok(())
     }
 
-    pub fn stop(&self) {
-        self.sink.stop()
+    /// Change what is played again once it's done playing.
+    /// When the playlist starts looping, songs which have already been played are only looped as far back as the history goes.
+    pub fn set_loop_status_modified(
+        &mut self,
+        loop_status: LoopStatus,
+        modify: &dyn Fn(SongDecoder, Item) -> Box<dyn Source<Item=i16> + Send>,
+    ) -> Result<(), PlayerError> {
+        self.update_history();
+        // the song after the current one was opened for the old loop status
+        self.unqueue_modified(modify)?;
+        if loop_status != LoopStatus::Playlist {
+            self.playlist = Vec::new();
+        } else if self.loop_status != LoopStatus::Playlist {
+            self.playlist = self
+                .history
+                .iter()
+                .chain(self.queued.iter().map(|(item, _)| item))
+                .chain(self.upcoming.iter())
+                .cloned()
+                .collect();
+        }
+        self.loop_status = loop_status;
+        Ok(())
+    }
+
+    pub fn loop_status(&self) -> LoopStatus {
+        self.loop_status
+    }
+
+    /// Turn shuffling on or off.
+    /// Turning it on reorders the songs after the current song; turning it off leaves them in their shuffled order.
+    /// Songs which the runner hasn't produced yet aren't shuffled, except when the playlist loops.
+    pub fn set_shuffle_modified(
+        &mut self,
+        shuffle: bool,
+        modify: &dyn Fn(SongDecoder, Item) -> Box<dyn Source<Item=i16> + Send>,
+    ) -> Result<(), PlayerError> {
+        self.update_history();
+        self.shuffle = shuffle;
+        if shuffle {
+            self.unqueue_modified(modify)?;
+            self.shuffle_upcoming();
+        }
+        Ok(())
+    }
+
+    pub fn is_shuffle(&self) -> bool {
+        self.shuffle
+    }
+
+    /// Save the session to `path` during playback, so that it can be resumed later.
+    pub fn set_session(&mut self, path: PathBuf, script: String) {
+        self.session_file = Some((path, script));
+    }
+
+    /// Continue playing a saved session, skipping the songs which the runner produced last time.
+    /// This returns false when the runner didn't produce the same songs as last time,
+    /// in which case songs which were already produced are skipped by filename instead.
+    pub fn restore_session(&mut self, session: Session) -> bool {
+        let already_played: HashSet<String> = session.emitted.iter().cloned().collect();
+        // only kept in case they have to be played after all
+        let mut not_played = Vec::new();
+        while self.produced < session.produced {
+            match self.runner.next() {
+                Some(Ok(music)) => {
+                    self.produced += 1;
+                    if let Some(filename) =
+                        music.field("filename").and_then(|x| x.to_owned().to_str())
+                    {
+                        if !already_played.contains(&filename) {
+                            not_played.push(music);
+                        }
+                        self.emitted.push(filename);
+                    }
+                }
+                Some(Err(_)) => self.produced += 1,
+                None => break,
+            }
+        }
+        let is_same =
+            self.produced == session.produced && self.emitted.last() == session.emitted.last();
+        self.upcoming = session.queue.into();
+        if !is_same {
+            self.upcoming.extend(not_played);
+            self.already_played = already_played;
+        }
+        if !self.upcoming.is_empty() && !session.position.is_zero() {
+            self.start_at = Some(session.position);
+        }
+        is_same
+    }
+
+    /// Save the session, if there is somewhere to save it.
+    pub fn save_session(&mut self) -> Result<(), PlayerError> {
+        self.update_history();
+        if let Some((path, script)) = &self.session_file {
+            let queue = self.queue();
+            let position = if queue.is_empty() {
+                Duration::ZERO
+            } else {
+                self.position()
+            };
+            Session {
+                script: script.clone(),
+                produced: self.produced,
+                emitted: self.emitted.clone(),
+                queue: queue,
+                position: position,
+            }
+            .save(path)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Forget the saved session once the script has finished, since there's nothing left to resume.
+    pub fn end_session(&mut self) -> Result<(), PlayerError> {
+        if let Some((path, _)) = self.session_file.take() {
+            Session::remove(path)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Set how long a song has to play before going back restarts it instead of playing the previous song.
+    /// When None, going back always plays the previous song.
+    pub fn set_restart_after(&mut self, restart_after: Option<Duration>) {
+        self.restart_after = restart_after;
+    }
+
+    /// Songs which have been played, most recent last.
+    pub fn history(&self) -> &VecDeque<Item> {
+        &self.history
+    }
+
+    /// Playback time of the current song.
+    pub fn position(&self) -> Duration {
+        if let Some(position) = self.backend.position() {
+            return position;
+        }
+        match self.resumed_at {
+            Some(resumed_at) => self.elapsed + resumed_at.elapsed(),
+            None => self.elapsed,
+        }
+    }
+
+    /// Move songs which the backend has finished playing into the history.
+    pub fn update_history(&mut self) {
+        while self.queued.len() > self.backend.len() {
+            if let Some((played, _)) = self.queued.pop_front() {
+                self.remember(played);
+            }
+            self.restart_clock();
+        }
+    }
+
+    pub fn resume(&mut self) -> Result<(), PlayerError> {
+        if self.resumed_at.is_none() {
+            self.resumed_at = Some(Instant::now());
+        }
+        self.backend.resume()
+    }
+
+    pub fn pause(&mut self) -> Result<(), PlayerError> {
+        if let Some(resumed_at) = self.resumed_at.take() {
+            self.elapsed += resumed_at.elapsed();
+        }
+        self.backend.pause()
+    }
+
+    pub fn stop(&mut self) -> Result<(), PlayerError> {
+        self.backend.stop()
     }
 
     pub fn sleep_until_end(&self) {
-        self.sink.sleep_until_end()
+        self.backend.sleep_until_end()
     }
 
     pub fn queue_len(&self) -> usize {
-        self.sink.len()
+        self.backend.len()
     }
 
     pub fn queue_empty(&self) -> bool {
-        self.sink.empty()
+        self.backend.is_empty()
     }
 
     pub fn save_m3u8<W: io::Write>(&mut self, w: &mut W) -> Result<(), PlayerError> {
@@ -219,113 +715,145 @@ impl<I: std::iter::Iterator<Item = Result<Item, InterpreterError>>> Player<I> {
     }
 
     pub fn is_paused(&self) -> bool {
-        self.sink.is_paused()
+        self.backend.is_paused()
     }
 
-    pub fn set_volume(&self, volume: f32) {
-        self.sink.set_volume(volume);
+    /// Whether songs are decoded by this process, so that modifiers are applied to them.
+    pub fn decodes(&self) -> bool {
+        self.backend.decodes()
     }
 
-    pub fn new_sink(&mut self) -> Result<(), PlayerError> {
-        let is_paused = self.sink.is_paused();
-        let volume = self.sink.volume();
+    /// Length of the song which is playing, when it's known.
+    pub fn current_duration(&self) -> Option<Duration> {
+        self.queued.front().and_then(|(_, duration)| *duration)
+    }
+
+    pub fn set_volume(&mut self, volume: f32) -> Result<(), PlayerError> {
+        self.backend.set_volume(volume)
+    }
 
-        self.stop();
-        self.sink = Sink::try_new(&self.output_handle).map_err(PlayerError::from_err_playback)?;
+    pub fn volume(&self) -> f32 {
+        self.backend.volume()
+    }
 
-        if is_paused {
-            self.sink.pause();
+    /// Drop the rest of the queue.
+    pub fn new_sink(&mut self) -> Result<(), PlayerError> {
+        // the current song counts as played
+        self.update_history();
+        if let Some((skipped, _)) = self.queued.pop_front() {
+            self.remember(skipped);
         }
-        self.sink.set_volume(volume);
+        self.queued.clear();
+        self.clear_backend()
+    }
+
+    fn clear_backend(&mut self) -> Result<(), PlayerError> {
+        self.backend.clear()?;
+        self.restart_clock();
         Ok(())
     }
 
-    fn append_source(&mut self, filename: &str) -> Result<(), PlayerError> {
-        let uri = Uri::new(filename);
-        match uri.scheme() {
-            Some(s) => match &s.to_lowercase() as &str {
-                "file:" => {
-                    let file =
-                        fs::File::open(uri.without_scheme()).map_err(|e| PlayerError::from_file_err_playback(e, uri.path()))?;
-                    let stream = io::BufReader::new(file);
-                    let source = Decoder::new(stream).map_err(PlayerError::from_err_playback)?;
-                    self.sink.append(source);
-                    Ok(())
-                }
-                #[cfg(feature = "mpd")]
-                "mpd:" => {
-                    if let Some(mpd_client) = &mut self.mpd_connection {
-                        //println!("Pushing {} into MPD queue", uri.path());
-                        let song = Song {
-                            file: uri.path().to_owned(),
-                            ..Default::default()
-                        };
-                        mpd_client
-                            .push(song)
-                            .map_err(PlayerError::from_err_playback)?;
-                        Ok(())
-                    } else {
-                        Err(PlayerError::from_err_playback(
-                            "Cannot play MPD song: no MPD client connected",
-                        ))
-                    }
+    // only songs which made it into the backend can be played again
+    fn track_queued(&mut self, item: Item, queue_len: usize, duration: Option<Duration>) {
+        if self.backend.len() > queue_len {
+            self.queued.push_back((item, duration));
+        }
+    }
+
+    fn next_item(&mut self) -> Option<Result<Item, InterpreterError>> {
+        if self.loop_status == LoopStatus::Track {
+            if let Some((item, _)) = self.queued.back() {
+                return Some(Ok(item.clone()));
+            }
+        }
+        match self.next_unlooped() {
+            None if self.loop_status == LoopStatus::Playlist && !self.playlist.is_empty() => {
+                self.upcoming.extend(self.playlist.iter().cloned());
+                if self.shuffle {
+                    self.shuffle_upcoming();
                 }
-                scheme => Err(UriError::Unsupported(scheme.to_owned()).into()),
-            },
-            None => {
-                //default
-                // NOTE: Default rodio::Decoder hangs here when decoding large files, but symphonia does not
-                let file = fs::File::open(uri.path()).map_err(PlayerError::from_err_playback)?;
-                let stream = io::BufReader::new(file);
-                let source = Decoder::new(stream).map_err(PlayerError::from_err_playback)?;
-                self.sink.append(source);
-                Ok(())
+                self.upcoming.pop_front().map(Ok)
             }
+            other => other,
         }
     }
 
-    fn append_source_modified(&mut self, filename: &str, modify: &dyn Fn(Decoder<io::BufReader<fs::File>>) -> Box<dyn Source<Item=i16> + Send>) -> Result<(), PlayerError> {
-        let uri = Uri::new(filename);
-        match uri.scheme() {
-            Some(s) => match &s.to_lowercase() as &str {
-                "file:" => {
-                    let file =
-                        fs::File::open(uri.without_scheme()).map_err(|e| PlayerError::from_file_err_playback(e, uri.path()))?;
-                    let stream = io::BufReader::new(file);
-                    let source = Decoder::new(stream).map_err(PlayerError::from_err_playback)?;
-                    self.sink.append(modify(source));
-                    Ok(())
-                }
-                #[cfg(feature = "mpd")]
-                "mpd:" => {
-                    if let Some(mpd_client) = &mut self.mpd_connection {
-                        //println!("Pushing {} into MPD queue", uri.path());
-                        let song = Song {
-                            file: uri.path().to_owned(),
-                            ..Default::default()
-                        };
-                        mpd_client
-                            .push(song)
-                            .map_err(PlayerError::from_err_playback)?;
-                        Ok(())
-                    } else {
-                        Err(PlayerError::from_err_playback(
-                            "Cannot play MPD song: no MPD client connected",
-                        ))
+    fn next_unlooped(&mut self) -> Option<Result<Item, InterpreterError>> {
+        if let Some(item) = self.upcoming.pop_front() {
+            return Some(Ok(item));
+        }
+        loop {
+            let item = self.runner.next()?;
+            self.produced += 1;
+            if let Ok(music) = &item {
+                if let Some(filename) = music.field("filename").and_then(|x| x.to_owned().to_str())
+                {
+                    self.emitted.push(filename.clone());
+                    if self.already_played.contains(&filename) {
+                        continue;
                     }
                 }
-                scheme => Err(UriError::Unsupported(scheme.to_owned()).into()),
-            },
-            None => {
-                //default
-                // NOTE: Default rodio::Decoder hangs here when decoding large files, but symphonia does not
-                let file = fs::File::open(uri.path()).map_err(PlayerError::from_err_playback)?;
-                let stream = io::BufReader::new(file);
-                let source = Decoder::new(stream).map_err(PlayerError::from_err_playback)?;
-                self.sink.append(source);
-                Ok(())
+                if self.loop_status == LoopStatus::Playlist {
+                    self.playlist.push(music.clone());
+                }
             }
+            return Some(item);
+        }
+    }
+
+    fn shuffle_upcoming(&mut self) {
+        self.upcoming
+            .make_contiguous()
+            .shuffle(&mut rand::thread_rng());
+    }
+
+    fn remember(&mut self, item: Item) {
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(item);
+    }
+
+    fn restart_clock(&mut self) {
+        self.elapsed = Duration::ZERO;
+        self.resumed_at = if self.backend.is_paused() {
+            None
+        } else {
+            Some(Instant::now())
+        };
+    }
+
+    // returns the length of the song, when it's known
+    fn append_source(&mut self, filename: &str) -> Result<Option<Duration>, PlayerError> {
+        self.backend.append(filename, &|x| Box::new(x))
+    }
+
+    fn append_source_modified(&mut self, filename: &str, modify: &dyn Fn(SongDecoder) -> Box<dyn Source<Item=i16> + Send>) -> Result<Option<Duration>, PlayerError> {
+        self.backend.append(filename, modify)
+    }
+
+    // append a song which starts `position` into it
+    fn append_from_modified(
+        &mut self,
+        filename: &str,
+        music: &Item,
+        position: Duration,
+        modify: &dyn Fn(SongDecoder, Item) -> Box<dyn Source<Item=i16> + Send>,
+    ) -> Result<Option<Duration>, PlayerError> {
+        if position.is_zero() {
+            return self.append_source_modified(filename, &|x| modify(x, music.clone()));
+        }
+        let decoded = Cell::new(false);
+        let duration = self.append_source_modified(filename, &|mut x| {
+            decoded.set(true);
+            x.seek(position);
+            modify(x, music.clone())
+        })?;
+        if !decoded.get() {
+            // the backend plays the song itself, so it has to skip the start too
+            self.backend.seek(position)?;
         }
+        Ok(duration)
     }
 }
 
@@ -334,6 +862,30 @@ pub fn mpd_connection(addr: std::net::SocketAddr) -> error::Result<Client<std::n
     Client::connect(addr)
 }
 
+pub(crate) fn decode_file(path: &str) -> Result<SongDecoder, PlayerError> {
+    SongDecoder::open(path)
+}
+
+#[inline]
+fn no_song_at(position: usize) -> PlayerError {
+    PlayerError::from_err_playback(format!("No song at queue position {}", position))
+}
+
+// consecutive songs from an album should play without a break
+pub(crate) fn same_album(a: &Item, b: &Item) -> bool {
+    let field = |item: &Item, name: &str| item.field(name).and_then(|x| x.to_owned().to_str());
+    match (field(a, "album"), field(b, "album")) {
+        (Some(album_a), Some(album_b)) if !album_a.is_empty() && album_a == album_b => {
+            // albums with common names like "Greatest Hits" need the same album artist too
+            match (field(a, "albumartist"), field(b, "albumartist")) {
+                (Some(artist_a), Some(artist_b)) => artist_a == artist_b,
+                _ => true,
+            }
+        }
+        _ => false,
+    }
+}
+
 #[inline]
 fn music_title(item: &Item) -> Option<String> {
     item.field("title").and_then(|x| x.to_owned().to_str())
@@ -356,9 +908,263 @@ fn music_filename(item: &Item) -> Option<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::backend::{BackendAction, NullBackend, NullHandle};
+    use muss_interpreter::lang::TypePrimitive;
     use muss_interpreter::Interpreter;
     use std::io;
 
+    type Songs = std::vec::IntoIter<Result<Item, InterpreterError>>;
+
+    fn song(filename: &str) -> Item {
+        Item::new().set_field_chain2("filename", TypePrimitive::String(filename.to_owned()))
+    }
+
+    fn null_player(filenames: &[&str]) -> (Player<Songs>, NullHandle) {
+        let songs: Vec<Result<Item, InterpreterError>> =
+            filenames.iter().map(|x| Ok(song(x))).collect();
+        let (backend, handle) = NullBackend::new();
+        (Player::with_backend(songs.into_iter(), backend), handle)
+    }
+
+    fn unmodified(
+        source: SongDecoder,
+        _item: Item,
+    ) -> Box<dyn Source<Item = i16> + Send> {
+        Box::new(source)
+    }
+
+    fn songs(filenames: &[&str]) -> Vec<Item> {
+        filenames.iter().map(|x| song(x)).collect()
+    }
+
+    fn session(produced: usize, emitted: &[&str], queue: &[&str]) -> Session {
+        Session {
+            produced: produced,
+            emitted: emitted.iter().map(|x| x.to_string()).collect(),
+            queue: queue.iter().map(|x| song(x)).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn null_play_order() -> Result<(), PlayerError> {
+        let (mut player, handle) = null_player(&["a", "b", "c"]);
+        player.enqueue(2)?;
+        assert_eq!(handle.queue(), vec!["a", "b"]);
+        assert_eq!(handle.finish(), Some("a".to_owned()));
+        player.update_history();
+        assert_eq!(player.history(), &[song("a")]);
+        player.enqueue(2)?;
+        assert_eq!(handle.queue(), vec!["b", "c"]);
+        handle.finish();
+        handle.finish();
+        player.update_history();
+        assert!(player.queue_empty());
+        assert!(player.enqueue(1)?.is_empty());
+        assert_eq!(handle.appended(), vec!["a", "b", "c"]);
+        Ok(())
+    }
+
+    #[test]
+    fn null_skip_previous() -> Result<(), PlayerError> {
+        let (mut player, handle) = null_player(&["a", "b", "c"]);
+        player.enqueue(2)?;
+        assert_eq!(player.skip_modified(&unmodified)?, Some(song("b")));
+        assert_eq!(handle.queue(), vec!["b"]);
+        assert_eq!(player.previous_modified(&unmodified)?, Some(song("a")));
+        assert_eq!(handle.queue(), vec!["a", "b"]);
+        assert_eq!(player.queue(), vec![song("a"), song("b")]);
+        // skipping and going back both replace the backend's queue
+        assert_eq!(handle.appended(), vec!["a", "b", "b", "a", "b"]);
+        Ok(())
+    }
+
+    #[test]
+    fn null_controls() -> Result<(), PlayerError> {
+        let (mut player, handle) = null_player(&["a"]);
+        player.enqueue(1)?;
+        player.pause()?;
+        assert!(player.is_paused());
+        assert!(handle.is_paused());
+        player.resume()?;
+        player.set_volume(0.5)?;
+        player.stop()?;
+        assert!(player.queue_empty());
+        assert_eq!(
+            handle.actions(),
+            vec![
+                BackendAction::Append("a".to_owned()),
+                BackendAction::Pause,
+                BackendAction::Resume,
+                BackendAction::SetVolume(0.5),
+                BackendAction::Stop,
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn null_previous_restart() -> Result<(), PlayerError> {
+        let (mut player, handle) = null_player(&["a", "b", "c"]);
+        player.enqueue(2)?;
+        handle.finish();
+        player.update_history();
+        // well into a song, going back restarts it
+        player.seek_modified(Duration::from_secs(10), &unmodified)?;
+        assert_eq!(player.previous_modified(&unmodified)?, Some(song("b")));
+        assert!(player.position() < DEFAULT_RESTART_AFTER);
+        assert_eq!(player.history(), &[song("a")]);
+        // but right after it starts, going back plays the previous song
+        assert_eq!(player.previous_modified(&unmodified)?, Some(song("a")));
+        assert_eq!(handle.queue(), vec!["a", "b"]);
+        assert!(player.history().is_empty());
+        // with nothing to go back to, the song starts again
+        assert_eq!(player.previous_modified(&unmodified)?, Some(song("a")));
+        assert_eq!(handle.queue(), vec!["a", "b"]);
+        Ok(())
+    }
+
+    #[test]
+    fn null_previous_always() -> Result<(), PlayerError> {
+        let (mut player, handle) = null_player(&["a", "b"]);
+        player.set_restart_after(None);
+        player.enqueue(2)?;
+        handle.finish();
+        player.update_history();
+        player.seek_modified(Duration::from_secs(10), &unmodified)?;
+        assert_eq!(player.previous_modified(&unmodified)?, Some(song("a")));
+        assert_eq!(handle.queue(), vec!["a", "b"]);
+        Ok(())
+    }
+
+    #[test]
+    fn null_seek() -> Result<(), PlayerError> {
+        let (mut player, handle) = null_player(&["a", "b"]);
+        assert_eq!(
+            player.seek_modified(Duration::from_secs(30), &unmodified)?,
+            None
+        );
+        player.enqueue(2)?;
+        // the clock doesn't move while paused
+        player.pause()?;
+        assert_eq!(
+            player.seek_modified(Duration::from_secs(30), &unmodified)?,
+            Some(song("a"))
+        );
+        assert_eq!(player.position(), Duration::from_secs(30));
+        // the null backend can't seek, so the queue is replaced (still paused)
+        assert_eq!(handle.queue(), vec!["a", "b"]);
+        assert_eq!(handle.appended(), vec!["a", "b", "a", "b"]);
+        assert!(handle.is_paused());
+        player.seek_modified(Duration::from_secs(5), &unmodified)?;
+        assert_eq!(player.position(), Duration::from_secs(5));
+        Ok(())
+    }
+
+    #[test]
+    fn null_queue_edits() -> Result<(), PlayerError> {
+        let (mut player, handle) = null_player(&["a", "b", "c", "d"]);
+        player.enqueue(3)?;
+        player.insert_next_modified(song("x"), &unmodified)?;
+        assert_eq!(player.queue(), songs(&["a", "x", "b", "c"]));
+        player.move_modified(3, 1, &unmodified)?;
+        assert_eq!(player.queue(), songs(&["a", "c", "x", "b"]));
+        assert_eq!(player.remove_modified(2, &unmodified)?, song("x"));
+        assert_eq!(player.queue(), songs(&["a", "c", "b"]));
+        // removing the current song skips it
+        assert_eq!(player.remove_modified(0, &unmodified)?, song("a"));
+        assert_eq!(player.history(), &[song("a")]);
+        player.enqueue(1)?;
+        assert_eq!(player.queue(), songs(&["c", "b"]));
+        // the runner's songs are still played afterwards
+        player.clear_upcoming_modified(&unmodified)?;
+        player.enqueue(1)?;
+        assert_eq!(handle.queue(), vec!["c", "d"]);
+        assert_eq!(player.queue(), songs(&["c", "d"]));
+        Ok(())
+    }
+
+    #[test]
+    fn null_queue_out_of_range() -> Result<(), PlayerError> {
+        let (mut player, handle) = null_player(&["a", "b", "c"]);
+        player.enqueue(3)?;
+        assert!(player.remove_modified(3, &unmodified).is_err());
+        assert!(player.move_modified(1, 3, &unmodified).is_err());
+        assert!(player.move_modified(3, 1, &unmodified).is_err());
+        // the current song stays first
+        assert!(player.move_modified(0, 1, &unmodified).is_err());
+        assert!(player.move_modified(1, 0, &unmodified).is_err());
+        assert_eq!(player.queue(), songs(&["a", "b", "c"]));
+        player.enqueue(2)?;
+        assert_eq!(handle.queue(), vec!["a", "b", "c"]);
+        // nothing is playing, so there's nothing to remove
+        player.stop()?;
+        player.update_history();
+        assert!(player.remove_modified(0, &unmodified).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn null_loop_track() -> Result<(), PlayerError> {
+        let (mut player, handle) = null_player(&["a", "b"]);
+        player.set_loop_status_modified(LoopStatus::Track, &unmodified)?;
+        player.enqueue(3)?;
+        assert_eq!(handle.queue(), vec!["a", "a", "a"]);
+        // the repeats are dropped, instead of being played once more
+        player.set_loop_status_modified(LoopStatus::None, &unmodified)?;
+        player.enqueue(2)?;
+        assert_eq!(handle.queue(), vec!["a", "b"]);
+        Ok(())
+    }
+
+    #[test]
+    fn null_loop_playlist() -> Result<(), PlayerError> {
+        let (mut player, handle) = null_player(&["a", "b", "c"]);
+        player.enqueue(2)?;
+        handle.finish();
+        player.update_history();
+        // songs from before looping was turned on are looped too
+        player.set_loop_status_modified(LoopStatus::Playlist, &unmodified)?;
+        player.enqueue(4)?;
+        assert_eq!(handle.queue(), vec!["b", "c", "a", "b", "c"]);
+        // looping stops once the songs which were already queued are done
+        player.set_loop_status_modified(LoopStatus::None, &unmodified)?;
+        assert!(player.playlist.is_empty());
+        player.enqueue(10)?;
+        assert_eq!(handle.queue(), vec!["b", "c", "a", "b", "c"]);
+        Ok(())
+    }
+
+    #[test]
+    fn null_shuffle() -> Result<(), PlayerError> {
+        let (mut player, handle) = null_player(&["a", "b", "c", "d", "e", "f"]);
+        player.enqueue(4)?;
+        player.set_shuffle_modified(true, &unmodified)?;
+        let mut queue = player.queue();
+        assert_eq!(queue.remove(0), song("a"));
+        queue.sort_by_key(|x| x.field("filename").and_then(|x| x.to_owned().to_str()));
+        assert_eq!(queue, vec![song("b"), song("c"), song("d")]);
+        // songs the runner hasn't produced yet are still played in order, afterwards
+        player.enqueue(5)?;
+        assert_eq!(handle.queue()[4..], ["e", "f"]);
+        assert!(player.is_shuffle());
+        Ok(())
+    }
+
+    #[test]
+    fn null_restore_session() -> Result<(), PlayerError> {
+        let (mut player, handle) = null_player(&["a", "b", "c", "d"]);
+        assert!(player.restore_session(session(2, &["a", "b"], &["b"])));
+        player.enqueue(3)?;
+        assert_eq!(handle.queue(), vec!["b", "c", "d"]);
+        // the script changed, so the songs it produced last time are left out instead
+        let (mut player, handle) = null_player(&["b", "x", "a", "c"]);
+        assert!(!player.restore_session(session(2, &["a", "b"], &["b"])));
+        player.enqueue(4)?;
+        assert_eq!(handle.queue(), vec!["b", "x", "c"]);
+        Ok(())
+    }
+
     #[allow(dead_code)]
     //#[test]
     fn play_cursor() -> Result<(), PlayerError> {
@@ -6,6 +6,7 @@ use rodio::Source;
 //use muss_interpreter::tokens::TokenReader;
 use muss_interpreter::{InterpreterError, Item};
 
+use super::player::LoopStatus;
 use super::Player;
 use super::PlayerError;
 
@@ -19,6 +20,9 @@ pub struct PlayerServer<I: std::iter::Iterator<Item = Result<Item, InterpreterEr
     event: Sender<PlayerAction>,
     playback: Sender<PlaybackAction>,
     keep_alive: bool,
+    now_playing: Option<Item>,
+    // whole seconds into the current song at the last tick, when the player doesn't decode songs
+    ticked_secs: u64,
 }
 
 impl<I: std::iter::Iterator<Item = Result<Item, InterpreterError>>> PlayerServer<I> {
@@ -35,10 +39,12 @@ impl<I: std::iter::Iterator<Item = Result<Item, InterpreterError>>> PlayerServer
             event: event,
             playback: playback,
             keep_alive: keep_alive,
+            now_playing: None,
+            ticked_secs: 0,
         }
     }
 
-    fn modify(&self) -> impl Fn(rodio::Decoder<std::io::BufReader<std::fs::File>>, Item) -> Box<dyn Source<Item=i16> + Send> {
+    fn modify(&self) -> impl Fn(crate::SongDecoder, Item) -> Box<dyn Source<Item=i16> + Send> {
         let event = std::sync::Arc::new(std::sync::Mutex::new(self.playback.clone()));
         move |source_in, item| {
             let event2 = event.clone();
@@ -112,6 +118,73 @@ impl<I: std::iter::Iterator<Item = Result<Item, InterpreterError>>> PlayerServer
         }
     }
 
+    fn seek(&mut self, position: std::time::Duration) {
+        match self.player.seek_modified(position, &self.modify()) {
+            Err(e) => {
+                self.event.send(PlayerAction::Exception(e)).unwrap();
+            }
+            Ok(Some(item)) => {
+                self.ticked_secs = position.as_secs();
+                self.playback
+                    .send(PlaybackAction::Seeked(item, position))
+                    .unwrap();
+            }
+            Ok(None) => {}
+        }
+    }
+
+    fn update_now_playing(&mut self) {
+        self.player.update_history();
+        let current = self.player.current();
+        if current != self.now_playing.as_ref() {
+            self.now_playing = current.cloned();
+            if let Some(item) = current.cloned() {
+                self.on_playing(item);
+            }
+            // the session grows with every song, so it's only saved when the song changes (and on exit)
+            self.save_session();
+        }
+    }
+
+    fn on_playing(&mut self, item: Item) {
+        self.playback
+            .send(PlaybackAction::Playing(item.clone()))
+            .unwrap();
+        if !self.player.decodes() {
+            self.ticked_secs = 0;
+            if let Some(duration) = self.player.current_duration() {
+                self.playback
+                    .send(PlaybackAction::Time(item, duration))
+                    .unwrap();
+            }
+        }
+    }
+
+    // the modifier never sees songs which aren't decoded here, so ticks come from the playback time instead
+    fn tick(&mut self) {
+        let item = match &self.now_playing {
+            Some(item) => item.clone(),
+            None => return,
+        };
+        let position = self.player.position();
+        let secs = position.as_secs();
+        if secs == self.ticked_secs + 1 {
+            self.playback.send(PlaybackAction::UpdateTick(item)).unwrap();
+        } else if secs != self.ticked_secs {
+            // something else (e.g. another MPD client) moved playback
+            self.playback
+                .send(PlaybackAction::Seeked(item, position))
+                .unwrap();
+        }
+        self.ticked_secs = secs;
+    }
+
+    fn save_session(&mut self) {
+        if let Err(e) = self.player.save_session() {
+            self.event.send(PlayerAction::Exception(e)).unwrap();
+        }
+    }
+
     fn on_empty(&self) {
         self.event.send(PlayerAction::Empty).unwrap();
         self.playback.send(PlaybackAction::Empty).unwrap();
@@ -129,6 +202,7 @@ impl<I: std::iter::Iterator<Item = Result<Item, InterpreterError>>> PlayerServer
         let mut is_empty = self.player.queue_len() == 0;
         loop {
             let command = self.control.recv().unwrap();
+            self.player.update_history();
 
             let mut is_exiting = false;
 
@@ -138,26 +212,54 @@ impl<I: std::iter::Iterator<Item = Result<Item, InterpreterError>>> PlayerServer
             match command {
                 ControlAction::Next { .. } => {
                     //println!("Executing next command (queue_len: {})", self.player.queue_len());
-                    if let Err(e) = self.player.new_sink() {
+                    // the next song is already queued, so it's not dropped with the current one
+                    if let Err(e) = self.player.skip_modified(&self.modify()) {
                         self.event.send(PlayerAction::Exception(e)).unwrap();
                     }
-                    if !self.player.is_paused() {
-                        self.enqeue_some(1);
+                }
+                ControlAction::Previous { .. } => {
+                    match self.player.previous_modified(&self.modify()) {
+                        Err(e) => {
+                            self.event.send(PlayerAction::Exception(e)).unwrap();
+                        }
+                        Ok(Some(item)) => {
+                            // restarting the same song still needs to be announced
+                            self.now_playing = Some(item.clone());
+                            self.on_playing(item);
+                        }
+                        Ok(None) => {}
+                    }
+                }
+                ControlAction::Play { .. } => {
+                    if let Err(e) = self.player.resume() {
+                        self.event.send(PlayerAction::Exception(e)).unwrap();
+                    }
+                }
+                ControlAction::Pause { .. } => {
+                    if let Err(e) = self.player.pause() {
+                        self.event.send(PlayerAction::Exception(e)).unwrap();
                     }
                 }
-                ControlAction::Previous { .. } => {} // TODO
-                ControlAction::Play { .. } => self.player.resume(),
-                ControlAction::Pause { .. } => self.player.pause(),
                 ControlAction::PlayPause { .. } => {
-                    if self.player.is_paused() {
-                        self.player.resume();
+                    let result = if self.player.is_paused() {
+                        self.player.resume()
                     } else {
-                        self.player.pause();
+                        self.player.pause()
+                    };
+                    if let Err(e) = result {
+                        self.event.send(PlayerAction::Exception(e)).unwrap();
+                    }
+                }
+                ControlAction::Stop { .. } => {
+                    if let Err(e) = self.player.stop() {
+                        self.event.send(PlayerAction::Exception(e)).unwrap();
                     }
                 }
-                ControlAction::Stop { .. } => self.player.stop(),
                 ControlAction::Exit { .. } => {
-                    self.player.stop();
+                    self.save_session();
+                    if let Err(e) = self.player.stop() {
+                        self.event.send(PlayerAction::Exception(e)).unwrap();
+                    }
                     is_exiting = true;
                 }
                 ControlAction::Enqueue { amount, .. } => {
@@ -165,22 +267,107 @@ impl<I: std::iter::Iterator<Item = Result<Item, InterpreterError>>> PlayerServer
                 }
                 ControlAction::NoOp { .. } => {} // empty by design
                 ControlAction::SetVolume { volume, .. } => {
-                    self.player.set_volume((volume as f32) / (u32::MAX as f32));
+                    if let Err(e) = self.player.set_volume((volume as f32) / (u32::MAX as f32)) {
+                        self.event.send(PlayerAction::Exception(e)).unwrap();
+                    }
                 }
                 ControlAction::CheckEmpty { .. } => {
                     check_empty = true;
                 }
+                ControlAction::Seek { offset, .. } => {
+                    let position = self.player.position().as_micros() as i64 + offset;
+                    self.seek(std::time::Duration::from_micros(position.max(0) as u64));
+                }
+                ControlAction::SetPosition { position, .. } => {
+                    self.seek(position);
+                }
+                ControlAction::GetQueue { .. } => {
+                    self.player.update_history();
+                    self.event
+                        .send(PlayerAction::Queue(self.player.queue()))
+                        .unwrap();
+                }
+                ControlAction::GetStatus { .. } => {
+                    self.player.update_history();
+                    let status = PlayerStatus {
+                        playing: self.player.current().cloned(),
+                        position: self.player.position(),
+                        duration: self.player.current_duration(),
+                        paused: self.player.is_paused(),
+                        volume: self.player.volume(),
+                        loop_status: self.player.loop_status(),
+                        shuffle: self.player.is_shuffle(),
+                    };
+                    self.event.send(PlayerAction::Status(status)).unwrap();
+                }
+                ControlAction::Remove { position, .. } => {
+                    if let Err(e) = self.player.remove_modified(position, &self.modify()) {
+                        self.event.send(PlayerAction::Exception(e)).unwrap();
+                    }
+                }
+                ControlAction::Move { from, to, .. } => {
+                    if let Err(e) = self.player.move_modified(from, to, &self.modify()) {
+                        self.event.send(PlayerAction::Exception(e)).unwrap();
+                    }
+                }
+                ControlAction::InsertNext { ref item, .. } => {
+                    if let Err(e) = self
+                        .player
+                        .insert_next_modified(item.clone(), &self.modify())
+                    {
+                        self.event.send(PlayerAction::Exception(e)).unwrap();
+                    }
+                }
+                ControlAction::Append { ref item, .. } => {
+                    self.player.append(item.clone());
+                }
+                ControlAction::ClearUpcoming { .. } => {
+                    if let Err(e) = self.player.clear_upcoming_modified(&self.modify()) {
+                        self.event.send(PlayerAction::Exception(e)).unwrap();
+                    }
+                }
+                ControlAction::SetLoopStatus { loop_status, .. } => {
+                    match self
+                        .player
+                        .set_loop_status_modified(loop_status, &self.modify())
+                    {
+                        Err(e) => self.event.send(PlayerAction::Exception(e)).unwrap(),
+                        Ok(_) => self
+                            .playback
+                            .send(PlaybackAction::LoopStatus(loop_status))
+                            .unwrap(),
+                    }
+                }
+                ControlAction::SetShuffle { shuffle, .. } => {
+                    match self.player.set_shuffle_modified(shuffle, &self.modify()) {
+                        Err(e) => self.event.send(PlayerAction::Exception(e)).unwrap(),
+                        Ok(_) => self.playback.send(PlaybackAction::Shuffle(shuffle)).unwrap(),
+                    }
+                }
+            }
+
+            if let Err(e) = self.player.update_modified(&self.modify()) {
+                self.event.send(PlayerAction::Exception(e)).unwrap();
             }
 
             // keep queue full (while playing music)
-            if self.player.queue_len() == 0 && !self.player.is_paused() && !is_exiting {
-                self.enqeue_some(1);
+            // the song after the current one is opened ahead of time, so there's no gap between them
+            if self.player.queue_len() < 2 && !self.player.is_paused() && !is_exiting {
+                self.enqeue_some(2 - self.player.queue_len());
                 if self.player.queue_len() == 0 {
                     // no more music to add
                     is_exiting = !self.keep_alive || is_exiting;
+                    if let Err(e) = self.player.end_session() {
+                        self.event.send(PlayerAction::Exception(e)).unwrap();
+                    }
                 }
             }
 
+            self.update_now_playing();
+            if !self.player.decodes() {
+                self.tick();
+            }
+
             if command.needs_ack() {
                 self.event.send(PlayerAction::Acknowledge(command)).unwrap();
             }
@@ -249,6 +436,20 @@ pub enum ControlAction {
     NoOp { ack: bool },
     SetVolume { ack: bool, volume: u32 },
     CheckEmpty { ack: bool },
+    /// Move within the current song by an offset in microseconds (negative to go back)
+    Seek { ack: bool, offset: i64 },
+    SetPosition { ack: bool, position: std::time::Duration },
+    /// Ask for the queue, which is sent back as `PlayerAction::Queue`
+    GetQueue { ack: bool },
+    /// Ask what's playing and how, which is sent back as `PlayerAction::Status`
+    GetStatus { ack: bool },
+    Remove { ack: bool, position: usize },
+    Move { ack: bool, from: usize, to: usize },
+    InsertNext { ack: bool, item: Item },
+    Append { ack: bool, item: Item },
+    ClearUpcoming { ack: bool },
+    SetLoopStatus { ack: bool, loop_status: LoopStatus },
+    SetShuffle { ack: bool, shuffle: bool },
 }
 
 impl ControlAction {
@@ -265,6 +466,17 @@ impl ControlAction {
             Self::NoOp { ack, .. } => ack,
             Self::SetVolume { ack, .. } => ack,
             Self::CheckEmpty { ack } => ack,
+            Self::Seek { ack, .. } => ack,
+            Self::SetPosition { ack, .. } => ack,
+            Self::GetQueue { ack } => ack,
+            Self::GetStatus { ack } => ack,
+            Self::Remove { ack, .. } => ack,
+            Self::Move { ack, .. } => ack,
+            Self::InsertNext { ack, .. } => ack,
+            Self::Append { ack, .. } => ack,
+            Self::ClearUpcoming { ack } => ack,
+            Self::SetLoopStatus { ack, .. } => ack,
+            Self::SetShuffle { ack, .. } => ack,
         }
     }
 }
@@ -276,22 +488,35 @@ pub enum PlayerAction {
     Exception(PlayerError),
     End,
     Empty,
+    /// The current song and the songs after it, in order
+    Queue(Vec<Item>),
+    Status(PlayerStatus),
+}
+
+/// What the player is doing
+#[derive(Clone, Debug)]
+pub struct PlayerStatus {
+    /// The song which is playing (or paused)
+    pub playing: Option<Item>,
+    /// Playback time of the current song
+    pub position: std::time::Duration,
+    /// Length of the current song, when it's known
+    pub duration: Option<std::time::Duration>,
+    pub paused: bool,
+    pub volume: f32,
+    pub loop_status: LoopStatus,
+    pub shuffle: bool,
 }
 
 #[derive(Clone, Debug)]
 pub enum PlaybackAction {
     Empty,
     Enqueued(Item),
+    Playing(Item),
     Time(Item, std::time::Duration),
     UpdateTick(Item), // tick sent once every second
+    Seeked(Item, std::time::Duration),
+    LoopStatus(LoopStatus),
+    Shuffle(bool),
     Exit,
 }
-
-impl PlayerAction {
-    pub fn is_acknowledgement(&self) -> bool {
-        match self {
-            Self::Acknowledge(_) => true,
-            _ => false,
-        }
-    }
-}
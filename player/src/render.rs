@@ -0,0 +1,293 @@
+use std::io::{Seek, SeekFrom, Write};
+use std::time::Duration;
+
+use rodio::source::UniformSourceIterator;
+
+use muss_interpreter::{InterpreterError, Item};
+
+use super::player::{decode_file, same_album};
+use super::uri::Uri;
+use super::{PlayerError, UriError};
+
+/// Peak level of normalised songs, a bit under full scale (about -1 dBFS)
+const NORMALISED_PEAK: f32 = 0.89;
+
+/// How to render a script to an audio file.
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    /// Every song is resampled to this rate
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Scale every song to the same peak level
+    pub normalise: bool,
+    /// Fade between songs over this long, except for songs from the same album
+    pub crossfade: Option<Duration>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: 44100,
+            channels: 2,
+            normalise: false,
+            crossfade: None,
+        }
+    }
+}
+
+/// Decode every song the runner produces and write them one after the other as a single 16-bit WAV file.
+/// This doesn't need an audio device, and the same songs always render to the same file.
+/// This returns the number of songs rendered.
+pub fn render_wav<I, W>(runner: I, w: W, options: &RenderOptions) -> Result<usize, PlayerError>
+where
+    I: std::iter::Iterator<Item = Result<Item, InterpreterError>>,
+    W: Write + Seek,
+{
+    let mut wav = WavWriter::new(w, options.sample_rate, options.channels)?;
+    let crossfade_len = options
+        .crossfade
+        .map(|x| sample_count(x, options))
+        .unwrap_or(0);
+    // end of the previous song, held back to fade into the start of the next song
+    let mut tail: Vec<i16> = Vec::new();
+    let mut previous: Option<Item> = None;
+    let mut count = 0;
+    for item in runner {
+        let music = item.map_err(PlayerError::from_err_playback)?;
+        let filename = music
+            .field("filename")
+            .and_then(|x| x.to_owned().to_str())
+            .ok_or_else(|| {
+                PlayerError::from_err_playback("Field `filename` does not exist on item")
+            })?;
+        let mut samples = decode_samples(&filename, options)?;
+        if options.normalise {
+            normalise(&mut samples);
+        }
+        let overlap = match &previous {
+            Some(previous) if !same_album(previous, &music) => tail.len().min(samples.len()),
+            _ => 0,
+        };
+        let split = tail.len() - overlap;
+        wav.write_samples(&tail[..split])?;
+        crossfade(&tail[split..], &mut samples[..overlap], options.channels);
+        tail = samples.split_off(samples.len() - crossfade_len.min(samples.len()));
+        wav.write_samples(&samples)?;
+        previous = Some(music);
+        count += 1;
+    }
+    wav.write_samples(&tail)?;
+    wav.finish()?;
+    Ok(count)
+}
+
+// decode a song, converted to the rendered sample rate and channels
+fn decode_samples(filename: &str, options: &RenderOptions) -> Result<Vec<i16>, PlayerError> {
+    let uri = Uri::new(filename);
+    let path = match uri.scheme() {
+        Some(s) => match &s.to_lowercase() as &str {
+            "file:" => uri.without_scheme(),
+            scheme => return Err(UriError::Unsupported(scheme.to_owned()).into()),
+        },
+        None => uri.path(),
+    };
+    let source = decode_file(path)?;
+    Ok(UniformSourceIterator::new(source, options.channels, options.sample_rate).collect())
+}
+
+#[inline]
+fn sample_count(duration: Duration, options: &RenderOptions) -> usize {
+    let frames = (duration.as_secs_f64() * options.sample_rate as f64) as usize;
+    frames * options.channels as usize
+}
+
+fn normalise(samples: &mut [i16]) {
+    let peak = samples.iter().map(|x| (*x as i32).abs()).max().unwrap_or(0);
+    if peak == 0 {
+        return; // silence stays silent
+    }
+    let gain = NORMALISED_PEAK * i16::MAX as f32 / peak as f32;
+    for sample in samples.iter_mut() {
+        *sample = (*sample as f32 * gain) as i16;
+    }
+}
+
+// mix the end of one song into the start of the next one, fading linearly between them
+fn crossfade(tail: &[i16], head: &mut [i16], channels: u16) {
+    let channels = channels as usize;
+    let frames = head.len() / channels;
+    for (i, (out, fading)) in head.iter_mut().zip(tail.iter()).enumerate() {
+        let progress = (i / channels) as f32 / frames as f32;
+        let mixed = *fading as f32 * (1.0 - progress) + *out as f32 * progress;
+        *out = mixed.clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+    }
+}
+
+/// 16-bit PCM WAV output, which fills in the lengths in the header once it's finished.
+struct WavWriter<W: Write + Seek> {
+    w: W,
+    start: u64,
+    data_len: u64,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    fn new(mut w: W, sample_rate: u32, channels: u16) -> Result<Self, PlayerError> {
+        let start = w
+            .stream_position()
+            .map_err(PlayerError::from_err_playback)?;
+        let block_align = channels * 2;
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&0u32.to_le_bytes()); // file length, filled in later
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        header.extend_from_slice(&channels.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes()); // data length, filled in later
+        w.write_all(&header)
+            .map_err(PlayerError::from_err_playback)?;
+        Ok(Self {
+            w: w,
+            start: start,
+            data_len: 0,
+        })
+    }
+
+    fn write_samples(&mut self, samples: &[i16]) -> Result<(), PlayerError> {
+        let bytes: Vec<u8> = samples.iter().flat_map(|x| x.to_le_bytes()).collect();
+        self.w
+            .write_all(&bytes)
+            .map_err(PlayerError::from_err_playback)?;
+        self.data_len += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<W, PlayerError> {
+        if self.data_len > (u32::MAX - 36) as u64 {
+            return Err(PlayerError::from_err_playback(
+                "Rendered audio is too long for a WAV file",
+            ));
+        }
+        let data_len = self.data_len as u32;
+        let mut fill_in = |offset: u64, value: u32| -> std::io::Result<()> {
+            self.w.seek(SeekFrom::Start(self.start + offset))?;
+            self.w.write_all(&value.to_le_bytes())
+        };
+        fill_in(4, data_len + 36).map_err(PlayerError::from_err_playback)?;
+        fill_in(40, data_len).map_err(PlayerError::from_err_playback)?;
+        self.w
+            .seek(SeekFrom::End(0))
+            .map_err(PlayerError::from_err_playback)?;
+        self.w.flush().map_err(PlayerError::from_err_playback)?;
+        Ok(self.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use muss_interpreter::lang::TypePrimitive;
+    use std::io::Cursor;
+
+    // a mono WAV file with every sample set to the same value
+    fn write_flat_wav(name: &str, value: i16, len: usize) -> Result<Item, PlayerError> {
+        let path = std::env::temp_dir().join(format!("muss_test_render_{}.wav", name));
+        let file = std::fs::File::create(&path).map_err(PlayerError::from_err_playback)?;
+        let mut wav = WavWriter::new(file, 1000, 1)?;
+        wav.write_samples(&vec![value; len])?;
+        wav.finish()?;
+        Ok(Item::new()
+            .set_field_chain2(
+                "filename",
+                TypePrimitive::String(path.to_string_lossy().into()),
+            )
+            .set_field_chain2("album", TypePrimitive::String(name.to_owned())))
+    }
+
+    fn rendered_samples(
+        songs: Vec<Item>,
+        options: &RenderOptions,
+    ) -> Result<Vec<i16>, PlayerError> {
+        let runner = songs.into_iter().map(Ok);
+        let mut out = Cursor::new(Vec::new());
+        assert_eq!(render_wav(runner, &mut out, options)?, 2);
+        let bytes = out.into_inner();
+        assert_eq!(&bytes[40..44], &(bytes.len() as u32 - 44).to_le_bytes());
+        Ok(bytes[44..]
+            .chunks(2)
+            .map(|x| i16::from_le_bytes([x[0], x[1]]))
+            .collect())
+    }
+
+    #[test]
+    fn wav_header() -> Result<(), PlayerError> {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 44100, 2)?;
+        wav.write_samples(&[1, -1, 2, -2])?;
+        let bytes = wav.finish()?.into_inner();
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[4..8], &(36u32 + 8).to_le_bytes());
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(&bytes[24..28], &44100u32.to_le_bytes());
+        assert_eq!(&bytes[28..32], &(44100u32 * 4).to_le_bytes());
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(&bytes[40..44], &8u32.to_le_bytes());
+        assert_eq!(&bytes[44..46], &1i16.to_le_bytes());
+        assert_eq!(&bytes[46..48], &(-1i16).to_le_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn crossfade_mix() {
+        let tail = [1000i16; 8];
+        let mut head = [0i16; 8];
+        crossfade(&tail, &mut head, 2);
+        // each frame (pair of samples) is faded together, from the tail towards the head
+        assert_eq!(head, [1000, 1000, 750, 750, 500, 500, 250, 250]);
+    }
+
+    #[test]
+    fn normalise_peak() {
+        let mut samples = [100i16, -200, 50];
+        normalise(&mut samples);
+        assert_eq!(samples[1], -(NORMALISED_PEAK * i16::MAX as f32) as i16);
+        assert_eq!(samples[0], -samples[1] / 2);
+        let mut silence = [0i16; 4];
+        normalise(&mut silence);
+        assert_eq!(silence, [0; 4]);
+    }
+
+    #[test]
+    fn render_crossfade() -> Result<(), PlayerError> {
+        let first = write_flat_wav("first", 1000, 1000)?;
+        let second = write_flat_wav("second", 2000, 1000)?;
+        let options = RenderOptions {
+            sample_rate: 1000,
+            channels: 1,
+            normalise: false,
+            crossfade: Some(Duration::from_millis(100)),
+        };
+        let samples = rendered_samples(vec![first.clone(), second], &options)?;
+        // the songs overlap by 100 samples
+        assert_eq!(samples.len(), 1900);
+        assert!(samples[..900].iter().all(|x| *x == 1000));
+        assert_eq!(samples[900], 1000);
+        assert_eq!(samples[950], 1500);
+        assert_eq!(samples[999], 1990);
+        assert!(samples[1000..].iter().all(|x| *x == 2000));
+        // songs from the same album are not faded together
+        let samples = rendered_samples(vec![first.clone(), first], &options)?;
+        assert_eq!(samples.len(), 2000);
+        assert!(samples.iter().all(|x| *x == 1000));
+        for name in ["first", "second"] {
+            let path = std::env::temp_dir().join(format!("muss_test_render_{}.wav", name));
+            std::fs::remove_file(path).unwrap_or(());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use muss_interpreter::{item_from_json, item_to_json, Item};
+
+use super::PlayerError;
+
+const SESSION_FILENAME: &str = "session.json";
+
+/// Playback state of a script, so that playback can continue where it left off after restarting.
+#[derive(Clone, Debug, Default)]
+pub struct Session {
+    /// Text of the script being played
+    pub script: String,
+    /// Number of results (songs and errors) the script has produced so far
+    pub produced: usize,
+    /// Filenames of the songs the script has produced so far, in order
+    pub emitted: Vec<String>,
+    /// The song which was playing followed by the songs which were going to play next
+    pub queue: Vec<Item>,
+    /// Playback time of the song which was playing
+    pub position: Duration,
+}
+
+impl Session {
+    pub fn default_path() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("./"))
+            .join("muss")
+            .join(SESSION_FILENAME)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PlayerError> {
+        let path = path.as_ref();
+        let file =
+            fs::File::open(path).map_err(|e| PlayerError::from_file_err_playback(e, path))?;
+        let value: Value = serde_json::from_reader(io::BufReader::new(file))
+            .map_err(|e| PlayerError::from_file_err_playback(e, path))?;
+        let items = |key: &str| -> Result<Vec<Item>, PlayerError> {
+            value
+                .get(key)
+                .and_then(|x| x.as_array())
+                .ok_or_else(|| {
+                    PlayerError::from_file_err_playback(
+                        format!("Missing or invalid session field `{}`", key),
+                        path,
+                    )
+                })?
+                .iter()
+                .map(|x| {
+                    item_from_json(x).map_err(|e| PlayerError::from_file_err_playback(e, path))
+                })
+                .collect()
+        };
+        Ok(Self {
+            script: value
+                .get("script")
+                .and_then(|x| x.as_str())
+                .ok_or_else(|| {
+                    PlayerError::from_file_err_playback(
+                        "Missing or invalid session field `script`",
+                        path,
+                    )
+                })?
+                .to_owned(),
+            produced: value
+                .get("produced")
+                .and_then(|x| x.as_u64())
+                .ok_or_else(|| {
+                    PlayerError::from_file_err_playback(
+                        "Missing or invalid session field `produced`",
+                        path,
+                    )
+                })? as usize,
+            emitted: value
+                .get("emitted")
+                .and_then(|x| x.as_array())
+                .and_then(|x| {
+                    x.iter()
+                        .map(|x| x.as_str().map(|x| x.to_owned()))
+                        .collect::<Option<Vec<String>>>()
+                })
+                .ok_or_else(|| {
+                    PlayerError::from_file_err_playback(
+                        "Missing or invalid session field `emitted`",
+                        path,
+                    )
+                })?,
+            queue: items("queue")?,
+            position: Duration::from_secs_f64(
+                value
+                    .get("position")
+                    .and_then(|x| x.as_f64())
+                    .filter(|x| x.is_finite() && *x >= 0.0)
+                    .unwrap_or(0.0),
+            ),
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PlayerError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| PlayerError::from_file_err_playback(e, parent))?;
+        }
+        let value = json!({
+            "script": self.script,
+            "produced": self.produced,
+            "emitted": self.emitted,
+            "queue": self.queue.iter().cloned().map(item_to_json).collect::<Vec<Value>>(),
+            "position": self.position.as_secs_f64(),
+        });
+        // write somewhere else first, so getting killed while saving doesn't lose the old session
+        let tmp_path = path.with_extension("json.tmp");
+        let file = fs::File::create(&tmp_path)
+            .map_err(|e| PlayerError::from_file_err_playback(e, &tmp_path))?;
+        let mut writer = io::BufWriter::new(file);
+        serde_json::to_writer(&mut writer, &value)
+            .map_err(|e| PlayerError::from_file_err_playback(e, &tmp_path))?;
+        writer
+            .flush()
+            .map_err(|e| PlayerError::from_file_err_playback(e, &tmp_path))?;
+        fs::rename(&tmp_path, path).map_err(|e| PlayerError::from_file_err_playback(e, path))
+    }
+
+    /// Forget the saved session, since there's nothing left to resume.
+    pub fn remove<P: AsRef<Path>>(path: P) -> Result<(), PlayerError> {
+        let path = path.as_ref();
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                Err(PlayerError::from_file_err_playback(e, path))
+            }
+            _ => Ok(()),
+        }
+    }
+}
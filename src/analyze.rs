@@ -0,0 +1,89 @@
+//! Bulk music analysis (`muss analyze`)
+use std::time::Duration;
+
+use console::Term;
+
+use muss_interpreter::processing::advanced::{AnalysisEvent, AnalysisProgress, BulkAnalyzer};
+
+use super::cli::AnalyzeArgs;
+
+pub fn analyze(args: &AnalyzeArgs) {
+    let mut analyzer = BulkAnalyzer::new().with_excludes(args.exclude.clone());
+    if let Some(workers) = args.workers {
+        analyzer = analyzer.with_workers(workers);
+    }
+    let term = Term::stdout();
+    let result = analyzer.analyze(&args.paths, |event| match event {
+        AnalysisEvent::Found(progress) => {
+            println!(
+                "Found {} songs ({} already analysed)",
+                progress.total, progress.cached
+            );
+        }
+        AnalysisEvent::Analysed { path, progress } => {
+            show_progress(&term, &progress, &path.to_string_lossy());
+        }
+        AnalysisEvent::Failed {
+            path,
+            msg,
+            progress,
+        } => {
+            term.clear_line().unwrap_or(());
+            eprintln!("Failed to analyse `{}`: {}", path.display(), msg);
+            show_progress(&term, &progress, &path.to_string_lossy());
+        }
+    });
+    term.clear_line().unwrap_or(());
+    match result {
+        Ok(progress) => println!(
+            "Success: Analysed {} songs in {} ({} already analysed, {} failed)",
+            progress.analysed,
+            format_duration(progress.elapsed),
+            progress.cached,
+            progress.failed
+        ),
+        Err(e) => eprintln!("Abort: Analysis failed: {}", e),
+    }
+}
+
+fn show_progress(term: &Term, progress: &AnalysisProgress, path: &str) {
+    let percent = if progress.total == 0 {
+        100
+    } else {
+        progress.done() * 100 / progress.total
+    };
+    let eta = progress
+        .eta()
+        .map(format_duration)
+        .unwrap_or_else(|| "?".to_owned());
+    let status = format!(
+        "[{}/{}] {}% ETA {} ",
+        progress.done(),
+        progress.total,
+        percent,
+        eta
+    );
+    // keep the line from wrapping, since only the current line can be cleared
+    let width = term.size().1 as usize;
+    let path_width = width.saturating_sub(status.chars().count() + 1);
+    let path: String = if path.chars().count() > path_width {
+        let skip = path.chars().count() - path_width;
+        path.chars().skip(skip).collect()
+    } else {
+        path.to_owned()
+    };
+    term.clear_line().unwrap_or(());
+    term.write_str(&status).unwrap_or(());
+    term.write_str(&path).unwrap_or(());
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}h{:02}m{:02}s", secs / 3600, secs % 3600 / 60, secs % 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
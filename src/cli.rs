@@ -1,9 +1,13 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 
 #[derive(Parser)]
 #[clap(author, version)]
 #[clap(about = "Music playlist scripting language runtime")]
+#[clap(args_conflicts_with_subcommands = true)]
 pub struct CliArgs {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
     /// Script to run
     pub file: Option<String>,
 
@@ -11,6 +15,14 @@ pub struct CliArgs {
     #[clap(short, long)]
     pub playlist: Option<String>,
 
+    /// Render every song into one WAV file, instead of playing them
+    #[clap(long, conflicts_with = "playlist")]
+    pub render: Option<String>,
+
+    /// When rendering, make every song equally loud
+    #[clap(long, requires = "render")]
+    pub normalise: bool,
+
     /// In REPL mode, wait for all music in the queue to complete before accepting new input
     #[clap(long)]
     pub wait: bool,
@@ -26,6 +38,73 @@ pub struct CliArgs {
     /// MPD server for music playback
     #[clap(short, long)]
     pub mpd: Option<String>,
+
+    /// MPD's music folder, so that local songs in it can be played with MPD
+    #[clap(long, requires = "mpd")]
+    pub mpd_music_dir: Option<String>,
+
+    /// Fade between songs over this long (e.g. 5s), except for songs from the same album
+    #[clap(long)]
+    pub crossfade: Option<String>,
+
+    /// Continue playing the last script from where it stopped
+    #[clap(long, conflicts_with_all = &["file", "playlist", "render"])]
+    pub resume: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Analyse music ahead of time, so that bliss sorters don't need to while playing
+    Analyze(AnalyzeArgs),
+    /// Control a running muss which is playing a script
+    Ctl(CtlArgs),
+}
+
+#[derive(Args)]
+pub struct AnalyzeArgs {
+    /// Files and folders to analyse [default: music folders]
+    pub paths: Vec<String>,
+
+    /// Number of songs to analyse at the same time
+    #[clap(short, long)]
+    pub workers: Option<usize>,
+
+    /// Skip files and folders matching a gitignore-style pattern
+    #[clap(short, long)]
+    pub exclude: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct CtlArgs {
+    #[clap(subcommand)]
+    pub command: CtlCommand,
+
+    /// Control socket of the running muss [default: muss.sock in the runtime folder]
+    #[clap(short, long)]
+    pub socket: Option<String>,
+}
+
+#[derive(Subcommand)]
+pub enum CtlCommand {
+    /// Resume playback
+    Play,
+    /// Pause playback
+    Pause,
+    /// Skip to the next song
+    Next,
+    /// Go back to the previous song
+    Previous,
+    /// Set the volume, out of 1.0
+    Volume { volume: f32 },
+    /// Show what's playing
+    Status,
+    /// List the upcoming songs
+    Queue,
+    /// Run a script and play its songs after the queued songs
+    Enqueue {
+        /// Script to run
+        file: String,
+    },
 }
 
 pub fn parse() -> CliArgs {
@@ -38,5 +117,40 @@ pub fn validate(args: &CliArgs) -> Result<(), String> {
             .parse()
             .map_err(|e| format!("Unrecognized MPS address `{}`: {}", mpd_addr, e))?;
     }
+    if let Some(crossfade) = &args.crossfade {
+        parse_duration(crossfade)?;
+    }
+    if let Some(Command::Analyze(analyze)) = &args.command {
+        if analyze.workers == Some(0) {
+            return Err("At least one worker is required to analyse music".to_owned());
+        }
+    }
+    if let Some(Command::Ctl(CtlArgs {
+        command: CtlCommand::Volume { volume },
+        ..
+    })) = &args.command
+    {
+        if !(0.0..=1.0).contains(volume) {
+            return Err(format!("Volume {} is not between 0.0 and 1.0", volume));
+        }
+    }
     Ok(())
 }
+
+/// Parse a duration like `5s`, `500ms`, `1m` or `2.5` (seconds)
+pub fn parse_duration(s: &str) -> Result<std::time::Duration, String> {
+    let s = s.trim();
+    let (number, scale) = if let Some(ms) = s.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(secs) = s.strip_suffix('s') {
+        (secs, 1.0)
+    } else if let Some(mins) = s.strip_suffix('m') {
+        (mins, 60.0)
+    } else {
+        (s, 1.0)
+    };
+    match number.trim().parse::<f64>() {
+        Ok(x) if x >= 0.0 && x.is_finite() => Ok(std::time::Duration::from_secs_f64(x * scale)),
+        _ => Err(format!("Unrecognized duration `{}`", s)),
+    }
+}
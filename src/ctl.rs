@@ -0,0 +1,87 @@
+//! Remote control of a running player (`muss ctl`)
+use muss_interpreter::Item;
+
+use super::cli::CtlArgs;
+
+#[cfg(unix)]
+pub fn ctl(args: &CtlArgs) -> Result<(), String> {
+    use muss_player::{ControlServer, Request, Response};
+
+    use super::cli::CtlCommand;
+
+    let request = match &args.command {
+        CtlCommand::Play => Request::Play,
+        CtlCommand::Pause => Request::Pause,
+        CtlCommand::Next => Request::Next,
+        CtlCommand::Previous => Request::Previous,
+        CtlCommand::Volume { volume } => Request::SetVolume(*volume),
+        CtlCommand::Status => Request::Status,
+        CtlCommand::Queue => Request::Queue,
+        CtlCommand::Enqueue { file } => match std::fs::read_to_string(file) {
+            Ok(script) => Request::Enqueue(script),
+            Err(e) => return Err(format!("Abort: Cannot read file `{}`: {}", file, e)),
+        },
+    };
+    let socket = args
+        .socket
+        .clone()
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(ControlServer::default_path);
+    match muss_player::send_request(&socket, &request) {
+        Ok(Response::Done) => {}
+        Ok(Response::Status(status)) => {
+            match &status.playing {
+                Some(item) => println!(
+                    "{} {} [{}/{}]",
+                    if status.paused { "Paused" } else { "Playing" },
+                    song_name(item),
+                    format_secs(status.position.as_secs()),
+                    status
+                        .duration
+                        .map(|x| format_secs(x.as_secs()))
+                        .unwrap_or_else(|| "?".to_owned()),
+                ),
+                None => println!("Stopped"),
+            }
+            println!(
+                "volume: {:.0}% loop: {:?} shuffle: {}",
+                status.volume * 100.0,
+                status.loop_status,
+                if status.shuffle { "on" } else { "off" }
+            );
+        }
+        Ok(Response::Queue(items)) => {
+            for (i, item) in items.iter().enumerate() {
+                println!("{}. {}", i, song_name(item));
+            }
+        }
+        Err(e) => {
+            return Err(format!(
+                "Cannot control muss at `{}`: {}",
+                socket.display(),
+                e
+            ))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn ctl(_args: &CtlArgs) -> Result<(), String> {
+    Err("Abort: muss ctl is only supported on unix-like systems".to_owned())
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+fn song_name(item: &Item) -> String {
+    let field = |name| item.field(name).map(|x| x.as_str());
+    match (field("title"), field("artist")) {
+        (Some(title), Some(artist)) => format!("`{}` by {}", title, artist),
+        (Some(title), None) => format!("`{}`", title),
+        _ => format!("`{}`", field("filename").unwrap_or_default()),
+    }
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+fn format_secs(secs: u64) -> String {
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
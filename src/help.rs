@@ -10,8 +10,8 @@ pub const FUNCTIONS: &str =
 Similar to most other languages: function_name(param1, param2, etc.)
 These always return an iterable which can be manipulated.
 
- sql_init(generate = true|false, folder = `path/to/music`)
-    Initialize the SQLite database connection using the provided parameters. This must be performed before any other database operation (otherwise the database will already be connected with default settings). Returns an empty iterable.
+ sql_init(generate = true|false, folder = `path/to/music`, exclude = `pattern`, watch = true|false)
+    Initialize the SQLite database connection using the provided parameters. This must be performed before any other database operation (otherwise the database will already be connected with default settings). Only new, modified and deleted files are re-indexed when generating. Several folders can be separated like PATH, and exclude can be repeated to skip more gitignore-style patterns. When watch is true, the database is kept up to date with the music folders while Muss is running. Returns an empty iterable.
 
  sql(`SQL query here`)
     Perform a raw SQLite query on the database which Muss auto-generates. An iterator of the results is returned.
@@ -31,8 +31,8 @@ These always return an iterable which can be manipulated.
  repeat(iterable, count)
     Repeat the iterable count times, or infinite times if count is omitted.
 
- files(folder = `path/to/music`, recursive = true|false, regex = `pattern`)
-    Retrieve all files from a folder, matching a regex pattern.
+ files(folder = `path/to/music`, exclude = `pattern`, recursive = true|false, regex = `pattern`)
+    Retrieve all files from a folder, matching a regex pattern. folder and exclude (gitignore-style) can be repeated. Paths in .mussignore files are always skipped.
 
  mpd(address, term = value, term2 = value2, ...);
 
@@ -50,11 +50,26 @@ These always return an iterable which can be manipulated.
  intersection(iterable1, iterable2, ...);
     Combine multiple iterables such that only items that exist in iterable1 and iterable2 and ... are returned. The order of items from iterable1 is maintained. There is no limit to the amount of iterables which can be provided as parameters.
 
+ cluster(iterable, k = count)
+    Group songs by audio similarity into k (default 8) clusters, setting each song's cluster field (0 to k-1) and cluster_distance field. Use a filter like .(.cluster == 2) to get the songs in one cluster. Clusters are the same every time for the same songs.
+
+ path(iterable, from = file(`path`), to = file(`path`), length = count)
+    Smooth transition of length (default 20) songs from one song to another, choosing songs in between from the iterable. Each song is similar to the previous one and a bit closer to the last one.
+
  empty()
     Empty iterator. Useful for deleting items using replacement filters.
 
  empties(count);
-    Iterate over count empty items. The items in this iterator have no fields (i.e. are empty).";
+    Iterate over count empty items. The items in this iterator have no fields (i.e. are empty).
+
+ persist variable;
+    Save a variable so that it is available in future REPL sessions. Iterables are saved as their script text.
+
+ forget variable;
+    Stop saving a persisted variable.
+
+ preset name = options; -- e.g. preset dance = tempo=2 loudness=0.5 cosine;
+    Define similarity options once, to use them in ~radio, bliss_first and bliss_next with preset=name.";
 
 pub const FILTERS: &str =
 "FILTERS (?filters)
@@ -109,13 +124,19 @@ Operations to sort the items in an iterable: iterable~(sorter)
 
  ~radio
  ~radio qualifier -- e.g. iterable~(~radio)
-    Sort by musical similarity, starting with a random first song from the iterator. The optional qualifier may be chroma, loudness, spectrum, or tempo. When the qualifier is omitted, they are all considered for comparing audio similarity.
+ ~radio from file(`path`) -- e.g. iterable~(~radio from file(`~/Music/song.flac`) max_distance=1.5)
+ ~radio seed .field comparison -- e.g. iterable~(~radio seed .title == `Perm`)
+ ~radio options -- e.g. iterable~(~radio tempo=2 chroma=0.5 cosine)
+    Sort by musical similarity, starting with a random first song from the iterator. The optional qualifier may be chroma, loudness, spectrum, or tempo. When the qualifier is omitted, they are all considered for comparing audio similarity. Use from to start from a song file (which doesn't have to be in the iterator), or seed to start from the first song matching a field filter. With max_distance, songs which aren't similar enough to the previous song are left out. Without bliss analysis, songs are compared by tags (artist, genre, year, BPM, etc.) instead.
+    Options weight the feature groups (e.g. tempo=2), choose the distance metric (euclidean, cosine or mahalanobis) or use a preset (preset=name). bliss_first and bliss_next accept the same options.
 
  advanced bliss_first -- e.g. iterable~(advanced bliss_first)
-    Sort by the distance (similarity) from the first song in the iterator. Songs which are more similar (lower distance) to the first song in the iterator will be placed closer to the first song, while less similar songs will be sorted to the end. This uses the bliss music analyser, which is a very slow operation and can cause music playback interruptions for large iterators. Requires `advanced` interpreter feature.
+ advanced bliss_first options -- e.g. iterable~(advanced bliss_first preset=dance)
+    Sort by the distance (similarity) from the first song in the iterator. Songs which are more similar (lower distance) to the first song in the iterator will be placed closer to the first song, while less similar songs will be sorted to the end. This uses the bliss music analyser, which is a very slow operation and can cause music playback interruptions for large iterators (run `muss analyze` beforehand to avoid this). Requires `advanced` interpreter feature.
 
  advanced bliss_next -- e.g. iterable~(advanced bliss_next)
-    Sort by the distance (similarity) between the last played song in the iterator. Similar to bliss_first. Songs which are more similar (lower distance) to the first song in the iterator will be placed closer to the first song, while less similar songs will be sorted to the end. This uses the bliss music analyser, which is a very slow operation and can cause music playback interruptions for large iterators. Requires `advanced` interpreter feature.";
+ advanced bliss_next options -- e.g. iterable~(advanced bliss_next tempo=2 mahalanobis)
+    Sort by the distance (similarity) between the last played song in the iterator. Similar to bliss_first. Songs which are more similar (lower distance) to the first song in the iterator will be placed closer to the first song, while less similar songs will be sorted to the end. This uses the bliss music analyser, which is a very slow operation and can cause music playback interruptions for large iterators (run `muss analyze` beforehand to avoid this). Requires `advanced` interpreter feature.";
 
 pub const PROCEDURES: &str =
 "PROCEDURES (?procedures)
@@ -165,7 +186,10 @@ Comma-separated procedure steps will be executed sequentially (like a for loop i
     Format a value into a string. This behaves differently depending on the value's type: When the value is an Item, the item's corresponding field will replace all `{field}` instances in the format string. When the value is a primitive type (String, Int, Bool, etc.), the value's text equivalent will replace all `{}` instances in the format string. When the value is an iterable operation (Op), the operation's script equivalent will replace all `{}` instances in the format string.
 
  file(filepath) -- e.g. file(`~/Music/Romantic Traffic.flac`)
-    Load an item from file, populating the item with the song's tags.";
+    Load an item from file, populating the item with the song's tags.
+
+ features(item) -- e.g. item = features(item)
+    Copy an item with bliss audio features added as fields (tempo, zcr, spectral_centroid, spectral_rolloff, spectral_flatness, loudness, *_deviation and chroma1 to chroma10). Requires `advanced` interpreter feature.";
 
 pub const REPL_COMMANDS: &str =
 "COMMANDS (?commands)
@@ -6,6 +6,16 @@
 //! # Usage
 //! To access the REPL, simply run `cargo run`. You will need the [Rust toolchain installed](https://rustup.rs/). For a bit of extra performance, run `cargo run --release` instead.
 //!
+//! Playing a script is saved as it goes, so it can be continued later with `cargo run -- --resume` after stopping or restarting.
+//!
+//! Songs can be played by an MPD server instead with `--mpd 127.0.0.1:6600`. Local songs are only playable when MPD shares their folder, which is given with `--mpd-music-dir`.
+//!
+//! Instead of playing a script, its songs can be rendered into one WAV file with `cargo run -- script.muss --render mix.wav`, optionally with `--crossfade 5s` and `--normalise`.
+//!
+//! While a script is playing (or the REPL is running), it can be controlled from another terminal with `cargo run -- ctl <command>` (e.g. `pause`, `next`, `status` or `enqueue script.muss`).
+//!
+//! Music analysis for the bliss sorters is slow, so it's best done ahead of time with `cargo run --release -- analyze [paths]` (all music folders by default). Use `--workers` to change how many songs are analysed at once.
+//!
 //! # Examples
 //!
 //! ## One-liners
@@ -46,8 +56,10 @@
 //! **Yes**. It evolved from a simple query language into something that can do arbitrary calculations. Whether it's Turing-complete is still unproven, but it's powerful enough for what I want it to do.
 //!
 
+mod analyze;
 mod channel_io;
 mod cli;
+mod ctl;
 mod help;
 mod repl;
 
@@ -55,7 +67,9 @@ use std::io;
 use std::path::PathBuf;
 
 use muss_interpreter::Interpreter;
-use muss_player::{Controller, Player, PlayerError};
+#[cfg(unix)]
+use muss_player::ControlServer;
+use muss_player::{Controller, MpdBackend, Player, PlayerError, RenderOptions, Session};
 
 #[allow(dead_code)]
 fn play_cursor() -> Result<(), PlayerError> {
@@ -72,15 +86,75 @@ fn main() {
         return;
     }
 
-    if let Some(script_file) = &args.file {
+    if let Some(cli::Command::Analyze(analyze_args)) = &args.command {
+        analyze::analyze(analyze_args);
+    } else if let Some(cli::Command::Ctl(ctl_args)) = &args.command {
+        if let Err(e) = ctl::ctl(ctl_args) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    } else if args.file.is_some() || args.resume {
         // interpret script
-        // script file checks
-        if file_checks(script_file).is_err() {
+        let (source, script, session) = if args.resume {
+            match Session::load(Session::default_path()) {
+                Ok(session) => (
+                    "resumed session".to_owned(),
+                    session.script.clone(),
+                    Some(session),
+                ),
+                Err(e) => {
+                    eprintln!("Abort: Cannot resume session: {}", e);
+                    return;
+                }
+            }
+        } else {
+            let script_file = args.file.clone().unwrap();
+            // script file checks
+            if file_checks(&script_file).is_err() {
+                return;
+            }
+            match std::fs::read_to_string(&script_file) {
+                Ok(script) => (format!("script `{}`", script_file), script, None),
+                Err(e) => {
+                    eprintln!("Abort: Cannot read file `{}`: {}", script_file, e);
+                    return;
+                }
+            }
+        };
+        let crossfade = args
+            .crossfade
+            .as_deref()
+            .map(|x| cli::parse_duration(x).unwrap());
+        if let Some(render_file) = &args.render {
+            // render to audio file
+            let runner = Interpreter::with_stream(io::Cursor::new(script));
+            let options = RenderOptions {
+                normalise: args.normalise,
+                crossfade: crossfade,
+                ..Default::default()
+            };
+            let writer =
+                io::BufWriter::new(std::fs::File::create(render_file).unwrap_or_else(|_| {
+                    panic!("Abort: Cannot create writeable file `{}`", render_file)
+                }));
+            match muss_player::render_wav(runner, writer, &options) {
+                Ok(count) => println!(
+                    "Success: Finished rendering {} songs to `{}` from {}",
+                    count, render_file, source
+                ),
+                Err(e) => eprintln!("{}", e),
+            }
             return;
         }
+        // only live playback can be resumed
+        let session_path = if args.playlist.is_none() {
+            Some(Session::default_path())
+        } else {
+            None
+        };
         // build playback controller
-        let script_file2 = script_file.clone();
         let volume = args.volume;
+        let mpd_music_dir = args.mpd_music_dir.clone();
         let mpd = match args
             .mpd
             .clone()
@@ -90,20 +164,38 @@ fn main() {
             Ok(mpd) => mpd,
             Err(e) => panic!("Abort: Cannot connect to MPD: {}", e),
         };
+        let mpd = match mpd.map(MpdBackend::new).transpose() {
+            Ok(mpd) => mpd,
+            Err(e) => panic!("Abort: Cannot play with MPD: {}", e),
+        };
         let player_builder = move || {
-            let script_reader = io::BufReader::new(
-                std::fs::File::open(&script_file2)
-                    .unwrap_or_else(|_| panic!("Abort: Cannot open file `{}`", &script_file2)),
-            );
-            let runner = Interpreter::with_stream(script_reader);
+            let runner = Interpreter::with_stream(io::Cursor::new(script.clone()));
 
-            let mut player = Player::new(runner).unwrap();
-            if let Some(vol) = volume {
-                player.set_volume(vol);
+            let mut player = match mpd {
+                Some(mut backend) => {
+                    if let Some(music_dir) = mpd_music_dir {
+                        backend.set_music_dir(music_dir);
+                    }
+                    Player::with_backend(runner, backend)
+                }
+                None => Player::new(runner).unwrap(),
+            };
+            if let Some(session) = session {
+                // songs which were played before resuming are produced again and skipped
+                if !player.restore_session(session) {
+                    eprintln!("Warning: Script produced different songs than before resuming, so already played songs are skipped by filename");
+                }
+            }
+            if let Some(session_path) = session_path {
+                player.set_session(session_path, script);
             }
-            if let Some(mpd) = mpd {
-                player.set_mpd(mpd);
+            if let Some(vol) = volume {
+                // e.g. MPD servers without a mixer can't change the volume, which isn't worth stopping for
+                if let Err(e) = player.set_volume(vol) {
+                    eprintln!("Warning: Cannot set volume: {}", e);
+                }
             }
+            player.set_crossfade(crossfade);
             player
         };
         if let Some(playlist_file) = &args.playlist {
@@ -115,16 +207,16 @@ fn main() {
                 }));
             match player.save_m3u8(&mut writer) {
                 Ok(_) => println!(
-                    "Success: Finished playlist `{}` from script `{}`",
-                    playlist_file, script_file
+                    "Success: Finished playlist `{}` from {}",
+                    playlist_file, source
                 ),
                 Err(e) => eprintln!("{}", e),
             }
         } else {
             // live playback
             let ctrl = Controller::create(player_builder);
-            match ctrl.wait_for_done() {
-                Ok(_) => println!("Success: Finished playback from script `{}`", script_file),
+            match wait_for_playback(&ctrl) {
+                Ok(_) => println!("Success: Finished playback from {}", source),
                 Err(e) => eprintln!("{}", e),
             }
         }
@@ -137,6 +229,23 @@ fn main() {
     }
 }
 
+#[cfg(unix)]
+fn wait_for_playback(ctrl: &Controller) -> Result<(), PlayerError> {
+    // `muss ctl` can control playback while it's going
+    match ControlServer::bind(ControlServer::default_path()) {
+        Ok(server) => server.serve_until_done(ctrl),
+        Err(e) => {
+            eprintln!("Cannot listen for `muss ctl`: {}", e);
+            ctrl.wait_for_done()
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn wait_for_playback(ctrl: &Controller) -> Result<(), PlayerError> {
+    ctrl.wait_for_done()
+}
+
 fn file_checks(path_str: &str) -> Result<(), ()> {
     let path = PathBuf::from(path_str);
     if !path.exists() {
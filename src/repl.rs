@@ -2,15 +2,18 @@
 #![allow(clippy::single_match)]
 use std::io::{self, Write};
 use std::sync::mpsc::{self, Receiver};
-use std::sync::RwLock;
+use std::sync::{Arc, Mutex, RwLock};
 
 use lazy_static::lazy_static;
 
 use console::{Key, Term};
 
 use muss_interpreter::lang::TypePrimitive;
-use muss_interpreter::{Debugger, Interpreter, InterpreterError, InterpreterEvent, Item};
-use muss_player::{Controller, Player};
+use muss_interpreter::processing::general::FileOpStorage;
+use muss_interpreter::{Context, Debugger, Interpreter, InterpreterError, InterpreterEvent, Item};
+#[cfg(unix)]
+use muss_player::{ControlServer, SharedControlServer};
+use muss_player::{Controller, MpdBackend, Player};
 
 use super::channel_io::{channel_io, ChannelWriter};
 use super::cli::CliArgs;
@@ -199,6 +202,11 @@ pub fn repl(args: CliArgs) {
     term.set_title("muss");
     let (writer, reader) = channel_io();
     let volume = args.volume;
+    let mpd_music_dir = args.mpd_music_dir.clone();
+    let crossfade = args
+        .crossfade
+        .as_deref()
+        .map(|x| super::cli::parse_duration(x).unwrap());
     let mpd = match args
         .mpd
         .clone()
@@ -215,10 +223,27 @@ pub fn repl(args: CliArgs) {
             return;
         }
     };
+    let mpd = match mpd.map(MpdBackend::new).transpose() {
+        Ok(mpd) => mpd,
+        Err(e) => {
+            eprintln!("Cannot play with MPD: {}", e);
+            return;
+        }
+    };
     let (list_tx, list_rx) = mpsc::channel();
     let mut state = ReplState::new(writer, term, list_rx);
     let player_builder = move || {
-        let runner = Interpreter::with_stream_and_callback(reader, &interpreter_event_callback);
+        let mut runner = Interpreter::with_stream_and_callback(reader, &interpreter_event_callback);
+        // variables saved with `persist` are restored from previous sessions
+        let variables_path = FileOpStorage::default_path();
+        match FileOpStorage::open(&variables_path) {
+            Ok(variables) => runner.set_context(Context::default().with_variables(variables)),
+            Err(e) => eprintln!(
+                "Warning: Cannot load persisted variables from `{}`: {}",
+                variables_path.display(),
+                e
+            ),
+        }
         let debugger = Debugger::new(runner, move |interpretor, item| {
             let flag = if let Ok(d_state) = DEBUG_STATE.read() {
                 d_state.debug_flag
@@ -248,13 +273,21 @@ pub fn repl(args: CliArgs) {
             }
         });
 
-        let mut player = Player::new(debugger).unwrap();
+        let mut player = match mpd {
+            Some(mut backend) => {
+                if let Some(music_dir) = mpd_music_dir {
+                    backend.set_music_dir(music_dir);
+                }
+                Player::with_backend(debugger, backend)
+            }
+            None => Player::new(debugger).unwrap(),
+        };
         if let Some(vol) = volume {
-            player.set_volume(vol);
-        }
-        if let Some(mpd) = mpd {
-            player.set_mpd(mpd);
+            if let Err(e) = player.set_volume(vol) {
+                eprintln!("Warning: Cannot set volume: {}", e);
+            }
         }
+        player.set_crossfade(crossfade);
         player
     };
     if let Some(playlist_file) = &args.playlist {
@@ -302,8 +335,11 @@ pub fn repl(args: CliArgs) {
             writeln!(state.terminal, "Playback mode (output: audio device)")
                 .expect(TERMINAL_WRITE_ERROR);
         }
-        let ctrl = Controller::create_repl(player_builder);
+        let ctrl = Arc::new(Mutex::new(Controller::create_repl(player_builder)));
+        // `muss ctl` is served until the REPL ends
+        let _ctl_server = serve_ctl(ctrl.clone());
         read_loop(&args, &mut state, |state, args| {
+            let ctrl = ctrl.lock().unwrap_or_else(|e| e.into_inner());
             if args.wait {
                 match ctrl.wait_for_empty() {
                     Ok(_) => {}
@@ -326,18 +362,37 @@ pub fn repl(args: CliArgs) {
     }
 }
 
+// `muss ctl` can control playback while the REPL waits for input
+#[cfg(unix)]
+fn serve_ctl(ctrl: Arc<Mutex<Controller>>) -> Option<SharedControlServer> {
+    match ControlServer::bind(ControlServer::default_path()) {
+        Ok(server) => Some(server.serve_shared(ctrl)),
+        Err(e) => {
+            eprintln!("Cannot listen for `muss ctl`: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn serve_ctl(_ctrl: Arc<Mutex<Controller>>) -> Option<()> {
+    None
+}
+
 fn read_loop<F: FnMut(&mut ReplState, &CliArgs)>(
     args: &CliArgs,
     state: &mut ReplState,
     mut execute: F,
-) -> ! {
+) {
     prompt(state, args);
     loop {
-        match state
-            .terminal
-            .read_key()
-            .expect("Failed to read terminal input")
-        {
+        let key = match state.terminal.read_key() {
+            Ok(key) => key,
+            // Ctrl+C ends the REPL
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => return,
+            Err(e) => panic!("Failed to read terminal input: {}", e),
+        };
+        match key {
             Key::Char(read_c) => {
                 if state.cursor_rightward_position == 0 {
                     write!(state.terminal, "{}", read_c).expect(TERMINAL_WRITE_ERROR);